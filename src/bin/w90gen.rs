@@ -0,0 +1,127 @@
+//! Generate a Wannier90 input file from a TOML description of a `w90::input::Input`.
+//!
+//! ```text
+//! w90gen [--stdout | --check] <config.toml> [<seedname.win>]
+//! ```
+//!
+//! Without an output path, the input file is written next to the configuration file
+//! with the extension `.win`. With `--stdout`, it is printed instead; with `--check`,
+//! the configuration is only validated. Warnings are printed but do not cause failure.
+//!
+//! The exit code is 0 on success, 64 for invalid arguments, 65 if the configuration
+//! cannot be parsed, 74 for I/O errors, and otherwise the code given by
+//! `w90::input::Error::exit_code` for the first validation error.
+extern crate w90;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use w90::input;
+use w90::input::ConfigError;
+use w90::serialize;
+
+const USAGE: &str = "usage: w90gen [--stdout | --check] <config.toml> [<seedname.win>]";
+
+enum Mode {
+    Write(PathBuf),
+    Stdout,
+    Check,
+}
+
+fn main() {
+    let (config_path, mode) = match parse_args(env::args().skip(1).collect()) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(64);
+        }
+    };
+
+    let input = match input::from_toml_file(&config_path) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("error: {}: {}", config_path.display(), e);
+            process::exit(match e {
+                ConfigError::Io(_) => 74,
+                _ => 65,
+            });
+        }
+    };
+
+    match input::validate(&input) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("warning: {}", warning);
+            }
+        }
+        Err(errs) => {
+            print_errors(&config_path, &errs);
+            process::exit(errs.errs.first().map_or(1, |e| e.exit_code()));
+        }
+    }
+
+    let result = match mode {
+        Mode::Check => Ok(()),
+        Mode::Stdout => serialize::make_input_file(&input).map(|text| println!("{}", text)),
+        Mode::Write(ref path) => serialize::write_input_file(&input, path),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+/// Configuration path and output mode given by the command-line arguments, or `None` if
+/// they are invalid.
+fn parse_args(args: Vec<String>) -> Option<(PathBuf, Mode)> {
+    let (flags, paths): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|a| a.starts_with("--"));
+
+    let config_path = PathBuf::from(paths.first()?);
+    let output_path = paths.get(1).map(PathBuf::from);
+    if paths.len() > 2 {
+        return None;
+    }
+
+    let mode = match (flags.len(), flags.first().map(|f| f.as_str())) {
+        (0, _) => Mode::Write(output_path.unwrap_or_else(|| config_path.with_extension("win"))),
+        (1, Some("--stdout")) if output_path.is_none() => Mode::Stdout,
+        (1, Some("--check")) if output_path.is_none() => Mode::Check,
+        _ => return None,
+    };
+
+    Some((config_path, mode))
+}
+
+/// Print each validation error, followed by the line of the configuration file which
+/// sets the field given by `Error::keyword`, if there is one: either a `keyword = ...`
+/// line or the header of a `[keyword]` table.
+fn print_errors(config_path: &Path, errs: &input::ErrorList) {
+    let mut config = String::new();
+    if let Ok(mut file) = File::open(config_path) {
+        let _ = file.read_to_string(&mut config);
+    }
+
+    for err in &errs.errs {
+        eprintln!("error: {}", err);
+
+        let keyword = match err.keyword() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let line = config.lines().enumerate().find(|&(_, line)| {
+            let line = line.trim();
+            if line.starts_with('[') {
+                let table = line.trim_matches(|c| c == '[' || c == ']').trim();
+                table == keyword || table.ends_with(&format!(".{}", keyword))
+            } else {
+                line.starts_with(keyword) && line[keyword.len()..].trim_start().starts_with('=')
+            }
+        });
+        if let Some((number, line)) = line {
+            eprintln!("  --> {}:{}\n   | {}", config_path.display(), number + 1, line);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::Path;
+use std::io;
+use std::io::Read;
+use std::fs::File;
+
+/// Band energies on the Wannierization k-point grid, as given in `seedname.eig`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Eigenvalues {
+    pub num_bands: usize,
+    pub num_kpts: usize,
+    /// `energies[k][n]` is the energy in eV of band `n` at k-point `k`, with zero-based
+    /// indices.
+    pub energies: Vec<Vec<f64>>,
+}
+
+pub fn read_eig<P: AsRef<Path>>(file_path: P) -> Result<Eigenvalues, EigError> {
+    let mut contents = String::new();
+    File::open(file_path)?.read_to_string(&mut contents)?;
+
+    parse_eig(&contents)
+}
+
+/// Parse the contents of `seedname.eig`, which has one `n k E` line for each band `n` at
+/// each k-point `k`, with one-based indices and the band index varying fastest.
+pub fn parse_eig(text: &str) -> Result<Eigenvalues, EigError> {
+    let mut energies: Vec<Vec<f64>> = Vec::new();
+
+    for line in text.lines().filter(|l| l.trim().len() > 0) {
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        if toks.len() != 3 {
+            return Err(EigError::UnexpectedLine(String::from(line)));
+        }
+
+        let n: usize = parse_token(toks[0])?;
+        let k: usize = parse_token(toks[1])?;
+        let energy: f64 = parse_token(toks[2])?;
+
+        if k == energies.len() + 1 {
+            energies.push(Vec::new());
+        }
+        if k != energies.len() || n != energies[k - 1].len() + 1 {
+            return Err(EigError::UnexpectedIndex(String::from(line)));
+        }
+
+        energies[k - 1].push(energy);
+    }
+
+    let num_kpts = energies.len();
+    let num_bands = energies.first().map_or(0, |e| e.len());
+
+    if energies.iter().any(|e| e.len() != num_bands) {
+        return Err(EigError::BandCount);
+    }
+
+    Ok(Eigenvalues {
+        num_bands,
+        num_kpts,
+        energies,
+    })
+}
+
+fn parse_token<T: ::std::str::FromStr>(tok: &str) -> Result<T, EigError> {
+    tok.parse()
+        .map_err(|_| EigError::InvalidNumber(String::from(tok)))
+}
+
+/// Gap between the highest occupied and lowest unoccupied bands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BandGap {
+    /// The highest occupied band overlaps the lowest unoccupied band.
+    Metallic,
+    Gapped {
+        /// Smallest energy difference between the two bands at the same k-point.
+        direct: f64,
+        /// Difference between the minimum of the lowest unoccupied band and the maximum
+        /// of the highest occupied band.
+        indirect: f64,
+        /// Zero-based index of the k-point at the maximum of the highest occupied band.
+        vbm_k: usize,
+        /// Zero-based index of the k-point at the minimum of the lowest unoccupied band.
+        cbm_k: usize,
+    },
+}
+
+/// Band gap between band `num_occupied` and band `num_occupied + 1` (one-based indices)
+/// over all k-points in `eig`. Gives `EigError::NumOccupied` if `num_occupied` is zero or
+/// there are no bands above band `num_occupied`.
+pub fn band_gap(eig: &Eigenvalues, num_occupied: u64) -> Result<BandGap, EigError> {
+    if num_occupied == 0 || num_occupied >= eig.num_bands as u64 {
+        return Err(EigError::NumOccupied {
+            num_occupied,
+            num_bands: eig.num_bands,
+        });
+    }
+    let occ = num_occupied as usize;
+
+    let mut vbm = (0, ::std::f64::NEG_INFINITY);
+    let mut cbm = (0, ::std::f64::INFINITY);
+    let mut direct = ::std::f64::INFINITY;
+
+    for (k, e) in eig.energies.iter().enumerate() {
+        let (top, bottom) = (e[occ - 1], e[occ]);
+
+        if top > vbm.1 {
+            vbm = (k, top);
+        }
+        if bottom < cbm.1 {
+            cbm = (k, bottom);
+        }
+        direct = direct.min(bottom - top);
+    }
+
+    if cbm.1 <= vbm.1 {
+        Ok(BandGap::Metallic)
+    } else {
+        Ok(BandGap::Gapped {
+            direct,
+            indirect: cbm.1 - vbm.1,
+            vbm_k: vbm.0,
+            cbm_k: cbm.0,
+        })
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum EigError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected line `{}`.", _0)] UnexpectedLine(String),
+    #[fail(display = "Unexpected band or k-point index in line `{}`.", _0)]
+    UnexpectedIndex(String),
+    #[fail(display = "Number of bands differs between k-points.")] BandCount,
+    #[fail(display = "{} occupied bands leaves no band gap among {} bands.", num_occupied,
+           num_bands)]
+    NumOccupied { num_occupied: u64, num_bands: usize },
+}
+
+impl From<io::Error> for EigError {
+    fn from(e: io::Error) -> EigError {
+        EigError::Io(e)
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use num_complex::Complex64;
+use tokens::{next_complex, next_parsed, next_r_blocks, RBlockError, TokenError};
+
+/// Hamiltonian matrix elements `H_mn(R) = <0m|H|Rn>` between Wannier functions, as
+/// written to `seedname_hr.dat` when `write_hr = .true.`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HamiltonianR {
+    pub num_wann: usize,
+    /// Lattice vectors `R` in units of the lattice vectors of the unit cell.
+    pub r_vectors: Vec<[i64; 3]>,
+    /// Degeneracy of each Wigner-Seitz point in `r_vectors`.
+    pub degeneracies: Vec<u64>,
+    /// `hoppings[r][m][n]` is `H_mn(R)` for `R = r_vectors[r]`, with zero-based `m`, `n`.
+    pub hoppings: Vec<Vec<Vec<Complex64>>>,
+}
+
+impl HamiltonianR {
+    pub fn nrpts(&self) -> usize {
+        self.r_vectors.len()
+    }
+}
+
+pub fn read_hr<P: AsRef<Path>>(file_path: P) -> Result<HamiltonianR, Error> {
+    let mut contents = String::new();
+    File::open(file_path)?.read_to_string(&mut contents)?;
+
+    parse_hr(&contents)
+}
+
+pub fn parse_hr(text: &str) -> Result<HamiltonianR, Error> {
+    // The first line is a comment giving the date the file was written.
+    let mut tokens = text.lines().skip(1).flat_map(|l| l.split_whitespace());
+
+    let num_wann: usize = next_parsed(&mut tokens)?;
+    let nrpts: usize = next_parsed(&mut tokens)?;
+
+    let mut degeneracies = Vec::with_capacity(nrpts);
+    for _ in 0..nrpts {
+        degeneracies.push(next_parsed(&mut tokens)?);
+    }
+
+    let (r_vectors, hoppings) = next_r_blocks(&mut tokens, nrpts, num_wann, next_complex)?;
+
+    if let Some(tok) = tokens.next() {
+        return Err(Error::UnexpectedToken(String::from(tok)));
+    }
+
+    Ok(HamiltonianR {
+        num_wann,
+        r_vectors,
+        degeneracies,
+        hoppings,
+    })
+}
+
+/// Expected number of Wigner-Seitz R-vectors for a Wannierization on the k-point grid
+/// `mp_grid`.
+///
+/// Wannier90 chooses the R-vectors lying in the Wigner-Seitz cell of the supercell
+/// conjugate to `mp_grid`. Each point in the interior of that cell is counted once, so
+/// there are exactly `product(mp_grid)` R-vectors when no point lies on the cell boundary.
+/// Points on the boundary are shared between `degeneracy` equivalent positions and are all
+/// included, which can only increase the count. Which points lie on the boundary depends
+/// on the shape of the unit cell, not just on `mp_grid`, so the value returned here is the
+/// boundary-free count and is a lower bound for `nrpts`. The boundary corrections are
+/// accounted for exactly by `check_nrpts`, which uses the degeneracies.
+pub fn expected_nrpts(mp_grid: [u64; 3]) -> usize {
+    (mp_grid[0] * mp_grid[1] * mp_grid[2]) as usize
+}
+
+/// Check that the R-vectors of `ham` are consistent with the k-point grid `mp_grid`.
+///
+/// The number of R-vectors must be at least `expected_nrpts(mp_grid)`, and the
+/// R-vectors weighted by the inverse of their degeneracies must sum to exactly that value.
+pub fn check_nrpts(ham: &HamiltonianR, mp_grid: [u64; 3]) -> Result<(), Error> {
+    let expected = expected_nrpts(mp_grid);
+
+    if ham.nrpts() < expected {
+        return Err(Error::TooFewRVectors {
+            nrpts: ham.nrpts(),
+            expected,
+        });
+    }
+
+    let weight: f64 = ham.degeneracies.iter().map(|&d| 1.0 / (d as f64)).sum();
+    if (weight - expected as f64).abs() > 1e-8 {
+        return Err(Error::DegeneracyWeight { weight, expected });
+    }
+
+    Ok(())
+}
+
+#[derive(Fail, Debug)]
+pub enum Error {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file.")] UnexpectedEof,
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected token `{}` after the last R-vector.", _0)]
+    UnexpectedToken(String),
+    #[fail(display = "Unexpected matrix element R = {:?}, m = {}, n = {}.", r, m, n)]
+    UnexpectedIndex { r: [i64; 3], m: usize, n: usize },
+    #[fail(display = "Got {} R-vectors, but expected at least {}.", nrpts, expected)]
+    TooFewRVectors { nrpts: usize, expected: usize },
+    #[fail(display = "Sum of inverse degeneracies is {}, but expected {}.", weight, expected)]
+    DegeneracyWeight { weight: f64, expected: usize },
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<TokenError> for Error {
+    fn from(e: TokenError) -> Error {
+        match e {
+            TokenError::Eof => Error::UnexpectedEof,
+            TokenError::Invalid(tok) => Error::InvalidNumber(tok),
+        }
+    }
+}
+
+impl From<RBlockError> for Error {
+    fn from(e: RBlockError) -> Error {
+        match e {
+            RBlockError::Token(e) => Error::from(e),
+            RBlockError::UnexpectedIndex { r, m, n } => Error::UnexpectedIndex { r, m, n },
+        }
+    }
+}
@@ -1,20 +1,255 @@
+use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::cmp::Ordering;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::fs::File;
+use std::f64::consts::PI;
+use serde_json;
+use toml;
+use kmesh;
+use kmesh::KPointIter;
+use kpath::KPath;
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Input {
     pub num_bands: u64,
     pub num_wann: u64,
     pub write_hr: Option<bool>,
+    /// Write the lattice vectors, Hamiltonian and position matrix elements to
+    /// `seedname_tb.dat`. This supersedes `write_hr` in Wannier90 2.1 and later, since the
+    /// Hamiltonian in `seedname_tb.dat` contains everything in `seedname_hr.dat`.
+    pub write_tb: Option<bool>,
+    /// Write the unitary matrices to `seedname_u.mat` and, with disentanglement,
+    /// `seedname_u_dis.mat`.
+    pub write_u_matrices: Option<bool>,
+    /// Choose the lattice vector of each hopping `H_mn(R)` to minimise the distance between
+    /// the Wannier centres, improving real-space interpolation. The shifted vectors are
+    /// written to `seedname_wsvec.dat`; `seedname_hr.dat` itself is unchanged.
+    pub use_ws_distance: Option<bool>,
     pub mlwf_iteration_mode: MLWFIterationMode,
+    /// Number of iterations between writes of the spreads to `seedname.wout`.
+    pub num_print_cycles: Option<u64>,
+    /// Number of iterations between writes of the checkpoint file.
+    pub num_dump_cycles: Option<u64>,
+    /// Trade memory for speed, from `MIN_OPTIMISATION` to `MAX_OPTIMISATION`. Higher levels
+    /// store more intermediate data to run faster; levels of 0 and below reduce the memory
+    /// used for large `num_wann` at some cost in speed. Wannier90 uses 3 by default.
+    pub optimisation: Option<i64>,
+    pub restart: Option<Restart>,
+    pub output_flags: Option<OutputFlags>,
+    /// Read the `seedname.unk` wavefunction files as formatted rather than binary files.
+    pub wvfn_formatted: Option<bool>,
+    /// Read `seedname.spn` as a formatted rather than binary file.
+    pub spn_formatted: Option<bool>,
+    /// Read `seedname.uHu` as a formatted rather than binary file.
+    pub uhu_formatted: Option<bool>,
+    /// Use the centres of the projections as guiding centres during localization.
+    pub guiding_centres: Option<bool>,
+    /// Number of iterations between updates of the guiding centres.
+    pub num_guide_cycles: Option<u64>,
+    /// Number of initial iterations before guiding centres are used.
+    pub num_no_guide_iter: Option<u64>,
+    pub selective_localization: Option<SelectiveLocalization>,
+    /// Construct symmetry-adapted Wannier functions using the site symmetry given in
+    /// `seedname.dmn`.
+    pub site_symmetry: Option<bool>,
+    /// Convergence threshold for symmetrizing the Wannier functions. May be given only if
+    /// `site_symmetry` is true.
+    pub symmetrize_eps: Option<f64>,
+    /// Bands (1-based indices in increasing order) to exclude from the Wannierization.
+    pub exclude_bands: Option<Vec<u64>>,
 
     pub disentanglement: Option<Disentanglement>,
 
+    pub dos: Option<Dos>,
+    pub berry: Option<Berry>,
+    pub boltzwann: Option<BoltzWann>,
+
+    /// Interpolate the energies at the k-points listed in `seedname_geninterp.kpt`, which
+    /// can be written by `serialize::write_geninterp_kpt`.
+    pub geninterp: Option<bool>,
+    /// Also compute the band velocities. May be given only if `geninterp` is true.
+    pub geninterp_alsofirstder: Option<bool>,
+    /// Write a single output file instead of one per process. May be given only if
+    /// `geninterp` is true.
+    pub geninterp_single_file: Option<bool>,
+
+    /// Interpolate the bands along `kpoint_path`, writing them to `seedname_band.dat`.
+    pub bands_plot: Option<bool>,
+    /// Path for the interpolated band structure. Required if `bands_plot` is true.
+    pub kpoint_path: Option<KPath>,
+    /// May be given only if `bands_plot` is true.
+    pub bands_plot_options: Option<BandsPlotOptions>,
+
+    /// Use the Bloch phases as the initial guess for the Wannier functions instead of
+    /// projections. In this case `projections` may be empty.
+    pub use_bloch_phases: Option<bool>,
+    /// The wavefunctions are spinors. Each projection orbital then gives two Wannier
+    /// functions, one per spin channel, unless the projection selects a single channel;
+    /// `num_wann` counts both. See `count_wannier`.
     pub spinors: bool,
+    /// Spin channel to Wannierize for collinear spin-polarized calculations.
+    pub spin: Option<SpinChannel>,
+    /// Polar angle in degrees of the spin quantisation axis, from 0 to 180. May be given
+    /// only if `spinors` is true.
+    pub spin_axis_polar: Option<f64>,
+    /// Azimuthal angle in degrees of the spin quantisation axis, from 0 to 360. May be
+    /// given only if `spinors` is true.
+    pub spin_axis_azimuth: Option<f64>,
     pub projection_units: Option<LatticeUnits>,
     pub projections: Vec<Projection>,
 
     pub unit_cell_cart: Cell,
     pub positions: Positions,
 
-    pub k_points: [u64; 3],
+    /// Named `k_points` in configuration files written by earlier versions.
+    #[serde(alias = "k_points")]
+    pub kpoints: KPointMesh,
+    /// Use only the Gamma point. Requires `kpoints == KPointMesh::Uniform([1, 1, 1])`.
+    pub gamma_only: Option<bool>,
+    /// Tolerance for deciding whether k-point shells have equal distance from a k-point.
+    pub kmesh_tol: Option<f64>,
+    /// Number of k-point shells to search when finding b-vectors. Must be at least 12,
+    /// the Wannier90 default.
+    pub search_shells: Option<u64>,
+    /// Skip the check that the b-vectors satisfy the B1 completeness relation.
+    pub skip_b1_tests: Option<bool>,
+
+    /// Comments written at the end of the line of a keyword or of the `begin` line of a
+    /// block, keyed by the lowercase keyword or block name. These may be taken from
+    /// `parse::WinFile::comments` to preserve annotations of a hand-edited input file.
+    /// Comments on the `kpoints` block are not written.
+    #[serde(default)]
+    pub comments: HashMap<String, String>,
+}
+
+impl Input {
+    /// Iterator over the k-points written to the `kpoints` block, in lattice coordinates:
+    /// only the Gamma point if `gamma_only` is set, and otherwise `kpoints.points()`.
+    /// Uniform grids are generated lazily, with the first component varying slowest and
+    /// the last varying fastest; this is the order in which pw.x must be given the nscf
+    /// k-points so that the k-point indices of `seedname.amn` and `seedname.mmn` match.
+    pub fn kpoint_iter<'a>(&'a self) -> KPointIter<'a> {
+        if self.gamma_only == Some(true) {
+            KPointIter::Uniform(kmesh::uniform([1, 1, 1]))
+        } else {
+            self.kpoints.points()
+        }
+    }
+
+    /// Return a copy of this input which restarts a previous calculation from the given
+    /// stage. Only `restart` is changed: Wannier90 requires the geometry and k-points of a
+    /// restarted calculation to be identical to those of the original calculation.
+    pub fn for_restart(&self, restart: Restart) -> Input {
+        let mut input = self.clone();
+        input.restart = Some(restart);
+
+        input
+    }
+
+    /// Return a copy of this input with the Gamma-centered uniform k-point grid `nk`. Other
+    /// settings, including `gamma_only`, are unchanged.
+    pub fn with_kpoints(&self, nk: [u64; 3]) -> Input {
+        let mut input = self.clone();
+        input.kpoints = KPointMesh::Uniform(nk);
+
+        input
+    }
+
+    /// Return a copy of this input with atoms and projections in a canonical order, so
+    /// that inputs describing the same system serialize identically.
+    ///
+    /// Atoms are sorted by species, by label, and then by their lattice coordinates; their
+    /// coordinates are not converted. Projections are sorted with species projections
+    /// first, by species name, followed by projections centered at a position, by that
+    /// position, and `Random` last. Projections which compare equal keep their relative
+    /// order. The order of the projections sets the order of the Wannier functions, so
+    /// the Wannier functions of the sorted input may be numbered differently.
+    pub fn sorted(&self) -> Input {
+        let mut input = self.clone();
+
+        let crystal = self.positions
+            .to_crystal(&self.unit_cell_cart)
+            .unwrap_or_else(|_| self.positions.clone());
+        let mut atoms: Vec<(AtomCoordinate, [f64; 3])> = self.positions
+            .coordinates
+            .iter()
+            .cloned()
+            .zip(crystal.coordinates.iter().map(|c| c.r))
+            .collect();
+        atoms.sort_by(|a, b| {
+            a.0
+                .species
+                .cmp(&b.0.species)
+                .then_with(|| a.0.label.cmp(&b.0.label))
+                .then_with(|| compare_vectors(a.1, b.1))
+        });
+        input.positions.coordinates = atoms.into_iter().map(|(c, _)| c).collect();
+
+        input.projections.sort_by(compare_projections);
+
+        input
+    }
+}
+
+fn compare_vectors(a: [f64; 3], b: [f64; 3]) -> Ordering {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| x.partial_cmp(y).unwrap_or(Ordering::Equal))
+        .find(|&o| o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn compare_projections(a: &Projection, b: &Projection) -> Ordering {
+    let key = |p: &Projection| match *p {
+        Projection::Site { ref site, .. } => match *site {
+            ProjectionSite::Species(ref species) => (0, species.clone(), [0.0; 3]),
+            ProjectionSite::CenterCrystal(r) => (1, String::new(), r),
+            ProjectionSite::CenterCartesian(r) => (2, String::new(), r),
+        },
+        Projection::Random => (3, String::new(), [0.0; 3]),
+    };
+    let (a_kind, a_species, a_r) = key(a);
+    let (b_kind, b_species, b_r) = key(b);
+
+    a_kind
+        .cmp(&b_kind)
+        .then_with(|| a_species.cmp(&b_species))
+        .then_with(|| compare_vectors(a_r, b_r))
+}
+
+/// Stage from which to restart a previous calculation using its `seedname.chk` checkpoint.
+///
+/// Every mode reads `seedname.chk` from a previous run with the same seedname, so that
+/// file must be present when Wannier90 is run; this is not checked by `validate`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Restart {
+    /// Continue from wherever the checkpoint was written.
+    Default,
+    /// Restart the minimisation of the spread from the checkpointed unitary matrices.
+    Wannierise,
+    /// Skip the minimisation and go directly to plotting.
+    Plot,
+    /// Skip the minimisation and go directly to the transport calculation.
+    Transport,
+}
+
+/// Less commonly used flags controlling additional output. Flags which are `None` are
+/// not written.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct OutputFlags {
+    /// Translate the Wannier function centres to the home unit cell on output.
+    pub translate_home_cell: Option<bool>,
+    /// Write the Wannier function centres to `seedname_centres.xyz`.
+    pub write_xyz: Option<bool>,
+    /// Write the data needed for van der Waals calculations to `seedname.vdw`.
+    pub write_vdw_data: Option<bool>,
+    /// Write the on-site energies and the magnitude of the hopping terms as a function of
+    /// distance to `seedname.wout`.
+    pub write_hr_diag: Option<bool>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -27,14 +262,153 @@ pub enum MLWFIterationMode {
     MLWF { num_iter: u64 },
 }
 
+/// Number of disentanglement iterations used by Wannier90 if `dis_num_iter` is not given.
+pub const DEFAULT_DIS_NUM_ITER: u64 = 200;
+
+/// Mixing ratio used by Wannier90 if `dis_mix_ratio` is not given.
+pub const DEFAULT_DIS_MIX_RATIO: f64 = 0.5;
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Disentanglement {
-    pub dis_win_min: f64,
-    pub dis_win_max: f64,
-    pub dis_froz_min: f64,
-    pub dis_froz_max: f64,
+    /// Bottom of the outer energy window. If `None`, Wannier90 uses the lowest band energy.
+    pub dis_win_min: Option<f64>,
+    /// Top of the outer energy window. If `None`, Wannier90 uses the highest band energy.
+    pub dis_win_max: Option<f64>,
+    /// Bottom of the frozen energy window. If `None`, Wannier90 uses `dis_win_min`.
+    pub dis_froz_min: Option<f64>,
+    /// Top of the frozen energy window. If `None`, no states are frozen by energy.
+    pub dis_froz_max: Option<f64>,
     pub dis_num_iter: u64,
     pub dis_mix_ratio: f64,
+    /// Restrict disentanglement to spheres in k-space.
+    pub dis_spheres: Option<DisSpheres>,
+    /// Freeze states by their projectability onto the projections, in addition to the
+    /// frozen energy window.
+    pub dis_froz_proj: Option<bool>,
+    /// States with projectability below `dis_proj_min` are excluded from disentanglement.
+    pub dis_proj_min: Option<f64>,
+    /// States with projectability above `dis_proj_max` are frozen.
+    pub dis_proj_max: Option<f64>,
+}
+
+/// Spheres in k-space outside of which the bands are not disentangled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisSpheres {
+    /// Number of spheres; must equal the length of `spheres`.
+    pub dis_spheres_num: u64,
+    /// Index of the lowest band included in the Wannierization outside of the spheres.
+    pub dis_spheres_first_wann: Option<u64>,
+    pub spheres: Vec<DisSphere>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisSphere {
+    /// Center in crystal coordinates (the basis of reciprocal lattice vectors).
+    pub center: [f64; 3],
+    /// Radius in inverse Angstrom.
+    pub radius: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpinChannel {
+    Up,
+    Down,
+}
+
+/// Selective localization of a subset of the Wannier functions (SLWF): only the spread of
+/// the first `slwf_num` Wannier functions is minimised.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelectiveLocalization {
+    /// Number of Wannier functions to localize, in the range `1..=num_wann`.
+    pub slwf_num: u64,
+    /// Constrain the centres of the selected Wannier functions to the centres of their
+    /// projections.
+    pub slwf_constrain: Option<bool>,
+    /// Weight of the centre constraint in the functional being minimised. Must not be
+    /// negative.
+    pub slwf_lambda: Option<f64>,
+}
+
+/// Density of states calculation. This is performed by postw90.x only; wannier90.x
+/// ignores these keywords.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dos {
+    /// Interpolation k-point grid used to compute the density of states.
+    pub dos_kmesh: [u64; 3],
+    pub dos_energy_min: f64,
+    pub dos_energy_max: f64,
+    pub dos_energy_step: f64,
+    /// Use adaptive smearing.
+    pub dos_adpt_smr: Option<bool>,
+}
+
+/// Berry phase calculation, performed by postw90.x.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Berry {
+    pub berry_task: BerryTask,
+    /// Interpolation k-point grid used to integrate over the Brillouin zone.
+    pub berry_kmesh: [u64; 3],
+    /// Size of the grid used to refine the integration around k-points with a large Berry
+    /// curvature.
+    pub berry_curv_adpt_kmesh: Option<u64>,
+}
+
+/// Boltzmann transport calculation (BoltzWann), performed by postw90.x. The chemical
+/// potential and temperature are scanned over `min..=max` in increments of `step`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoltzWann {
+    /// Interpolation k-point grid used to compute the transport distribution function.
+    pub boltz_kmesh: [u64; 3],
+    /// Energy step in eV of the transport distribution function.
+    pub boltz_tdf_energy_step: Option<f64>,
+    /// Chemical potential range in eV.
+    pub boltz_mu_min: f64,
+    pub boltz_mu_max: f64,
+    pub boltz_mu_step: f64,
+    /// Temperature range in K.
+    pub boltz_temp_min: f64,
+    pub boltz_temp_max: f64,
+    pub boltz_temp_step: f64,
+    /// Constant relaxation time in fs.
+    pub boltz_relax_time: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BerryTask {
+    /// Anomalous Hall conductivity.
+    Ahc,
+    /// Orbital magnetization.
+    Morb,
+    /// Complex optical conductivity and joint density of states.
+    Kubo,
+    /// Spin Hall conductivity.
+    Shc,
+}
+
+/// Output settings for the interpolated band structure.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandsPlotOptions {
+    /// Formats of the plotting scripts written along with `seedname_band.dat`. If empty,
+    /// the Wannier90 default (gnuplot) is used.
+    pub format: Vec<BandsPlotFormat>,
+    pub mode: Option<BandsPlotMode>,
+    /// Wannier functions (1-based indices in increasing order) onto which the bands are
+    /// projected, giving the orbital character of each band in `seedname_band.dat`.
+    pub project: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandsPlotFormat {
+    Gnuplot,
+    Xmgrace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandsPlotMode {
+    /// Slater-Koster interpolation using all hoppings.
+    SlaterKoster,
+    /// Truncate the Hamiltonian to the hoppings within `hr_cutoff` and `dist_cutoff`.
+    Cut,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -45,11 +419,148 @@ pub enum Projection {
         ang_mtm: Vec<AngularMomentum>,
         zaxis: Option<[f64; 3]>,
         xaxis: Option<[f64; 3]>,
+        /// Index of the radial function, from 1 to `MAX_RADIAL`.
         radial: Option<u64>,
+        /// Diffusivity of the radial function, which must be positive.
         zona: Option<f64>,
+        /// Spin channel of the trial orbitals. May be given only if `spinors` is true; if
+        /// `None`, both spin channels are used.
+        spin: Option<SpinProjection>,
+        /// Spin quantisation axis in Cartesian coordinates, written only together with
+        /// `spin`; Wannier90 uses the z-axis by default.
+        quant_dir: Option<[f64; 3]>,
+        /// Per-angular-momentum `radial` and `zona`, overriding the site-wide values above
+        /// for the given orbitals.
+        #[serde(default)]
+        per_orbital: Vec<OrbitalRadial>,
     },
 }
 
+/// Spin channel of the trial orbitals of a projection in a spinor calculation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpinProjection {
+    Up,
+    Down,
+    Both,
+}
+
+impl SpinProjection {
+    /// Number of spin channels included.
+    pub fn num_channels(&self) -> u64 {
+        match *self {
+            SpinProjection::Up | SpinProjection::Down => 1,
+            SpinProjection::Both => 2,
+        }
+    }
+}
+
+/// Radial part of the trial orbitals with angular momentum `ang_mtm` in a
+/// `Projection::Site`. A `None` field falls back to the site-wide value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrbitalRadial {
+    pub ang_mtm: AngularMomentum,
+    pub radial: Option<u64>,
+    pub zona: Option<f64>,
+}
+
+impl Projection {
+    /// One `Projection::Site` for each orbital given for each species in `map`, such as
+    /// `W: d` and `Se: p`. Species are taken in the order they first appear in `positions`,
+    /// and matched by `AtomCoordinate::site_label`; species in `map` which are not present
+    /// in `positions` are skipped. Compare `count_wannier` for the result to `num_wann` to
+    /// check the choice of orbitals.
+    pub fn from_species_map(
+        positions: &Positions,
+        map: &HashMap<String, Vec<AngularMomentum>>,
+    ) -> Vec<Projection> {
+        let mut species: Vec<&str> = Vec::new();
+        for c in &positions.coordinates {
+            if !species.contains(&c.site_label()) {
+                species.push(c.site_label());
+            }
+        }
+
+        let mut projections = Vec::new();
+        for name in species {
+            let ang_mtms = match map.get(name) {
+                Some(ang_mtms) => ang_mtms,
+                None => continue,
+            };
+            for l in ang_mtms {
+                projections.push(Projection::Site {
+                    site: ProjectionSite::Species(String::from(name)),
+                    ang_mtm: vec![l.clone()],
+                    zaxis: None,
+                    xaxis: None,
+                    radial: None,
+                    zona: None,
+                    spin: None,
+                    quant_dir: None,
+                    per_orbital: Vec::new(),
+                });
+            }
+        }
+        projections
+    }
+
+    /// Like `from_species_map`, but fails if `map` gives a species which is not present in
+    /// `positions`. If several species are missing, the first in sorted order is reported.
+    pub fn from_species_map_strict(
+        positions: &Positions,
+        map: &HashMap<String, Vec<AngularMomentum>>,
+    ) -> Result<Vec<Projection>, Error> {
+        let missing = map.keys()
+            .filter(|&name| !positions.coordinates.iter().any(|c| c.site_label() == name))
+            .min();
+        if let Some(species) = missing {
+            return Err(Error::UnknownSpecies(species.clone()));
+        }
+
+        Ok(Projection::from_species_map(positions, map))
+    }
+
+    /// Return a copy of this projection with `zaxis` and `xaxis` rescaled to unit length.
+    /// Zero-length axes, which are rejected by `validate`, are left unchanged.
+    pub fn normalized(&self) -> Projection {
+        let mut proj = self.clone();
+
+        if let Projection::Site {
+            ref mut zaxis,
+            ref mut xaxis,
+            ..
+        } = proj
+        {
+            for axis in vec![zaxis, xaxis] {
+                if let Some(ref mut v) = *axis {
+                    let len = norm(*v);
+                    if len > 0.0 {
+                        *v = [v[0] / len, v[1] / len, v[2] / len];
+                    }
+                }
+            }
+        }
+
+        proj
+    }
+
+    /// Effective `(radial, zona)` for the orbitals with angular momentum `l`: a matching
+    /// `per_orbital` entry takes precedence over the site-wide values.
+    pub fn radial_for(&self, l: &AngularMomentum) -> (Option<u64>, Option<f64>) {
+        match *self {
+            Projection::Random => (None, None),
+            Projection::Site {
+                radial,
+                zona,
+                ref per_orbital,
+                ..
+            } => match per_orbital.iter().find(|o| o.ang_mtm == *l) {
+                Some(o) => (o.radial.or(radial), o.zona.or(zona)),
+                None => (radial, zona),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ProjectionSite {
     /// Projection centered on all atoms of the given species.
@@ -60,13 +571,119 @@ pub enum ProjectionSite {
     CenterCrystal([f64; 3]),
 }
 
+impl ProjectionSite {
+    /// Return this site with a position center expressed in Cartesian coordinates, in the
+    /// units of `cell`. The result should be used with `projection_units` equal to
+    /// `cell.units`. `Species` and Cartesian sites are returned unchanged.
+    pub fn to_cartesian(&self, cell: &Cell) -> ProjectionSite {
+        match *self {
+            ProjectionSite::CenterCrystal(r) => {
+                ProjectionSite::CenterCartesian(crystal_to_cartesian(cell.cell, r))
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Return this site with a position center expressed in lattice coordinates of `cell`,
+    /// treating a Cartesian center as given in the units of `cell`. `Species` and lattice
+    /// coordinate sites are returned unchanged.
+    pub fn to_crystal(&self, cell: &Cell) -> Result<ProjectionSite, Error> {
+        match *self {
+            ProjectionSite::CenterCartesian(r) => {
+                let lat_inv = invert_matrix(cell.cell).ok_or(Error::SingularCell)?;
+                Ok(ProjectionSite::CenterCrystal(cartesian_to_crystal(lat_inv, r)))
+            }
+            _ => Ok(self.clone()),
+        }
+    }
+}
+
+/// Angular part of a trial orbital: a complete shell with angular momentum `l`, a hybrid
+/// (`l` = -1 through -5), or selected orbitals `mr` of either.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AngularMomentum {
     S,
     P,
     D,
     F,
-    // TODO: hybrid orbitals and individual l=l,mr=mr orbitals.
+    Sp,
+    Sp2,
+    Sp3,
+    Sp3d,
+    Sp3d2,
+    /// The orbitals `mr` (counting from 1, in the order of the Wannier90 user guide) of the
+    /// shell or hybrid `l`, as in `l=2,mr=1,4`.
+    Orbitals { l: i64, mr: Vec<u64> },
+}
+
+impl AngularMomentum {
+    /// Number of orbitals with this angular momentum, not counting spin.
+    pub fn num_orbitals(&self) -> u64 {
+        match *self {
+            AngularMomentum::S => 1,
+            AngularMomentum::P => 3,
+            AngularMomentum::D => 5,
+            AngularMomentum::F => 7,
+            AngularMomentum::Sp => 2,
+            AngularMomentum::Sp2 => 3,
+            AngularMomentum::Sp3 => 4,
+            AngularMomentum::Sp3d => 5,
+            AngularMomentum::Sp3d2 => 6,
+            AngularMomentum::Orbitals { ref mr, .. } => mr.len() as u64,
+        }
+    }
+
+    /// The Wannier90 value of `l`: 0 through 3 for shells, -1 through -5 for hybrids.
+    pub fn l(&self) -> i64 {
+        match *self {
+            AngularMomentum::S => 0,
+            AngularMomentum::P => 1,
+            AngularMomentum::D => 2,
+            AngularMomentum::F => 3,
+            AngularMomentum::Sp => -1,
+            AngularMomentum::Sp2 => -2,
+            AngularMomentum::Sp3 => -3,
+            AngularMomentum::Sp3d => -4,
+            AngularMomentum::Sp3d2 => -5,
+            AngularMomentum::Orbitals { l, .. } => l,
+        }
+    }
+
+    /// The complete shell or hybrid with the given `l`, or `None` if `l` is not in -5..=3.
+    pub fn from_l(l: i64) -> Option<AngularMomentum> {
+        match l {
+            0 => Some(AngularMomentum::S),
+            1 => Some(AngularMomentum::P),
+            2 => Some(AngularMomentum::D),
+            3 => Some(AngularMomentum::F),
+            -1 => Some(AngularMomentum::Sp),
+            -2 => Some(AngularMomentum::Sp2),
+            -3 => Some(AngularMomentum::Sp3),
+            -4 => Some(AngularMomentum::Sp3d),
+            -5 => Some(AngularMomentum::Sp3d2),
+            _ => None,
+        }
+    }
+
+    /// True unless this lists orbitals `mr` which are missing from the shell `l`, repeated,
+    /// or absent altogether.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            AngularMomentum::Orbitals { l, ref mr } => {
+                let shell_size = match AngularMomentum::from_l(l) {
+                    Some(shell) => shell.num_orbitals(),
+                    None => return false,
+                };
+                let mut sorted = mr.clone();
+                sorted.sort();
+                sorted.dedup();
+                !mr.is_empty()
+                    && sorted.len() == mr.len()
+                    && mr.iter().all(|&m| m >= 1 && m <= shell_size)
+            }
+            _ => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -75,18 +692,144 @@ pub struct Cell {
     pub cell: [[f64; 3]; 3],
 }
 
+impl Cell {
+    /// Return a copy of this cell with lattice vectors expressed in `target` units.
+    /// If `target` is the same as the current units, the cell is returned unchanged.
+    pub fn to_units(&self, target: LatticeUnits) -> Cell {
+        let scale = self.units.scale_to(&target);
+
+        let mut cell = self.cell;
+        for a in cell.iter_mut() {
+            for x in a.iter_mut() {
+                *x *= scale;
+            }
+        }
+
+        Cell {
+            units: target,
+            cell,
+        }
+    }
+
+    /// Matrix whose rows are the lattice vectors expressed in `out_units`. Multiplying
+    /// lattice coordinates on the left by this matrix gives Cartesian coordinates.
+    pub fn to_cartesian_matrix(&self, out_units: LatticeUnits) -> [[f64; 3]; 3] {
+        self.to_units(out_units).cell
+    }
+
+    /// Reciprocal lattice vectors `b_j` (rows), satisfying `a_i . b_j = 2 pi delta_ij`,
+    /// in units of inverse `self.units`.
+    pub fn reciprocal(&self) -> Result<[[f64; 3]; 3], Error> {
+        let lat_inv = invert_matrix(self.cell).ok_or(Error::SingularCell)?;
+
+        let mut recip = [[0.0; 3]; 3];
+        for j in 0..3 {
+            for k in 0..3 {
+                recip[j][k] = 2.0 * PI * lat_inv[k][j];
+            }
+        }
+        Ok(recip)
+    }
+}
+
+/// Length of one Bohr radius in Angstrom.
+pub const BOHR_TO_ANGSTROM: f64 = 0.52917721067;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LatticeUnits {
     Bohr,
     Angstrom,
 }
 
+impl LatticeUnits {
+    /// Factor by which a length given in these units must be multiplied to express it
+    /// in `target` units.
+    pub fn scale_to(&self, target: &LatticeUnits) -> f64 {
+        match (self, target) {
+            (&LatticeUnits::Bohr, &LatticeUnits::Angstrom) => BOHR_TO_ANGSTROM,
+            (&LatticeUnits::Angstrom, &LatticeUnits::Bohr) => 1.0 / BOHR_TO_ANGSTROM,
+            _ => 1.0,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Positions {
     pub coordinate_type: PositionCoordinateType,
     pub coordinates: Vec<AtomCoordinate>,
 }
 
+impl Positions {
+    /// Number of atoms of each species. Atoms are counted by `species`, not by `label`.
+    pub fn species_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for c in &self.coordinates {
+            *counts.entry(c.species.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Return these positions expressed in lattice coordinates of `cell`.
+    /// Fails if the lattice vectors of `cell` are linearly dependent.
+    pub fn to_crystal(&self, cell: &Cell) -> Result<Positions, Error> {
+        let from_units = match self.coordinate_type.units() {
+            Some(units) => units,
+            None => return Ok(self.clone()),
+        };
+
+        let lat = cell.to_cartesian_matrix(from_units);
+        let lat_inv = invert_matrix(lat).ok_or(Error::SingularCell)?;
+
+        let coordinates = self.coordinates
+            .iter()
+            .map(|c| AtomCoordinate {
+                species: c.species.clone(),
+                label: c.label.clone(),
+                r: cartesian_to_crystal(lat_inv, c.r),
+            })
+            .collect();
+
+        Ok(Positions {
+            coordinate_type: PositionCoordinateType::Crystal,
+            coordinates,
+        })
+    }
+
+    /// Return these positions expressed as Cartesian coordinates in the given `units`.
+    /// `cell` is used to convert from lattice coordinates.
+    pub fn to_cartesian(&self, cell: &Cell, units: LatticeUnits) -> Positions {
+        let coordinates = match self.coordinate_type.units() {
+            Some(from_units) => {
+                let scale = from_units.scale_to(&units);
+                self.coordinates
+                    .iter()
+                    .map(|c| AtomCoordinate {
+                        species: c.species.clone(),
+                        label: c.label.clone(),
+                        r: [scale * c.r[0], scale * c.r[1], scale * c.r[2]],
+                    })
+                    .collect()
+            }
+            None => {
+                let lat = cell.to_cartesian_matrix(units.clone());
+                self.coordinates
+                    .iter()
+                    .map(|c| AtomCoordinate {
+                        species: c.species.clone(),
+                        label: c.label.clone(),
+                        r: crystal_to_cartesian(lat, c.r),
+                    })
+                    .collect()
+            }
+        };
+
+        Positions {
+            coordinate_type: PositionCoordinateType::cartesian(units),
+            coordinates,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PositionCoordinateType {
     BohrCartesian,
@@ -94,16 +837,406 @@ pub enum PositionCoordinateType {
     Crystal,
 }
 
+impl PositionCoordinateType {
+    /// Cartesian coordinate type with lengths given in `units`.
+    pub fn cartesian(units: LatticeUnits) -> PositionCoordinateType {
+        match units {
+            LatticeUnits::Bohr => PositionCoordinateType::BohrCartesian,
+            LatticeUnits::Angstrom => PositionCoordinateType::AngstromCartesian,
+        }
+    }
+
+    /// Length units of Cartesian coordinates, or `None` for lattice coordinates.
+    pub fn units(&self) -> Option<LatticeUnits> {
+        match *self {
+            PositionCoordinateType::BohrCartesian => Some(LatticeUnits::Bohr),
+            PositionCoordinateType::AngstromCartesian => Some(LatticeUnits::Angstrom),
+            PositionCoordinateType::Crystal => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AtomCoordinate {
     pub species: String,
+    /// Label distinguishing this atom from other atoms of the same species, such as `Fe1`.
+    /// If given, the label is written in place of `species` in the atomic positions block
+    /// and is the name matched by `ProjectionSite::Species`.
+    pub label: Option<String>,
     pub r: [f64; 3],
 }
 
-pub fn validate(input: &Input) -> Result<(), ErrorList> {
+impl AtomCoordinate {
+    /// Name identifying this atom in the input file: `label` if given, otherwise
+    /// `species`.
+    pub fn site_label(&self) -> &str {
+        self.label.as_ref().unwrap_or(&self.species)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum KPointMesh {
+    /// Gamma-centered uniform grid with the given number of points along each
+    /// reciprocal lattice vector.
+    Uniform([u64; 3]),
+    /// Uniform grid with each point displaced by `shift[i] / nk[i]` along reciprocal
+    /// lattice vector `i`; i.e. `shift` is given in units of the grid spacing, so that
+    /// a shift of 0.5 gives a grid offset by half a step.
+    UniformShifted([u64; 3], [f64; 3]),
+    /// Explicit list of k-points in lattice coordinates.
+    Explicit(Vec<[f64; 3]>),
+}
+
+impl KPointMesh {
+    /// Grid dimensions to use for `mp_grid`. For an explicit list, the dimensions are
+    /// inferred from the number of distinct values of each k-point component, and `None`
+    /// is returned if the list of k-points does not form a complete grid or contains a
+    /// non-finite component.
+    pub fn mp_grid(&self) -> Option<[u64; 3]> {
+        match *self {
+            KPointMesh::Uniform(nk) | KPointMesh::UniformShifted(nk, _) => Some(nk),
+            KPointMesh::Explicit(ref ks) => {
+                if ks.iter().any(|k| k.iter().any(|x| !x.is_finite())) {
+                    return None;
+                }
+                let mut nk = [0; 3];
+                for i in 0..3 {
+                    let mut vals: Vec<f64> = ks.iter().map(|k| k[i]).collect();
+                    vals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    vals.dedup_by(|a, b| (*a - *b).abs() < 1e-8);
+                    nk[i] = vals.len() as u64;
+                }
+
+                if nk[0] * nk[1] * nk[2] == ks.len() as u64 && ks.len() > 0 {
+                    Some(nk)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Iterator over all k-points in lattice coordinates, in the order they are written
+    /// to the `kpoints` block. Uniform grids are generated lazily.
+    ///
+    /// Uniform grids are listed in the order of `qe::pw::input::generate_uniform_kpoints`,
+    /// with the first component varying slowest and the last component varying fastest.
+    /// This order is relied on: pw2wannier90.x writes `seedname.mmn` and `seedname.amn`
+    /// for the k-points of the nscf calculation in the order they are listed there, and
+    /// Wannier90 matches them to the `kpoints` block by position.
+    pub fn points<'a>(&'a self) -> KPointIter<'a> {
+        match *self {
+            KPointMesh::Uniform(nk) => KPointIter::Uniform(kmesh::uniform(nk)),
+            KPointMesh::UniformShifted(nk, shift) => {
+                KPointIter::Uniform(kmesh::uniform_shifted_iter(nk, shift))
+            }
+            KPointMesh::Explicit(ref ks) => KPointIter::Explicit(ks.iter()),
+        }
+    }
+}
+
+/// Time-reversal partners of the k-points written to the input file. Entry `i` gives
+/// the index `i` together with the index of the k-point equal to `-k_i` modulo a
+/// reciprocal lattice vector. The partner is `None` if `k_i` is its own partner (as for
+/// the Gamma point), or if the partner does not appear in the list of k-points.
+pub fn trs_pairs(input: &Input) -> Vec<(usize, Option<usize>)> {
+    let ks: Vec<[f64; 3]> = input.kpoint_iter().collect();
+
+    let is_integer = |x: f64| (x - x.round()).abs() < 1e-8;
+    let is_partner = |a: &[f64; 3], b: &[f64; 3]| (0..3).all(|i| is_integer(a[i] + b[i]));
+
+    ks.iter()
+        .enumerate()
+        .map(|(i, k)| {
+            if is_partner(k, k) {
+                (i, None)
+            } else {
+                (i, ks.iter().position(|other| is_partner(k, other)))
+            }
+        })
+        .collect()
+}
+
+/// Combine site projections which differ only in their angular momenta into a single
+/// projection, so that `Se:l=0` and `Se:l=1` become `Se:l=0;l=1`. Each merged projection
+/// takes the place of the first of its parts; `Random` is kept as is.
+///
+/// Repeated angular momenta are kept, since each occurrence gives its own set of trial
+/// orbitals. The number of orbitals given by the projections is therefore unchanged.
+pub fn merge_projections(projections: &[Projection]) -> Vec<Projection> {
+    let mut merged: Vec<Projection> = Vec::new();
+
+    for proj in projections {
+        let target = merged.iter_mut().find(|m| same_site_settings(m, proj));
+        match (target, proj) {
+            (
+                Some(&mut Projection::Site {
+                    ang_mtm: ref mut merged_ang_mtm,
+                    ..
+                }),
+                &Projection::Site { ref ang_mtm, .. },
+            ) => merged_ang_mtm.extend(ang_mtm.iter().cloned()),
+            _ => merged.push(proj.clone()),
+        }
+    }
+
+    merged
+}
+
+/// True if `a` and `b` are site projections with everything but `ang_mtm` equal.
+fn same_site_settings(a: &Projection, b: &Projection) -> bool {
+    match (a, b) {
+        (&Projection::Site { .. }, &Projection::Site { .. }) => {
+            let without_ang_mtm = |p: &Projection| match *p {
+                Projection::Site {
+                    ref site,
+                    zaxis,
+                    xaxis,
+                    radial,
+                    zona,
+                    ref spin,
+                    quant_dir,
+                    ref per_orbital,
+                    ..
+                } => (
+                    site.clone(),
+                    zaxis,
+                    xaxis,
+                    radial,
+                    zona,
+                    spin.clone(),
+                    quant_dir,
+                    per_orbital.clone(),
+                ),
+                Projection::Random => unreachable!(),
+            };
+            without_ang_mtm(a) == without_ang_mtm(b)
+        }
+        _ => false,
+    }
+}
+
+/// Number of orbitals given by `proj`, not counting spin. A projection onto a species
+/// gives orbitals centered on each atom in `positions` whose `site_label` is that species,
+/// so a label such as `Fe1` selects only the atoms carrying it. `Random` gives no
+/// orbitals.
+///
+/// Each site gives `AngularMomentum::num_orbitals` orbitals per entry of `ang_mtm`: 1, 3,
+/// 5 and 7 for the s, p, d and f shells, 2 through 6 for the hybrids sp through sp3d2,
+/// and one for each `mr` of an explicit orbital list.
+///
+/// This is the number of Wannier functions from `proj` only in a calculation without
+/// spinors; `count_wannier` applies the spin factor. Do not multiply by 2 again when
+/// combining the two.
+pub fn count_projection_orbitals(proj: &Projection, positions: &Positions) -> u64 {
+    match *proj {
+        Projection::Random => 0,
+        Projection::Site {
+            ref site,
+            ref ang_mtm,
+            ..
+        } => {
+            let num_sites = match *site {
+                ProjectionSite::Species(ref species) => positions
+                    .coordinates
+                    .iter()
+                    .filter(|c| c.site_label() == species)
+                    .count() as u64,
+                ProjectionSite::CenterCartesian(_) | ProjectionSite::CenterCrystal(_) => 1,
+            };
+            let orbitals_per_site: u64 = ang_mtm.iter().map(|l| l.num_orbitals()).sum();
+
+            num_sites * orbitals_per_site
+        }
+    }
+}
+
+/// Number of Wannier functions given by the explicit projections of `input`, which is
+/// the value `num_wann` must have if every Wannier function comes from a projection.
+///
+/// Each orbital counted by `count_projection_orbitals` gives two Wannier functions if
+/// `input.spinors` is true, or one if its projection selects a single spin channel.
+/// Without spinors, each orbital gives one Wannier function; a spin-polarized
+/// calculation Wannierizes each channel separately (see `spin`).
+pub fn count_wannier(input: &Input) -> u64 {
+    input
+        .projections
+        .iter()
+        .map(|p| {
+            let orbitals = count_projection_orbitals(p, &input.positions);
+            let channels = match *p {
+                Projection::Site {
+                    spin: Some(ref spin),
+                    ..
+                } if input.spinors => spin.num_channels(),
+                _ if input.spinors => 2,
+                _ => 1,
+            };
+            orbitals * channels
+        })
+        .sum()
+}
+
+/// Convert one-based band indices of a calculation without spinors to the indices of the
+/// corresponding spin-doubled bands of a spinor calculation, in which band `n` becomes
+/// bands `2n - 1` and `2n`. Use this to build `exclude_bands` for a spinor calculation; it
+/// is not applied automatically. Index 0, which does not name a band, is skipped.
+pub fn exclude_bands_for_spinor(indices: &[u64]) -> Vec<u64> {
+    indices
+        .iter()
+        .filter(|&&n| n > 0)
+        .flat_map(|&n| vec![2 * n - 1, 2 * n])
+        .collect()
+}
+
+/// A field whose value differs between two `Input`s, as found by `diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    /// Path to the field, such as `num_wann`, `disentanglement.dis_win_max` or
+    /// `projections[1].zaxis`.
+    pub keyword: String,
+    /// Value in the first input, or `none` if it is unset or absent there.
+    pub old: String,
+    /// Value in the second input, or `none` if it is unset or absent there.
+    pub new: String,
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.keyword, self.old, self.new)
+    }
+}
+
+/// The fields which differ between `a` and `b`. Nested structures such as
+/// `disentanglement`, `projections` and `positions` are compared field by field and
+/// element by element, so that each difference names the innermost field which changed;
+/// lists of plain values such as `exclude_bands` or a projection's `zaxis` are compared
+/// whole. Fields are listed in alphabetical order within each structure.
+pub fn diff(a: &Input, b: &Input) -> Vec<FieldDiff> {
+    let to_value = |input: &Input| {
+        serde_json::to_value(input).expect("Input serializes to JSON")
+    };
+    let mut diffs = Vec::new();
+    diff_values(String::new(), &to_value(a), &to_value(b), &mut diffs);
+    diffs
+}
+
+fn diff_values(
+    keyword: String,
+    a: &serde_json::Value,
+    b: &serde_json::Value,
+    diffs: &mut Vec<FieldDiff>,
+) {
+    use serde_json::Value;
+
+    if a == b {
+        return;
+    }
+
+    // The fields of an enum variant kept by both sides are compared without naming it; a
+    // change of variant is a change of the whole value.
+    if let (Some((a_name, a_fields)), Some((b_name, b_fields))) = (variant(a), variant(b)) {
+        if a_name == b_name {
+            diff_values(keyword, a_fields, b_fields, diffs);
+            return;
+        }
+    }
+
+    let null = Value::Null;
+    match (a, b) {
+        (&Value::Object(ref a_map), &Value::Object(ref b_map))
+            if variant(a).is_none() && variant(b).is_none() =>
+        {
+            let names: BTreeSet<&String> = a_map.keys().chain(b_map.keys()).collect();
+            for name in names {
+                let field = if keyword.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", keyword, name)
+                };
+                let a_value = a_map.get(name.as_str()).unwrap_or(&null);
+                let b_value = b_map.get(name.as_str()).unwrap_or(&null);
+                diff_values(field, a_value, b_value, diffs);
+            }
+        }
+        (&Value::Array(ref a_list), &Value::Array(ref b_list))
+            if a_list.iter().chain(b_list).any(|v| v.is_object() || v.is_array()) =>
+        {
+            for i in 0..a_list.len().max(b_list.len()) {
+                let a_value = a_list.get(i).unwrap_or(&null);
+                let b_value = b_list.get(i).unwrap_or(&null);
+                diff_values(format!("{}[{}]", keyword, i), a_value, b_value, diffs);
+            }
+        }
+        _ => diffs.push(FieldDiff {
+            keyword,
+            old: display_value(a),
+            new: display_value(b),
+        }),
+    }
+}
+
+/// The name and fields of `value` if it is an enum variant with fields, which serde
+/// writes as `{ "Variant": fields }`. Struct fields are never capitalized.
+fn variant(value: &serde_json::Value) -> Option<(&String, &serde_json::Value)> {
+    match *value {
+        serde_json::Value::Object(ref map) if map.len() == 1 => map.iter()
+            .next()
+            .filter(|&(name, _)| name.chars().next().map_or(false, char::is_uppercase)),
+        _ => None,
+    }
+}
+
+/// `value` as it would be read in a diff: strings without quotes, and `none` for null.
+fn display_value(value: &serde_json::Value) -> String {
+    match *value {
+        serde_json::Value::Null => String::from("none"),
+        serde_json::Value::String(ref s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+/// Largest radial function index `r` accepted by Wannier90; indices start at 1.
+pub const MAX_RADIAL: u64 = 3;
+
+/// Range of `optimisation` levels accepted by Wannier90.
+pub const MIN_OPTIMISATION: i64 = -1;
+pub const MAX_OPTIMISATION: i64 = 3;
+
+/// Largest allowed cosine of the angle between the `zaxis` and `xaxis` of a projection.
+pub const AXIS_ORTHOGONALITY_TOL: f64 = 1e-6;
+
+/// Check `input` for settings which Wannier90 would reject or which are inconsistent.
+/// If there are none, returns the warnings given by `lint`.
+pub fn validate(input: &Input) -> Result<Vec<Warning>, ErrorList> {
     let mut errs = Vec::new();
 
-    // TODO: Check that all atom-centered projections have Species that exist in the coordinates.
+    // Positions in crystal coordinates and the reciprocal lattice require an invertible cell.
+    if invert_matrix(input.unit_cell_cart.cell).is_none() {
+        errs.push(Error::SingularCell);
+    }
+
+    // Check that each species projection is centered on at least one atom. A labelled atom
+    // is matched only by its label.
+    for (index, proj) in input.projections.iter().enumerate() {
+        if let Projection::Site {
+            site: ProjectionSite::Species(ref species),
+            ..
+        } = *proj
+        {
+            if !input
+                .positions
+                .coordinates
+                .iter()
+                .any(|c| c.site_label() == species)
+            {
+                errs.push(Error::UnknownProjectionSpecies {
+                    index,
+                    species: species.clone(),
+                });
+            }
+        }
+    }
 
     // Check that `Random` does not appear more than once in the list of projections.
     let random_count = input
@@ -115,24 +1248,931 @@ pub fn validate(input: &Input) -> Result<(), ErrorList> {
         errs.push(Error::RandomCount);
     }
 
-    // TODO: Check that the number of projections given is compatible with `num_wann`, or that
-    // `Random` is in the list of projections.
-    //if random_count == 0 {
-    //
-    //}
+    if let Some(ref exclude_bands) = input.exclude_bands {
+        if exclude_bands.iter().any(|&n| n < 1 || n > input.num_bands) {
+            errs.push(Error::ExcludeBandsRange);
+        }
+        if exclude_bands.windows(2).any(|w| w[0] >= w[1]) {
+            errs.push(Error::ExcludeBandsOrder);
+        }
+    }
+
+    // Projectabilities lie in [0, 1]; an unset bound is taken as the end of that range.
+    if let Some(ref dis) = input.disentanglement {
+        let min = dis.dis_proj_min.unwrap_or(0.0);
+        let max = dis.dis_proj_max.unwrap_or(1.0);
+        if min < 0.0 || max > 1.0 || min >= max {
+            errs.push(Error::DisProjRange);
+        }
+    }
+
+    if let Some(spheres) = input
+        .disentanglement
+        .as_ref()
+        .and_then(|dis| dis.dis_spheres.as_ref())
+    {
+        if spheres.dis_spheres_num != spheres.spheres.len() as u64 {
+            errs.push(Error::DisSpheresNum);
+        }
+        if spheres.spheres.iter().any(|sphere| sphere.radius <= 0.0) {
+            errs.push(Error::DisSpheresRadius);
+        }
+        if let Some(first_wann) = spheres.dis_spheres_first_wann {
+            if first_wann < 1 || first_wann + input.num_wann > input.num_bands + 1 {
+                errs.push(Error::DisSpheresFirstWann);
+            }
+        }
+    }
+
+    if input.gamma_only == Some(true) && input.kpoints != KPointMesh::Uniform([1, 1, 1]) {
+        errs.push(Error::GammaOnlyGrid);
+    }
+
+    if input.kmesh_tol.map_or(false, |tol| tol <= 0.0) {
+        errs.push(Error::KmeshTol);
+    }
+    if input.search_shells.map_or(false, |n| n < 12) {
+        errs.push(Error::SearchShells);
+    }
+
+    // Wannier90 requires `mp_grid` even when the k-points are given explicitly.
+    let non_finite = match input.kpoints {
+        KPointMesh::Explicit(ref ks) => ks.iter().position(|k| k.iter().any(|x| !x.is_finite())),
+        _ => None,
+    };
+    match (non_finite, input.kpoints.mp_grid()) {
+        (Some(index), _) => errs.push(Error::NonFiniteKPoint { index }),
+        (None, Some(nk)) => {
+            if nk.iter().any(|&n| n == 0) {
+                errs.push(Error::InvalidKpointGrid);
+            }
+        }
+        (None, None) => errs.push(Error::ExplicitKPointGrid),
+    }
+
+    // Projection axes must be nonzero, and the x-axis must be perpendicular to the z-axis.
+    for (index, proj) in input.projections.iter().enumerate() {
+        if let Projection::Site {
+            zaxis,
+            xaxis,
+            ref ang_mtm,
+            radial,
+            zona,
+            ref per_orbital,
+            ref spin,
+            quant_dir,
+            ..
+        } = *proj
+        {
+            if ang_mtm.iter().any(|l| !l.is_valid()) {
+                errs.push(Error::InvalidOrbitals { index });
+            }
+
+            let radials = per_orbital.iter().map(|o| o.radial).chain(Some(radial));
+            if radials.flatten().any(|r| r < 1 || r > MAX_RADIAL) {
+                errs.push(Error::InvalidRadial { index });
+            }
+            let zonas = per_orbital.iter().map(|o| o.zona).chain(Some(zona));
+            if zonas.flatten().any(|z| z <= 0.0) {
+                errs.push(Error::InvalidZona { index });
+            }
+
+            if per_orbital.iter().any(|o| !ang_mtm.contains(&o.ang_mtm)) {
+                errs.push(Error::OrbitalRadialNotInProjection { index });
+            }
+
+            if !input.spinors && (spin.is_some() || quant_dir.is_some()) {
+                errs.push(Error::SpinProjectionWithoutSpinors { index });
+            }
+
+            let zero = |v: Option<[f64; 3]>| v.map_or(false, |v| norm(v) == 0.0);
+            if zero(zaxis) || zero(xaxis) {
+                errs.push(Error::ZeroAxis { index });
+            } else if let (Some(z), Some(x)) = (zaxis, xaxis) {
+                let cos = dot(z, x) / (norm(z) * norm(x));
+                if cos.abs() > AXIS_ORTHOGONALITY_TOL {
+                    errs.push(Error::NonOrthogonalAxes { index });
+                }
+            }
+        }
+    }
+
+    // An initial guess is required, either from projections or from the Bloch phases.
+    if input.projections.len() == 0 && input.use_bloch_phases != Some(true) {
+        errs.push(match input.mlwf_iteration_mode {
+            MLWFIterationMode::MLWF { .. } => Error::NoProjections,
+            MLWFIterationMode::ProjectionOnly => Error::NoProjectionsProjectionOnly,
+        });
+    }
+
+    // Guiding centres are taken from explicit projections.
+    if input.guiding_centres == Some(true)
+        && input.projections.iter().all(|p| *p == Projection::Random)
+    {
+        errs.push(Error::GuidingCentresProjections);
+    }
+
+    if input.guiding_centres != Some(true)
+        && (input.num_guide_cycles.is_some() || input.num_no_guide_iter.is_some())
+    {
+        errs.push(Error::GuideCyclesWithoutGuidingCentres);
+    }
+
+    if let Some(ref slwf) = input.selective_localization {
+        if slwf.slwf_num < 1 || slwf.slwf_num > input.num_wann {
+            errs.push(Error::SlwfNum);
+        }
+        if slwf.slwf_lambda.map_or(false, |lambda| lambda < 0.0) {
+            errs.push(Error::SlwfLambda);
+        }
+    }
+
+    if let Some(symmetrize_eps) = input.symmetrize_eps {
+        if symmetrize_eps <= 0.0 {
+            errs.push(Error::SymmetrizeEps);
+        }
+        if input.site_symmetry != Some(true) {
+            errs.push(Error::SymmetrizeEpsWithoutSiteSymmetry);
+        }
+    }
+
+    if input.num_print_cycles == Some(0) {
+        errs.push(Error::NumPrintCycles);
+    }
+    if input.num_dump_cycles == Some(0) {
+        errs.push(Error::NumDumpCycles);
+    }
+    if input
+        .optimisation
+        .map_or(false, |level| level < MIN_OPTIMISATION || level > MAX_OPTIMISATION)
+    {
+        errs.push(Error::Optimisation);
+    }
+
+    if input.bands_plot == Some(true) {
+        if input.kpoint_path.is_none() {
+            errs.push(Error::BandsPlotWithoutKPath);
+        }
+    } else if input.bands_plot_options.is_some() {
+        errs.push(Error::BandsPlotOptionsWithoutBandsPlot);
+    }
+
+    if input.geninterp != Some(true)
+        && (input.geninterp_alsofirstder.is_some() || input.geninterp_single_file.is_some())
+    {
+        errs.push(Error::GeninterpOptionsWithoutGeninterp);
+    }
+
+    if let Some(project) = input.bands_plot_options.as_ref().and_then(|o| o.project.as_ref()) {
+        if project.iter().any(|&n| n < 1 || n > input.num_wann) {
+            errs.push(Error::BandsPlotProjectRange);
+        }
+        if project.windows(2).any(|w| w[0] >= w[1]) {
+            errs.push(Error::BandsPlotProjectOrder);
+        }
+    }
+
+    if let Some(ref dos) = input.dos {
+        if dos.dos_energy_min >= dos.dos_energy_max {
+            errs.push(Error::DosEnergyRange);
+        }
+        if dos.dos_energy_step <= 0.0 {
+            errs.push(Error::DosEnergyStep);
+        }
+        if dos.dos_kmesh.iter().any(|&n| n == 0) {
+            errs.push(Error::DosKmesh);
+        }
+    }
+
+    if let Some(ref berry) = input.berry {
+        if berry.berry_kmesh.iter().any(|&n| n == 0) {
+            errs.push(Error::BerryKmesh);
+        }
+        if berry.berry_curv_adpt_kmesh == Some(0) {
+            errs.push(Error::BerryCurvAdptKmesh);
+        }
+    }
+
+    if let Some(ref boltz) = input.boltzwann {
+        if boltz.boltz_kmesh.iter().any(|&n| n == 0) {
+            errs.push(Error::BoltzKmesh);
+        }
+        if boltz.boltz_tdf_energy_step.map_or(false, |step| step <= 0.0) {
+            errs.push(Error::BoltzTdfEnergyStep);
+        }
+        if boltz.boltz_mu_min > boltz.boltz_mu_max {
+            errs.push(Error::BoltzMuRange);
+        }
+        if boltz.boltz_mu_step <= 0.0 {
+            errs.push(Error::BoltzMuStep);
+        }
+        if boltz.boltz_temp_min > boltz.boltz_temp_max {
+            errs.push(Error::BoltzTempRange);
+        }
+        if boltz.boltz_temp_step <= 0.0 {
+            errs.push(Error::BoltzTempStep);
+        }
+        if boltz.boltz_relax_time.map_or(false, |t| t <= 0.0) {
+            errs.push(Error::BoltzRelaxTime);
+        }
+    }
+
+    // Spin channels are only defined for collinear calculations.
+    if input.spin.is_some() && input.spinors {
+        errs.push(Error::SpinWithSpinors);
+    }
+
+    if input.spin_axis_polar.is_some() || input.spin_axis_azimuth.is_some() {
+        if !input.spinors {
+            errs.push(Error::SpinAxisWithoutSpinors);
+        }
+        if input
+            .spin_axis_polar
+            .map_or(false, |theta| theta < 0.0 || theta > 180.0)
+        {
+            errs.push(Error::SpinAxisPolar);
+        }
+        if input
+            .spin_axis_azimuth
+            .map_or(false, |phi| phi < 0.0 || phi > 360.0)
+        {
+            errs.push(Error::SpinAxisAzimuth);
+        }
+    }
+
+    // Each Wannier function requires at least one band.
+    let count = count_wannier(input);
+    if count > input.num_bands {
+        errs.push(Error::ProjectionsExceedNumBands);
+    }
+
+    // Without `Random` to fill the remainder, the projections must give exactly `num_wann`
+    // Wannier functions. Projections are not used with `use_bloch_phases`.
+    if random_count == 0
+        && input.projections.len() > 0
+        && input.use_bloch_phases != Some(true)
+        && count != input.num_wann
+    {
+        errs.push(Error::NumWannProjections {
+            num_wann: input.num_wann,
+            count,
+        });
+    }
 
     if errs.len() == 0 {
-        Ok(())
+        Ok(lint(input))
     } else {
         Err(ErrorList { errs })
     }
 }
 
-#[derive(Fail, Debug)]
+/// Read an `Input` from a TOML file.
+pub fn from_toml_file<P: AsRef<Path>>(file_path: P) -> Result<Input, ConfigError> {
+    let mut contents = String::new();
+    File::open(file_path)?.read_to_string(&mut contents)?;
+
+    Ok(toml::from_str(&contents)?)
+}
+
+/// Read an `Input` from a JSON file.
+pub fn from_json_file<P: AsRef<Path>>(file_path: P) -> Result<Input, ConfigError> {
+    let file = File::open(file_path)?;
+
+    Ok(serde_json::from_reader(file)?)
+}
+
+/// Write `input` to a TOML file.
+pub fn to_toml_file<P: AsRef<Path>>(input: &Input, file_path: P) -> Result<(), ConfigError> {
+    let contents = toml::to_string_pretty(input)?;
+
+    let mut file = File::create(file_path)?;
+    file.write_all(contents.as_bytes())?;
+
+    Ok(())
+}
+
+/// Write `input` to a JSON file.
+pub fn to_json_file<P: AsRef<Path>>(input: &Input, file_path: P) -> Result<(), ConfigError> {
+    let file = File::create(file_path)?;
+    serde_json::to_writer_pretty(file, input)?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "{}", e),
+            ConfigError::Json(ref e) => write!(f, "{}", e),
+            ConfigError::TomlDe(ref e) => write!(f, "{}", e),
+            ConfigError::TomlSer(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl StdError for ConfigError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ConfigError::Io(ref e) => Some(e),
+            ConfigError::Json(ref e) => Some(e),
+            ConfigError::TomlDe(ref e) => Some(e),
+            ConfigError::TomlSer(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> ConfigError {
+        ConfigError::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> ConfigError {
+        ConfigError::TomlDe(e)
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError {
+    fn from(e: toml::ser::Error) -> ConfigError {
+        ConfigError::TomlSer(e)
+    }
+}
+
+/// `dis_num_iter` above which `lint` warns that disentanglement may be slow to converge.
+pub const LARGE_DIS_NUM_ITER: u64 = 10000;
+
+/// Ratio of `num_bands` to `num_wann` above which `lint` warns about excess bands.
+pub const LARGE_NUM_BANDS_RATIO: u64 = 4;
+
+/// Default `LintOptions::projection_center_margin`.
+pub const DEFAULT_PROJECTION_CENTER_MARGIN: f64 = 0.5;
+
+/// Tolerances used by `lint_with_options`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintOptions {
+    /// Distance in lattice coordinates by which a projection center may lie outside the
+    /// unit cell `[0, 1)` along each lattice vector without a warning.
+    pub projection_center_margin: f64,
+    /// Allow Cartesian positions in different units from `unit_cell_cart` without a
+    /// warning.
+    pub allow_mixed_units: bool,
+}
+
+impl Default for LintOptions {
+    fn default() -> LintOptions {
+        LintOptions {
+            projection_center_margin: DEFAULT_PROJECTION_CENTER_MARGIN,
+            allow_mixed_units: false,
+        }
+    }
+}
+
+/// Check `input` for settings which are valid but likely to be unintended, using the
+/// default `LintOptions`.
+/// Unlike the errors returned by `validate`, these do not prevent generating an input file.
+pub fn lint(input: &Input) -> Vec<Warning> {
+    lint_with_options(input, &LintOptions::default())
+}
+
+/// Check `input` for settings which are valid but likely to be unintended, as done by
+/// `lint`, with the given tolerances.
+pub fn lint_with_options(input: &Input, options: &LintOptions) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    // Wannier90 reads the units of the cell and positions separately, so a mismatch is
+    // accepted but usually means the wrong units were given for one of them. Lattice
+    // coordinates do not have units.
+    if let Some(units) = input.positions.coordinate_type.units() {
+        if units != input.unit_cell_cart.units && !options.allow_mixed_units {
+            warnings.push(Warning::PositionUnitsMismatch);
+        }
+    }
+
+    // Centers far from the cell are usually a mistake in the coordinates, but may be
+    // intended, for example to follow a molecule which crosses the cell boundary.
+    // Cartesian centers are given in `projection_units`, which default to Angstrom.
+    let projection_cell = input
+        .unit_cell_cart
+        .to_units(input.projection_units.clone().unwrap_or(LatticeUnits::Angstrom));
+    let (low, high) = (
+        -options.projection_center_margin,
+        1.0 + options.projection_center_margin,
+    );
+    for (index, proj) in input.projections.iter().enumerate() {
+        let center = match *proj {
+            Projection::Site { ref site, .. } => match site.to_crystal(&projection_cell) {
+                Ok(ProjectionSite::CenterCrystal(r)) => r,
+                _ => continue,
+            },
+            Projection::Random => continue,
+        };
+        if center.iter().any(|&x| x < low || x > high) {
+            warnings.push(Warning::ProjectionCenterOutsideCell { index });
+        }
+    }
+
+    // Disentanglement is a no-op if there are no bands to disentangle.
+    if input.disentanglement.is_some() && input.num_bands == input.num_wann {
+        warnings.push(Warning::UnusedDisentanglement);
+    }
+
+    // Needing this many iterations usually means the energy windows are poorly chosen.
+    if let Some(ref dis) = input.disentanglement {
+        if dis.dis_num_iter > LARGE_DIS_NUM_ITER {
+            warnings.push(Warning::LargeDisNumIter {
+                dis_num_iter: dis.dis_num_iter,
+                max: LARGE_DIS_NUM_ITER,
+            });
+        }
+    }
+
+    // Bands far above the target manifold slow down disentanglement without improving it.
+    if input.num_bands > LARGE_NUM_BANDS_RATIO * input.num_wann {
+        warnings.push(Warning::ManyExtraBands {
+            num_bands: input.num_bands,
+            num_wann: input.num_wann,
+            ratio: LARGE_NUM_BANDS_RATIO,
+        });
+    }
+
+    // seedname_tb.dat already contains the Hamiltonian written to seedname_hr.dat.
+    if input.write_tb == Some(true) && input.write_hr == Some(true) {
+        warnings.push(Warning::RedundantWriteHr);
+    }
+
+    warnings
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    UnusedDisentanglement,
+    RedundantWriteHr,
+    LargeDisNumIter { dis_num_iter: u64, max: u64 },
+    ManyExtraBands { num_bands: u64, num_wann: u64, ratio: u64 },
+    ProjectionCenterOutsideCell { index: usize },
+    PositionUnitsMismatch,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::UnusedDisentanglement => write!(
+                f,
+                "`disentanglement` is given but has no effect since `num_bands == num_wann`."
+            ),
+            Warning::RedundantWriteHr => {
+                write!(f, "`write_hr` is redundant when `write_tb` is given.")
+            }
+            Warning::LargeDisNumIter { dis_num_iter, max } => {
+                write!(f, "`dis_num_iter` = {} is larger than {}.", dis_num_iter, max)
+            }
+            Warning::ManyExtraBands {
+                num_bands,
+                num_wann,
+                ratio,
+            } => write!(
+                f,
+                "`num_bands` = {} is more than {} times `num_wann` = {}.",
+                num_bands, ratio, num_wann
+            ),
+            Warning::ProjectionCenterOutsideCell { index } => {
+                write!(f, "Projection {} is centered far outside the unit cell.", index)
+            }
+            Warning::PositionUnitsMismatch => write!(
+                f,
+                "Cartesian positions are given in different units from `unit_cell_cart`."
+            ),
+        }
+    }
+}
+
+impl StdError for Warning {}
+
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "`Random` may appear at most once in the list of projections.")] RandomCount,
-    //#[fail(display = "Number of projections is incompatible with `num_wann`.")]
-    //ProjectionNumber,
+    RandomCount,
+    NoProjections,
+    NoProjectionsProjectionOnly,
+    ProjectionsExceedNumBands,
+    NonOrthogonalAxes { index: usize },
+    ZeroAxis { index: usize },
+    UnknownProjectionSpecies { index: usize, species: String },
+    UnknownSpecies(String),
+    OrbitalRadialNotInProjection { index: usize },
+    SpinProjectionWithoutSpinors { index: usize },
+    InvalidOrbitals { index: usize },
+    InvalidRadial { index: usize },
+    InvalidZona { index: usize },
+    NumWannProjections { num_wann: u64, count: u64 },
+    SingularCell,
+    ExcludeBandsRange,
+    ExcludeBandsOrder,
+    SpinWithSpinors,
+    SpinAxisWithoutSpinors,
+    SpinAxisPolar,
+    SpinAxisAzimuth,
+    DisSpheresNum,
+    DisSpheresRadius,
+    DisSpheresFirstWann,
+    DisProjRange,
+    GammaOnlyGrid,
+    ExplicitKPointGrid,
+    NonFiniteKPoint { index: usize },
+    InvalidKpointGrid,
+    KmeshTol,
+    SearchShells,
+    GuidingCentresProjections,
+    GuideCyclesWithoutGuidingCentres,
+    SlwfNum,
+    SlwfLambda,
+    SymmetrizeEps,
+    SymmetrizeEpsWithoutSiteSymmetry,
+    NumPrintCycles,
+    NumDumpCycles,
+    Optimisation,
+    BandsPlotWithoutKPath,
+    BandsPlotOptionsWithoutBandsPlot,
+    BandsPlotProjectRange,
+    BandsPlotProjectOrder,
+    DosEnergyRange,
+    DosEnergyStep,
+    DosKmesh,
+    BerryKmesh,
+    BerryCurvAdptKmesh,
+    BoltzKmesh,
+    BoltzTdfEnergyStep,
+    BoltzMuRange,
+    BoltzMuStep,
+    BoltzTempRange,
+    BoltzTempStep,
+    BoltzRelaxTime,
+    GeninterpOptionsWithoutGeninterp,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::RandomCount => {
+                write!(f, "`Random` may appear at most once in the list of projections.")
+            }
+            Error::NoProjections => write!(
+                f,
+                "`projections` is empty: without `use_bloch_phases`, the MLWF \
+                    iterations have no initial guess."
+            ),
+            Error::NoProjectionsProjectionOnly => write!(
+                f,
+                "`projections` is empty: `ProjectionOnly` without `use_bloch_phases` \
+                    has no projections to give the Wannier functions."
+            ),
+            Error::ProjectionsExceedNumBands => {
+                write!(f, "Projections give more Wannier functions than `num_bands`.")
+            }
+            Error::NonOrthogonalAxes { index } => write!(
+                f,
+                "Projection {}: `zaxis` and `xaxis` must be orthogonal.",
+                index
+            ),
+            Error::ZeroAxis { index } => write!(f, "Projection {}: axes must be nonzero.", index),
+            Error::UnknownProjectionSpecies { index, ref species } => write!(
+                f,
+                "Projection {}: species `{}` does not match the species or label of an atom.",
+                index, species
+            ),
+            Error::UnknownSpecies(ref species) => write!(
+                f,
+                "Species `{}` does not match the species or label of an atom.",
+                species
+            ),
+            Error::OrbitalRadialNotInProjection { index } => write!(
+                f,
+                "Projection {}: `per_orbital` entries must match an angular momentum of the \
+                 projection.",
+                index
+            ),
+            Error::SpinProjectionWithoutSpinors { index } => write!(
+                f,
+                "Projection {}: spin and quantisation axis require `spinors`.",
+                index
+            ),
+            Error::InvalidOrbitals { index } => write!(
+                f,
+                "Projection {}: each `mr` must be a distinct orbital of its `l`.",
+                index
+            ),
+            Error::InvalidRadial { index } => write!(
+                f,
+                "Projection {}: `radial` must be in the range 1..={}.",
+                index, MAX_RADIAL
+            ),
+            Error::InvalidZona { index } => {
+                write!(f, "Projection {}: `zona` must be positive.", index)
+            }
+            Error::NumWannProjections { num_wann, count } => write!(
+                f,
+                "Projections give {} Wannier functions, but `num_wann` is {}.",
+                count, num_wann
+            ),
+            Error::SingularCell => write!(f, "Lattice vectors are linearly dependent."),
+            Error::ExcludeBandsRange => {
+                write!(f, "`exclude_bands` indices must be in the range 1..=num_bands.")
+            }
+            Error::ExcludeBandsOrder => {
+                write!(f, "`exclude_bands` indices must be sorted and unique.")
+            }
+            Error::SpinWithSpinors => write!(f, "`spin` may not be given when `spinors` is true."),
+            Error::SpinAxisWithoutSpinors => {
+                write!(f, "`spin_axis_polar` and `spin_axis_azimuth` require `spinors`.")
+            }
+            Error::SpinAxisPolar => write!(f, "`spin_axis_polar` must be in the range [0, 180]."),
+            Error::SpinAxisAzimuth => {
+                write!(f, "`spin_axis_azimuth` must be in the range [0, 360].")
+            }
+            Error::DisSpheresNum => {
+                write!(f, "`dis_spheres_num` must equal the number of lines in `dis_spheres`.")
+            }
+            Error::DisSpheresRadius => write!(f, "`dis_spheres` radii must be positive."),
+            Error::DisSpheresFirstWann => {
+                write!(f, "`dis_spheres_first_wann` must be in the range 1..=num_bands-num_wann+1.")
+            }
+            Error::DisProjRange => write!(
+                f,
+                "`dis_proj_min` and `dis_proj_max` must satisfy \
+                    0 <= dis_proj_min < dis_proj_max <= 1."
+            ),
+            Error::GammaOnlyGrid => {
+                write!(f, "`gamma_only` requires an unshifted 1x1x1 k-point grid.")
+            }
+            Error::ExplicitKPointGrid => write!(f, "Explicit k-points do not form a regular grid."),
+            Error::NonFiniteKPoint { index } => {
+                write!(f, "K-point {} has a non-finite component.", index)
+            }
+            Error::InvalidKpointGrid => {
+                write!(f, "Each dimension of the k-point grid must be at least 1.")
+            }
+            Error::KmeshTol => write!(f, "`kmesh_tol` must be positive."),
+            Error::SearchShells => write!(f, "`search_shells` must be at least 12."),
+            Error::GuidingCentresProjections => {
+                write!(f, "`guiding_centres` requires at least one explicit projection.")
+            }
+            Error::GuideCyclesWithoutGuidingCentres => {
+                write!(f, "`num_guide_cycles` and `num_no_guide_iter` require `guiding_centres`.")
+            }
+            Error::SlwfNum => write!(f, "`slwf_num` must be in the range 1..=num_wann."),
+            Error::SlwfLambda => write!(f, "`slwf_lambda` must not be negative."),
+            Error::SymmetrizeEps => write!(f, "`symmetrize_eps` must be positive."),
+            Error::SymmetrizeEpsWithoutSiteSymmetry => {
+                write!(f, "`symmetrize_eps` requires `site_symmetry`.")
+            }
+            Error::NumPrintCycles => write!(f, "`num_print_cycles` must be positive."),
+            Error::NumDumpCycles => write!(f, "`num_dump_cycles` must be positive."),
+            Error::Optimisation => write!(
+                f,
+                "`optimisation` must be in the range {}..={}.",
+                MIN_OPTIMISATION, MAX_OPTIMISATION
+            ),
+            Error::BandsPlotWithoutKPath => write!(f, "`bands_plot` requires `kpoint_path`."),
+            Error::BandsPlotOptionsWithoutBandsPlot => {
+                write!(f, "`bands_plot_options` requires `bands_plot`.")
+            }
+            Error::BandsPlotProjectRange => {
+                write!(f, "`bands_plot_project` indices must be in the range 1..=num_wann.")
+            }
+            Error::BandsPlotProjectOrder => {
+                write!(f, "`bands_plot_project` indices must be sorted and unique.")
+            }
+            Error::DosEnergyRange => {
+                write!(f, "`dos_energy_min` must be less than `dos_energy_max`.")
+            }
+            Error::DosEnergyStep => write!(f, "`dos_energy_step` must be positive."),
+            Error::DosKmesh => write!(f, "`dos_kmesh` entries must be positive."),
+            Error::BerryKmesh => write!(f, "`berry_kmesh` entries must be positive."),
+            Error::BerryCurvAdptKmesh => write!(f, "`berry_curv_adpt_kmesh` must be positive."),
+            Error::BoltzKmesh => write!(f, "`boltz_kmesh` entries must be positive."),
+            Error::BoltzTdfEnergyStep => write!(f, "`boltz_tdf_energy_step` must be positive."),
+            Error::BoltzMuRange => {
+                write!(f, "`boltz_mu_min` must not be greater than `boltz_mu_max`.")
+            }
+            Error::BoltzMuStep => write!(f, "`boltz_mu_step` must be positive."),
+            Error::BoltzTempRange => {
+                write!(f, "`boltz_temp_min` must not be greater than `boltz_temp_max`.")
+            }
+            Error::BoltzTempStep => write!(f, "`boltz_temp_step` must be positive."),
+            Error::BoltzRelaxTime => write!(f, "`boltz_relax_time` must be positive."),
+            Error::GeninterpOptionsWithoutGeninterp => write!(
+                f,
+                "`geninterp_alsofirstder` and `geninterp_single_file` require `geninterp`."
+            ),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl Error {
+    /// Process exit code identifying the class of this error:
+    ///
+    /// * 2: projections
+    /// * 3: geometry (unit cell and atomic positions)
+    /// * 4: k-points
+    /// * 5: band, spin and disentanglement settings
+    /// * 6: localization settings
+    /// * 7: postw90 settings
+    /// * 8: plotting settings
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::RandomCount
+            | Error::NoProjections
+            | Error::NoProjectionsProjectionOnly
+            | Error::ProjectionsExceedNumBands
+            | Error::NonOrthogonalAxes { .. }
+            | Error::ZeroAxis { .. }
+            | Error::UnknownProjectionSpecies { .. }
+            | Error::UnknownSpecies(_)
+            | Error::OrbitalRadialNotInProjection { .. }
+            | Error::SpinProjectionWithoutSpinors { .. }
+            | Error::InvalidOrbitals { .. }
+            | Error::InvalidRadial { .. }
+            | Error::InvalidZona { .. }
+            | Error::NumWannProjections { .. }
+            | Error::GuidingCentresProjections => 2,
+            Error::SingularCell => 3,
+            Error::GammaOnlyGrid
+            | Error::ExplicitKPointGrid
+            | Error::NonFiniteKPoint { .. }
+            | Error::InvalidKpointGrid
+            | Error::KmeshTol
+            | Error::SearchShells => 4,
+            Error::ExcludeBandsRange
+            | Error::ExcludeBandsOrder
+            | Error::SpinWithSpinors
+            | Error::SpinAxisWithoutSpinors
+            | Error::SpinAxisPolar
+            | Error::SpinAxisAzimuth
+            | Error::DisSpheresNum
+            | Error::DisSpheresRadius
+            | Error::DisSpheresFirstWann
+            | Error::DisProjRange => 5,
+            Error::GuideCyclesWithoutGuidingCentres
+            | Error::SlwfNum
+            | Error::SlwfLambda
+            | Error::SymmetrizeEps
+            | Error::SymmetrizeEpsWithoutSiteSymmetry
+            | Error::NumPrintCycles
+            | Error::NumDumpCycles
+            | Error::Optimisation => 6,
+            Error::DosEnergyRange
+            | Error::DosEnergyStep
+            | Error::DosKmesh
+            | Error::BerryKmesh
+            | Error::BerryCurvAdptKmesh
+            | Error::BoltzKmesh
+            | Error::BoltzTdfEnergyStep
+            | Error::BoltzMuRange
+            | Error::BoltzMuStep
+            | Error::BoltzTempRange
+            | Error::BoltzTempStep
+            | Error::BoltzRelaxTime
+            | Error::GeninterpOptionsWithoutGeninterp => 7,
+            Error::BandsPlotWithoutKPath
+            | Error::BandsPlotOptionsWithoutBandsPlot
+            | Error::BandsPlotProjectRange
+            | Error::BandsPlotProjectOrder => 8,
+        }
+    }
+
+    /// Field of the configuration which this error concerns, as named in a TOML or JSON
+    /// description of an `Input`, or `None` if the error does not concern a single field.
+    pub fn keyword(&self) -> Option<&'static str> {
+        Some(match *self {
+            Error::RandomCount
+            | Error::NoProjections
+            | Error::NoProjectionsProjectionOnly
+            | Error::NonOrthogonalAxes { .. }
+            | Error::ZeroAxis { .. }
+            | Error::UnknownProjectionSpecies { .. }
+            | Error::OrbitalRadialNotInProjection { .. }
+            | Error::SpinProjectionWithoutSpinors { .. }
+            | Error::InvalidOrbitals { .. }
+            | Error::InvalidRadial { .. }
+            | Error::InvalidZona { .. } => "projections",
+            Error::UnknownSpecies(_) => return None,
+            Error::ProjectionsExceedNumBands => "num_bands",
+            Error::NumWannProjections { .. } => "num_wann",
+            Error::SingularCell => "unit_cell_cart",
+            Error::ExcludeBandsRange | Error::ExcludeBandsOrder => "exclude_bands",
+            Error::SpinWithSpinors => "spin",
+            Error::SpinAxisWithoutSpinors | Error::SpinAxisPolar => "spin_axis_polar",
+            Error::SpinAxisAzimuth => "spin_axis_azimuth",
+            Error::DisSpheresNum => "dis_spheres_num",
+            Error::DisSpheresRadius => "dis_spheres",
+            Error::DisSpheresFirstWann => "dis_spheres_first_wann",
+            Error::DisProjRange => "dis_proj_min",
+            Error::GammaOnlyGrid => "gamma_only",
+            Error::ExplicitKPointGrid
+            | Error::NonFiniteKPoint { .. }
+            | Error::InvalidKpointGrid => "kpoints",
+            Error::KmeshTol => "kmesh_tol",
+            Error::SearchShells => "search_shells",
+            Error::GuidingCentresProjections => "guiding_centres",
+            Error::GuideCyclesWithoutGuidingCentres => "num_guide_cycles",
+            Error::SlwfNum => "slwf_num",
+            Error::SlwfLambda => "slwf_lambda",
+            Error::SymmetrizeEps | Error::SymmetrizeEpsWithoutSiteSymmetry => "symmetrize_eps",
+            Error::NumPrintCycles => "num_print_cycles",
+            Error::NumDumpCycles => "num_dump_cycles",
+            Error::Optimisation => "optimisation",
+            Error::DosEnergyRange => "dos_energy_min",
+            Error::DosEnergyStep => "dos_energy_step",
+            Error::DosKmesh => "dos_kmesh",
+            Error::BerryKmesh => "berry_kmesh",
+            Error::BerryCurvAdptKmesh => "berry_curv_adpt_kmesh",
+            Error::BoltzKmesh => "boltz_kmesh",
+            Error::BoltzTdfEnergyStep => "boltz_tdf_energy_step",
+            Error::BoltzMuRange => "boltz_mu_min",
+            Error::BoltzMuStep => "boltz_mu_step",
+            Error::BoltzTempRange => "boltz_temp_min",
+            Error::BoltzTempStep => "boltz_temp_step",
+            Error::BoltzRelaxTime => "boltz_relax_time",
+            Error::GeninterpOptionsWithoutGeninterp => "geninterp_alsofirstder",
+            Error::BandsPlotWithoutKPath => "bands_plot",
+            Error::BandsPlotOptionsWithoutBandsPlot => "bands_plot_options",
+            Error::BandsPlotProjectRange | Error::BandsPlotProjectOrder => "project",
+        })
+    }
 }
 
 pub type ErrorList = ::qe::error::ErrorList<Error>;
+
+/// Cartesian position of the point with lattice coordinates `r`, where the rows of `lat`
+/// are the lattice vectors.
+fn crystal_to_cartesian(lat: [[f64; 3]; 3], r: [f64; 3]) -> [f64; 3] {
+    let mut cart = [0.0; 3];
+    for j in 0..3 {
+        for i in 0..3 {
+            cart[j] += r[i] * lat[i][j];
+        }
+    }
+    cart
+}
+
+/// Lattice coordinates of the Cartesian point `r`, where `lat_inv` is the inverse of
+/// the matrix whose rows are the lattice vectors.
+fn cartesian_to_crystal(lat_inv: [[f64; 3]; 3], r: [f64; 3]) -> [f64; 3] {
+    let mut frac = [0.0; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            frac[i] += r[j] * lat_inv[j][i];
+        }
+    }
+    frac
+}
+
+/// Inverse of the 3x3 matrix `m`, or `None` if `m` is singular.
+fn invert_matrix(m: [[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+
+    let det = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+
+    // Compare to the product of row norms so that the check is independent of the
+    // overall length scale.
+    let scale = norm(m[0]) * norm(m[1]) * norm(m[2]);
+    if scale == 0.0 || det.abs() <= 1e-12 * scale {
+        return None;
+    }
+
+    let mut inv = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            inv[j][i] = cofactor(i, j) / det;
+        }
+    }
+    Some(inv)
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn norm(v: [f64; 3]) -> f64 {
+    dot(v, v).sqrt()
+}
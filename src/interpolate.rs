@@ -0,0 +1,178 @@
+use std::f64::consts::PI;
+use num_complex::Complex64;
+use hr::HamiltonianR;
+use input::Cell;
+use kpath::KPath;
+use read::BandStructure;
+
+/// Bands interpolated from the Wannier Hamiltonian `ham` along `path`, with
+/// `points_per_segment` evenly spaced k-points on each segment.
+///
+/// As for the Wannier90 `bands_plot` output, a point shared by consecutive segments
+/// appears once, and distances are Cartesian (inverse units of `cell`) and do not
+/// increase across a jump between disconnected segments.
+///
+/// The Hamiltonian is transformed using the R-vectors of `ham` as given. If Wannier90 was
+/// run with `use_ws_distance = .true.`, its own interpolation additionally remaps each
+/// hopping according to `seedname_wsvec.dat`, which is not done here, so the bands may
+/// differ slightly from Wannier90's `bands_plot` output.
+///
+/// # Panics
+///
+/// Panics if the lattice vectors of `cell` are linearly dependent or if
+/// `points_per_segment` is zero.
+pub fn band_path(
+    ham: &HamiltonianR,
+    cell: &Cell,
+    path: &KPath,
+    points_per_segment: u64,
+) -> BandStructure {
+    assert!(points_per_segment > 0, "points_per_segment must be positive");
+    let recip = cell.reciprocal().expect("unit cell is not singular");
+
+    let mut kpoints: Vec<[f64; 3]> = Vec::new();
+    let mut distances: Vec<f64> = Vec::new();
+
+    for &(ref start, ref end) in path.segments.iter() {
+        if let Some(&last) = kpoints.last() {
+            // Continue from the previous distance, across a jump if there is one.
+            if last != start.k {
+                kpoints.push(start.k);
+                distances.push(*distances.last().unwrap());
+            }
+        } else {
+            kpoints.push(start.k);
+            distances.push(0.0);
+        }
+
+        let dk = [
+            end.k[0] - start.k[0],
+            end.k[1] - start.k[1],
+            end.k[2] - start.k[2],
+        ];
+        let step = cartesian_length(recip, dk) / points_per_segment as f64;
+
+        for j in 1..(points_per_segment + 1) {
+            let t = j as f64 / points_per_segment as f64;
+            let k = [
+                start.k[0] + t * dk[0],
+                start.k[1] + t * dk[1],
+                start.k[2] + t * dk[2],
+            ];
+            let d = *distances.last().unwrap() + step;
+
+            kpoints.push(k);
+            distances.push(d);
+        }
+    }
+
+    let num_wann = ham.num_wann;
+    let mut energies = vec![Vec::with_capacity(kpoints.len()); num_wann];
+    for k in kpoints.iter() {
+        let evals = hermitian_eigenvalues(&hamiltonian_k(ham, *k));
+        for (n, e) in evals.into_iter().enumerate() {
+            energies[n].push(e);
+        }
+    }
+
+    BandStructure {
+        energies,
+        distances,
+        kpoints,
+    }
+}
+
+/// Fourier transform `H(k) = sum_R exp(2 pi i k.R) H(R) / degeneracy(R)` of the Wannier
+/// Hamiltonian, with `k` in reciprocal lattice coordinates. No Wigner-Seitz distance
+/// remapping is applied (see `band_path`).
+pub fn hamiltonian_k(ham: &HamiltonianR, k: [f64; 3]) -> Vec<Vec<Complex64>> {
+    let num_wann = ham.num_wann;
+    let mut h_k = vec![vec![Complex64::new(0.0, 0.0); num_wann]; num_wann];
+
+    for ((r, h_r), &deg) in ham.r_vectors
+        .iter()
+        .zip(ham.hoppings.iter())
+        .zip(ham.degeneracies.iter())
+    {
+        let phase = 2.0 * PI * (k[0] * r[0] as f64 + k[1] * r[1] as f64 + k[2] * r[2] as f64);
+        let factor = Complex64::new(phase.cos(), phase.sin()) / deg as f64;
+
+        for m in 0..num_wann {
+            for n in 0..num_wann {
+                h_k[m][n] += factor * h_r[m][n];
+            }
+        }
+    }
+
+    h_k
+}
+
+/// Eigenvalues of the Hermitian matrix `h`, in increasing order.
+///
+/// The `n x n` complex matrix `A + iB` is represented as the `2n x 2n` real symmetric
+/// matrix `[[A, -B], [B, A]]`, whose spectrum is that of `h` with each eigenvalue doubled.
+/// That matrix is diagonalized by the cyclic Jacobi method, which is simple and accurate
+/// for the small matrices typical of Wannier Hamiltonians.
+pub fn hermitian_eigenvalues(h: &[Vec<Complex64>]) -> Vec<f64> {
+    let n = h.len();
+    let size = 2 * n;
+    let mut a = vec![vec![0.0; size]; size];
+    for i in 0..n {
+        for j in 0..n {
+            a[i][j] = h[i][j].re;
+            a[i + n][j + n] = h[i][j].re;
+            a[i][j + n] = -h[i][j].im;
+            a[i + n][j] = h[i][j].im;
+        }
+    }
+
+    for _ in 0..100 {
+        let off: f64 = (0..size)
+            .flat_map(|p| (0..size).filter(move |&q| q != p).map(move |q| (p, q)))
+            .map(|(p, q)| a[p][q] * a[p][q])
+            .sum();
+        let diag: f64 = (0..size).map(|p| a[p][p] * a[p][p]).sum();
+        if off <= 1e-30 * diag.max(1e-300) {
+            break;
+        }
+
+        for p in 0..size {
+            for q in (p + 1)..size {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for r in 0..size {
+                    let (arp, arq) = (a[r][p], a[r][q]);
+                    a[r][p] = c * arp - s * arq;
+                    a[r][q] = s * arp + c * arq;
+                }
+                for r in 0..size {
+                    let (apr, aqr) = (a[p][r], a[q][r]);
+                    a[p][r] = c * apr - s * aqr;
+                    a[q][r] = s * apr + c * aqr;
+                }
+            }
+        }
+    }
+
+    let mut evals: Vec<f64> = (0..size).map(|p| a[p][p]).collect();
+    evals.sort_by(|x, y| x.partial_cmp(y).expect("eigenvalues are not NaN"));
+
+    evals.into_iter().step_by(2).collect()
+}
+
+fn cartesian_length(recip: [[f64; 3]; 3], dk: [f64; 3]) -> f64 {
+    let mut v = [0.0; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            v[j] += dk[i] * recip[i][j];
+        }
+    }
+
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
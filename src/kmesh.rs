@@ -0,0 +1,85 @@
+use std::slice;
+
+/// Lazy iterator over a uniform grid of k-points in lattice coordinates.
+///
+/// The k-points are `k = ((i0 + s0) / n0, (i1 + s1) / n1, (i2 + s2) / n2)` for
+/// `0 <= i < n`, where `n` is the grid size and `s` the shift in units of the grid
+/// spacing. They are given in the order of `generate_uniform_kpoints` and of Wannier90's
+/// `kmesh.pl`: `i0` varies slowest and `i2` varies fastest.
+#[derive(Debug, Clone)]
+pub struct UniformKPoints {
+    nk: [u64; 3],
+    shift: [f64; 3],
+    next: u64,
+}
+
+/// Gamma-centered uniform grid with `nk[i]` points along reciprocal lattice vector `i`.
+pub fn uniform(nk: [u64; 3]) -> UniformKPoints {
+    uniform_shifted_iter(nk, [0.0, 0.0, 0.0])
+}
+
+/// Uniform grid displaced by `shift[i] / nk[i]` along reciprocal lattice vector `i`.
+pub fn uniform_shifted_iter(nk: [u64; 3], shift: [f64; 3]) -> UniformKPoints {
+    UniformKPoints { nk, shift, next: 0 }
+}
+
+/// The k-points of `uniform_shifted_iter(nk, shift)`, collected in order. This is the list
+/// to give pw.x for the nscf calculation on a shifted grid, in the order expected by
+/// pw2wannier90.x and used for the `kpoints` block, so that the k-point indices of the
+/// `.amn` and `.mmn` files line up.
+pub fn uniform_shifted(nk: [u64; 3], shift: [f64; 3]) -> Vec<[f64; 3]> {
+    uniform_shifted_iter(nk, shift).collect()
+}
+
+impl Iterator for UniformKPoints {
+    type Item = [f64; 3];
+
+    fn next(&mut self) -> Option<[f64; 3]> {
+        let nk = self.nk;
+        if self.next >= nk[0] * nk[1] * nk[2] {
+            return None;
+        }
+
+        let i2 = self.next % nk[2];
+        let i1 = (self.next / nk[2]) % nk[1];
+        let i0 = self.next / (nk[1] * nk[2]);
+        self.next += 1;
+
+        let component = |i: u64, d: usize| (i as f64 + self.shift[d]) / (nk[d] as f64);
+
+        Some([component(i0, 0), component(i1, 1), component(i2, 2)])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let nk = self.nk;
+        let remaining = (nk[0] * nk[1] * nk[2]).saturating_sub(self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for UniformKPoints {}
+
+/// Iterator over the k-points of a `KPointMesh`.
+#[derive(Debug, Clone)]
+pub enum KPointIter<'a> {
+    Uniform(UniformKPoints),
+    Explicit(slice::Iter<'a, [f64; 3]>),
+}
+
+impl<'a> Iterator for KPointIter<'a> {
+    type Item = [f64; 3];
+
+    fn next(&mut self) -> Option<[f64; 3]> {
+        match *self {
+            KPointIter::Uniform(ref mut ks) => ks.next(),
+            KPointIter::Explicit(ref mut ks) => ks.next().cloned(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match *self {
+            KPointIter::Uniform(ref ks) => ks.size_hint(),
+            KPointIter::Explicit(ref ks) => ks.size_hint(),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use input::Cell;
+
+/// High-symmetry point of the Brillouin zone, in lattice coordinates of the reciprocal
+/// lattice.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymmetryPoint {
+    pub label: String,
+    pub k: [f64; 3],
+}
+
+impl SymmetryPoint {
+    pub fn new(label: &str, k: [f64; 3]) -> SymmetryPoint {
+        SymmetryPoint {
+            label: String::from(label),
+            k,
+        }
+    }
+}
+
+/// Path through the Brillouin zone made of straight segments between high-symmetry
+/// points, as given in the Wannier90 `kpoint_path` block. Consecutive segments need not
+/// share an endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KPath {
+    pub segments: Vec<(SymmetryPoint, SymmetryPoint)>,
+}
+
+impl KPath {
+    /// Continuous path visiting `points` in order.
+    pub fn through(points: &[SymmetryPoint]) -> KPath {
+        let segments = points
+            .windows(2)
+            .map(|w| (w[0].clone(), w[1].clone()))
+            .collect();
+
+        KPath { segments }
+    }
+}
+
+/// Bravais lattices recognized by `bravais_lattice`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BravaisLattice {
+    Cubic,
+    FaceCenteredCubic,
+    BodyCenteredCubic,
+    Hexagonal,
+}
+
+/// Relative tolerance for comparing lattice vector lengths and angle cosines.
+pub const LATTICE_TOL: f64 = 1e-6;
+
+/// Identify the Bravais lattice of `cell` from the lengths of and angles between its
+/// lattice vectors, which must be the primitive vectors of the conventions of
+/// Setyawan and Curtarolo, Comp. Mat. Sci. 49, 299 (2010), up to rotation:
+///
+/// * cubic: three orthogonal vectors of equal length;
+/// * face-centered cubic: three vectors of equal length at 60 degrees to each other;
+/// * body-centered cubic: three vectors of equal length at `arccos(-1/3)` to each other;
+/// * hexagonal: `a1` and `a2` of equal length at 120 degrees, with `a3` perpendicular
+///   to both.
+pub fn bravais_lattice(cell: &Cell) -> Result<BravaisLattice, Error> {
+    let a = cell.cell;
+    let len = [norm(a[0]), norm(a[1]), norm(a[2])];
+    if len.iter().any(|&l| l == 0.0) {
+        return Err(Error::UnrecognizedLattice);
+    }
+    let cos = |i: usize, j: usize| dot(a[i], a[j]) / (len[i] * len[j]);
+    let (cos12, cos23, cos13) = (cos(0, 1), cos(1, 2), cos(0, 2));
+
+    let close = |x: f64, y: f64| (x - y).abs() <= LATTICE_TOL * x.abs().max(y.abs()).max(1.0);
+    let equal_lengths = close(len[0], len[1]) && close(len[1], len[2]);
+    let all_cos = |c: f64| close(cos12, c) && close(cos23, c) && close(cos13, c);
+
+    if equal_lengths && all_cos(0.0) {
+        Ok(BravaisLattice::Cubic)
+    } else if equal_lengths && all_cos(0.5) {
+        Ok(BravaisLattice::FaceCenteredCubic)
+    } else if equal_lengths && all_cos(-1.0 / 3.0) {
+        Ok(BravaisLattice::BodyCenteredCubic)
+    } else if close(len[0], len[1]) && close(cos12, -0.5) && close(cos23, 0.0)
+        && close(cos13, 0.0)
+    {
+        Ok(BravaisLattice::Hexagonal)
+    } else {
+        Err(Error::UnrecognizedLattice)
+    }
+}
+
+/// Standard high-symmetry path of Setyawan and Curtarolo for the Bravais lattice of
+/// `cell`, as identified by `bravais_lattice`. Points are given in lattice coordinates
+/// of the reciprocal lattice of `cell`, and the label `G` is used for Gamma.
+pub fn standard_path(cell: &Cell) -> Result<KPath, Error> {
+    let p = SymmetryPoint::new;
+    let g = p("G", [0.0, 0.0, 0.0]);
+
+    let segments = match bravais_lattice(cell)? {
+        BravaisLattice::Cubic => {
+            let (x, m, r) = (
+                p("X", [0.0, 0.5, 0.0]),
+                p("M", [0.5, 0.5, 0.0]),
+                p("R", [0.5, 0.5, 0.5]),
+            );
+            // G-X-M-G-R-X|M-R
+            vec![
+                vec![g.clone(), x.clone(), m.clone(), g.clone(), r.clone(), x],
+                vec![m, r],
+            ]
+        }
+        BravaisLattice::FaceCenteredCubic => {
+            let (k, l, u, w, x) = (
+                p("K", [0.375, 0.375, 0.75]),
+                p("L", [0.5, 0.5, 0.5]),
+                p("U", [0.625, 0.25, 0.625]),
+                p("W", [0.5, 0.25, 0.75]),
+                p("X", [0.5, 0.0, 0.5]),
+            );
+            // G-X-W-K-G-L-U-W-L-K|U-X
+            vec![
+                vec![
+                    g.clone(),
+                    x.clone(),
+                    w.clone(),
+                    k.clone(),
+                    g,
+                    l.clone(),
+                    u.clone(),
+                    w,
+                    l,
+                    k,
+                ],
+                vec![u, x],
+            ]
+        }
+        BravaisLattice::BodyCenteredCubic => {
+            let (h, n, pt) = (
+                p("H", [0.5, -0.5, 0.5]),
+                p("N", [0.0, 0.0, 0.5]),
+                p("P", [0.25, 0.25, 0.25]),
+            );
+            // G-H-N-G-P-H|P-N
+            vec![
+                vec![g.clone(), h.clone(), n.clone(), g, pt.clone(), h],
+                vec![pt, n],
+            ]
+        }
+        BravaisLattice::Hexagonal => {
+            let (a, h, k, l, m) = (
+                p("A", [0.0, 0.0, 0.5]),
+                p("H", [1.0 / 3.0, 1.0 / 3.0, 0.5]),
+                p("K", [1.0 / 3.0, 1.0 / 3.0, 0.0]),
+                p("L", [0.5, 0.0, 0.5]),
+                p("M", [0.5, 0.0, 0.0]),
+            );
+            // G-M-K-G-A-L-H-A|L-M|K-H
+            vec![
+                vec![
+                    g.clone(),
+                    m.clone(),
+                    k.clone(),
+                    g,
+                    a.clone(),
+                    l.clone(),
+                    h.clone(),
+                    a,
+                ],
+                vec![l, m],
+                vec![k, h],
+            ]
+        }
+    };
+
+    Ok(KPath {
+        segments: segments
+            .iter()
+            .flat_map(|points| KPath::through(points).segments)
+            .collect(),
+    })
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn norm(v: [f64; 3]) -> f64 {
+    dot(v, v).sqrt()
+}
+
+#[derive(Fail, Debug)]
+pub enum Error {
+    #[fail(display = "Lattice is not one of the Bravais lattices with a standard path.")]
+    UnrecognizedLattice,
+}
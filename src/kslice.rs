@@ -0,0 +1,50 @@
+/// Parse the contents of `seedname-kslice-fermi_lines.dat` written by postw90 with
+/// `kslice = .true.` and `kslice_task = fermi_lines`.
+///
+/// Each contour of the Fermi lines in the 2D k-plane is given as a block of `k1 k2` lines,
+/// with consecutive contours separated by blank lines. The contours are returned in file
+/// order as polylines of points.
+pub fn parse_fermi_lines(text: &str) -> Result<Vec<Vec<[f64; 2]>>, KsliceError> {
+    let mut contours = Vec::new();
+    let mut contour = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if line.len() == 0 {
+            if contour.len() > 0 {
+                contours.push(contour);
+                contour = Vec::new();
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let vals = line.split_whitespace()
+            .map(|tok| {
+                tok.parse()
+                    .map_err(|_| KsliceError::InvalidNumber(String::from(tok)))
+            })
+            .collect::<Result<Vec<f64>, KsliceError>>()?;
+
+        if vals.len() != 2 {
+            return Err(KsliceError::UnexpectedLine(String::from(line)));
+        }
+
+        contour.push([vals[0], vals[1]]);
+    }
+
+    if contour.len() > 0 {
+        contours.push(contour);
+    }
+
+    Ok(contours)
+}
+
+#[derive(Fail, Debug)]
+pub enum KsliceError {
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Expected two columns, got `{}`.", _0)] UnexpectedLine(String),
+}
@@ -2,9 +2,25 @@
 extern crate failure;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
 
+extern crate num_complex;
 extern crate qe;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 
 pub mod input;
 pub mod serialize;
+pub mod parse;
+pub mod hr;
+pub mod eig;
+pub mod read;
+pub mod write;
+pub mod kslice;
 pub mod qe_workflow;
+pub mod kmesh;
+pub mod kpath;
+pub mod interpolate;
+pub mod run;
+mod tokens;
@@ -0,0 +1,472 @@
+use std::str::FromStr;
+use std::collections::HashMap;
+use input::{AngularMomentum, DisSphere, DisSpheres, Disentanglement, Projection, ProjectionSite,
+            SpinProjection, DEFAULT_DIS_MIX_RATIO, DEFAULT_DIS_NUM_ITER};
+
+impl FromStr for AngularMomentum {
+    type Err = Error;
+
+    /// Parse an angular momentum given as `l=-5` through `l=3`, as a list of orbitals
+    /// such as `l=2,mr=1,4`, or by name: `s`, `p`, `d`, `f`, the hybrids `sp` through
+    /// `sp3d2` and their members such as `sp3-1`, the p and d orbitals such as `pz` and
+    /// `dx2-y2`, and the d orbital sets `eg` and `t2g`.
+    fn from_str(s: &str) -> Result<AngularMomentum, Error> {
+        let token: String = s.chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+        let invalid = || Error::InvalidAngularMomentum(String::from(s));
+
+        match token.as_str() {
+            "s" => return Ok(AngularMomentum::S),
+            "p" => return Ok(AngularMomentum::P),
+            "d" => return Ok(AngularMomentum::D),
+            "f" => return Ok(AngularMomentum::F),
+            "sp" => return Ok(AngularMomentum::Sp),
+            "sp2" => return Ok(AngularMomentum::Sp2),
+            "sp3" => return Ok(AngularMomentum::Sp3),
+            "sp3d" => return Ok(AngularMomentum::Sp3d),
+            "sp3d2" => return Ok(AngularMomentum::Sp3d2),
+            _ => (),
+        }
+        if let Some((l, mr)) = named_orbitals(&token) {
+            return Ok(AngularMomentum::Orbitals { l, mr });
+        }
+
+        if !token.starts_with("l=") {
+            return Err(invalid());
+        }
+        let mut parts = token["l=".len()..].splitn(2, ",mr=");
+        let l: i64 = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+        match parts.next() {
+            Some(mr) => {
+                let mr = mr.split(',')
+                    .map(|m| m.parse().map_err(|_| invalid()))
+                    .collect::<Result<Vec<u64>, Error>>()?;
+                Ok(AngularMomentum::Orbitals { l, mr })
+            }
+            None => AngularMomentum::from_l(l).ok_or_else(invalid),
+        }
+    }
+}
+
+/// The `l` and `mr` of a lowercase orbital name such as `dxy`, `eg` or `sp3-2`.
+fn named_orbitals(name: &str) -> Option<(i64, Vec<u64>)> {
+    let orbitals = match name {
+        "pz" => (1, vec![1]),
+        "px" => (1, vec![2]),
+        "py" => (1, vec![3]),
+        "dz2" => (2, vec![1]),
+        "dxz" => (2, vec![2]),
+        "dyz" => (2, vec![3]),
+        "dx2-y2" => (2, vec![4]),
+        "dxy" => (2, vec![5]),
+        "eg" => (2, vec![1, 4]),
+        "t2g" => (2, vec![2, 3, 5]),
+        _ => {
+            let mut split = name.rsplitn(2, '-');
+            let mr = split.next()?.parse().ok()?;
+            let l = match split.next()? {
+                "sp" => -1,
+                "sp2" => -2,
+                "sp3" => -3,
+                "sp3d" => -4,
+                "sp3d2" => -5,
+                _ => return None,
+            };
+            (l, vec![mr])
+        }
+    };
+    Some(orbitals)
+}
+
+/// Split the angular momentum part of a projection line into its entries. Entries are
+/// separated by `;` or `,`, except that the commas of an `mr` list such as `l=2,mr=1,4`
+/// belong to its entry.
+fn split_angular_momenta(ang_mtm: &str) -> Vec<String> {
+    let mut entries: Vec<String> = Vec::new();
+    for token in ang_mtm.split(|c| c == ';' || c == ',') {
+        let in_mr_list = |entry: &String| entry.to_lowercase().contains("mr=");
+        let continues = token.to_lowercase().starts_with("mr=")
+            || (token.parse::<u64>().is_ok() && entries.last().map_or(false, in_mr_list));
+        match entries.last_mut() {
+            Some(entry) if continues => {
+                entry.push(',');
+                entry.push_str(token);
+            }
+            _ => entries.push(String::from(token)),
+        }
+    }
+    entries
+}
+
+impl FromStr for Projection {
+    type Err = Error;
+
+    /// Parse a single line of a `projections` block, such as `Se:l=1;l=2` or
+    /// `f=0,0,0:l=0:z=0,0,1`. Angular momenta may be separated by either `;` or `,`, as
+    /// in `Fe:l=0;l=2,mr=1,4`.
+    fn from_str(s: &str) -> Result<Projection, Error> {
+        let line: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+
+        if line.to_lowercase() == "random" {
+            return Ok(Projection::Random);
+        }
+
+        let mut parts = line.split(':');
+
+        let site = parse_site(parts.next().unwrap_or(""))?;
+
+        let (ang_mtm, spin, quant_dir) = match parts.next() {
+            Some(part) => {
+                let (ang_mtm, spin, quant_dir) = split_spin(part)?;
+                let ang_mtm = split_angular_momenta(ang_mtm)
+                    .iter()
+                    .map(|l| l.parse())
+                    .collect::<Result<Vec<AngularMomentum>, Error>>()?;
+                (ang_mtm, spin, quant_dir)
+            }
+            None => return Err(Error::MissingAngularMomentum(String::from(s))),
+        };
+
+        let mut zaxis = None;
+        let mut xaxis = None;
+        let mut radial = None;
+        let mut zona = None;
+
+        for part in parts {
+            let (key, val) = split_key_value(part)?;
+            let duplicate = match key.to_lowercase().as_str() {
+                "z" => zaxis.replace(parse_vector(val)?).is_some(),
+                "x" => xaxis.replace(parse_vector(val)?).is_some(),
+                "r" => radial.replace(parse_number(val)?).is_some(),
+                "zona" => zona.replace(parse_number(val)?).is_some(),
+                _ => return Err(Error::UnknownProjectionOption(String::from(part))),
+            };
+            if duplicate {
+                return Err(Error::DuplicateProjectionOption(String::from(key)));
+            }
+        }
+
+        Ok(Projection::Site {
+            site,
+            ang_mtm,
+            zaxis,
+            xaxis,
+            radial,
+            zona,
+            spin,
+            quant_dir,
+            per_orbital: Vec::new(),
+        })
+    }
+}
+
+/// Split the angular momentum part of a projection into the angular momenta and the
+/// optional spin channel and quantisation axis of a spinor projection, as in
+/// `l=1;l=2(u)[0,0,1]`.
+fn split_spin(part: &str) -> Result<(&str, Option<SpinProjection>, Option<[f64; 3]>), Error> {
+    let open = match part.find('(') {
+        Some(open) => open,
+        None => return Ok((part, None, None)),
+    };
+    let close = part.find(')').ok_or(Error::InvalidSpin(String::from(part)))?;
+    if close < open {
+        return Err(Error::InvalidSpin(String::from(part)));
+    }
+
+    let spin = match part[open + 1..close].to_lowercase().as_str() {
+        "u" => SpinProjection::Up,
+        "d" => SpinProjection::Down,
+        "u,d" | "d,u" => SpinProjection::Both,
+        _ => return Err(Error::InvalidSpin(String::from(part))),
+    };
+
+    let rest = &part[close + 1..];
+    let quant_dir = if rest.is_empty() {
+        None
+    } else if rest.starts_with('[') && rest.ends_with(']') {
+        Some(parse_vector(&rest[1..rest.len() - 1])?)
+    } else {
+        return Err(Error::InvalidSpin(String::from(part)));
+    };
+
+    Ok((&part[..open], Some(spin), quant_dir))
+}
+
+fn parse_site(s: &str) -> Result<ProjectionSite, Error> {
+    if s.is_empty() {
+        return Err(Error::MissingSite);
+    }
+
+    let lower = s.to_lowercase();
+    if lower.starts_with("c=") {
+        Ok(ProjectionSite::CenterCartesian(parse_vector(&s[2..])?))
+    } else if lower.starts_with("f=") {
+        Ok(ProjectionSite::CenterCrystal(parse_vector(&s[2..])?))
+    } else {
+        Ok(ProjectionSite::Species(String::from(s)))
+    }
+}
+
+
+fn split_key_value(s: &str) -> Result<(&str, &str), Error> {
+    match s.find('=') {
+        Some(eq) => Ok((&s[..eq], &s[eq + 1..])),
+        None => Err(Error::UnknownProjectionOption(String::from(s))),
+    }
+}
+
+fn parse_vector(s: &str) -> Result<[f64; 3], Error> {
+    let vals = s.split(',')
+        .map(parse_number)
+        .collect::<Result<Vec<f64>, Error>>()?;
+
+    if vals.len() == 3 {
+        Ok([vals[0], vals[1], vals[2]])
+    } else {
+        Err(Error::InvalidVector(String::from(s)))
+    }
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, Error> {
+    s.parse().map_err(|_| Error::InvalidNumber(String::from(s)))
+}
+
+/// Keywords and blocks of a Wannier90 `.win` file, in file order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WinFile {
+    /// `(keyword, value)` pairs, with keywords in lowercase.
+    pub keywords: Vec<(String, String)>,
+    /// `(name, lines)` for each `begin name` ... `end name` block, with names in lowercase
+    /// and lines trimmed.
+    pub blocks: Vec<(String, Vec<String>)>,
+    /// Trailing comments on keyword lines and `begin` lines, keyed by the lowercase
+    /// keyword or block name, without the comment character. Comments inside blocks and
+    /// on lines of their own are dropped.
+    pub comments: HashMap<String, String>,
+}
+
+impl WinFile {
+    /// Value of the keyword `name`, which must be given in lowercase.
+    pub fn keyword(&self, name: &str) -> Option<&str> {
+        self.keywords
+            .iter()
+            .find(|&&(ref k, _)| k == name)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    /// Lines of the block `name`, which must be given in lowercase.
+    pub fn block(&self, name: &str) -> Option<&[String]> {
+        self.blocks
+            .iter()
+            .find(|&&(ref b, _)| b == name)
+            .map(|&(_, ref lines)| lines.as_slice())
+    }
+
+    /// Disentanglement settings given by the `dis_*` keywords, or `None` if none of them
+    /// are present. `dis_num_iter` and `dis_mix_ratio` default to `DEFAULT_DIS_NUM_ITER`
+    /// and `DEFAULT_DIS_MIX_RATIO`, and the outer and frozen window bounds to `None`.
+    /// `dis_spheres_num` is required if the `dis_spheres`
+    /// block is given. The projectability settings `dis_froz_proj`, `dis_proj_min` and
+    /// `dis_proj_max` are `None` unless present.
+    pub fn disentanglement(&self) -> Result<Option<Disentanglement>, Error> {
+        if !self.keywords.iter().any(|&(ref k, _)| k.starts_with("dis_")) {
+            return Ok(None);
+        }
+
+        let optional = |name: &str| -> Result<Option<f64>, Error> {
+            match self.keyword(name) {
+                Some(val) => parse_fortran_real(val).map(Some),
+                None => Ok(None),
+            }
+        };
+
+        Ok(Some(Disentanglement {
+            dis_win_min: optional("dis_win_min")?,
+            dis_win_max: optional("dis_win_max")?,
+            dis_froz_min: optional("dis_froz_min")?,
+            dis_froz_max: optional("dis_froz_max")?,
+            dis_num_iter: match self.keyword("dis_num_iter") {
+                Some(val) => parse_number(val)?,
+                None => DEFAULT_DIS_NUM_ITER,
+            },
+            dis_mix_ratio: optional("dis_mix_ratio")?.unwrap_or(DEFAULT_DIS_MIX_RATIO),
+            dis_spheres: self.dis_spheres()?,
+            dis_froz_proj: match self.keyword("dis_froz_proj") {
+                Some(val) => Some(parse_fortran_bool(val)?),
+                None => None,
+            },
+            dis_proj_min: optional("dis_proj_min")?,
+            dis_proj_max: optional("dis_proj_max")?,
+        }))
+    }
+
+    fn dis_spheres(&self) -> Result<Option<DisSpheres>, Error> {
+        let block = self.block("dis_spheres");
+        let num = match self.keyword("dis_spheres_num") {
+            Some(val) => parse_number(val)?,
+            None if block.is_some() => {
+                return Err(Error::MissingKeyword(String::from("dis_spheres_num")))
+            }
+            None => return Ok(None),
+        };
+
+        let spheres = block
+            .unwrap_or(&[])
+            .iter()
+            .map(|line| {
+                let values = line.split_whitespace()
+                    .map(parse_fortran_real)
+                    .collect::<Result<Vec<f64>, Error>>()?;
+                if values.len() != 4 {
+                    return Err(Error::InvalidDisSphere(line.clone()));
+                }
+
+                Ok(DisSphere {
+                    center: [values[0], values[1], values[2]],
+                    radius: values[3],
+                })
+            })
+            .collect::<Result<Vec<DisSphere>, Error>>()?;
+
+        Ok(Some(DisSpheres {
+            dis_spheres_num: num,
+            dis_spheres_first_wann: match self.keyword("dis_spheres_first_wann") {
+                Some(val) => Some(parse_number(val)?),
+                None => None,
+            },
+            spheres,
+        }))
+    }
+}
+
+/// Parse a real number, accepting the Fortran `d` exponent marker as in `1.0d-3`.
+fn parse_fortran_real(s: &str) -> Result<f64, Error> {
+    parse_number(&s.replace(|c| c == 'd' || c == 'D', "e"))
+        .map_err(|_| Error::InvalidNumber(String::from(s)))
+}
+
+/// Parse a logical given as `.true.` or `.false.`, or by any of the other forms Wannier90
+/// accepts: `t`, `true`, `f` or `false`, with or without dots, in any case.
+fn parse_fortran_bool(s: &str) -> Result<bool, Error> {
+    match s.trim_matches('.').to_lowercase().as_str() {
+        "t" | "true" => Ok(true),
+        "f" | "false" => Ok(false),
+        _ => Err(Error::InvalidBool(String::from(s))),
+    }
+}
+
+/// Split the text of a `.win` file into keywords and blocks.
+///
+/// Comments, which run from a `!` or `#` to the end of the line, and blank lines are
+/// skipped; this includes the header written by `serialize::provenance_header`. Keywords
+/// are separated from their values by `=`, `:`, or whitespace, following Wannier90.
+pub fn parse_win(text: &str) -> Result<WinFile, Error> {
+    let mut keywords = Vec::new();
+    let mut blocks = Vec::new();
+    let mut comments = HashMap::new();
+    let mut current_block: Option<(String, Vec<String>)> = None;
+
+    for full_line in text.lines() {
+        let (line, comment) = split_comment(full_line);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (first, rest) = split_keyword(line);
+        let first = first.to_lowercase();
+
+        if let Some((name, lines)) = current_block.take() {
+            if first == "end" {
+                if rest.to_lowercase() != name {
+                    return Err(Error::UnexpectedBlockEnd(String::from(line)));
+                }
+                blocks.push((name, lines));
+            } else {
+                let mut lines = lines;
+                lines.push(String::from(line));
+                current_block = Some((name, lines));
+            }
+            continue;
+        }
+
+        let key = if first == "begin" {
+            rest.to_lowercase()
+        } else {
+            first.clone()
+        };
+        if let Some(comment) = comment.map(str::trim).filter(|c| !c.is_empty()) {
+            comments.insert(key, String::from(comment));
+        }
+
+        match first.as_str() {
+            "begin" => current_block = Some((rest.to_lowercase(), Vec::new())),
+            "end" => return Err(Error::UnexpectedBlockEnd(String::from(line))),
+            _ => {
+                if rest.is_empty() {
+                    return Err(Error::MissingValue(first));
+                }
+                keywords.push((first, String::from(rest)));
+            }
+        }
+    }
+
+    if let Some((name, _)) = current_block {
+        return Err(Error::UnterminatedBlock(name));
+    }
+
+    Ok(WinFile {
+        keywords,
+        blocks,
+        comments,
+    })
+}
+
+/// Split `line` into the text before any comment and the comment text following the `!`
+/// or `#` which starts it.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match line.find(|c| c == '!' || c == '#') {
+        Some(i) => (&line[..i], Some(&line[i + 1..])),
+        None => (line, None),
+    }
+}
+
+/// Split a trimmed line into its first word and the remainder, removing a `=` or `:`
+/// separator between them. For `begin` and `end` lines, the remainder is the block name.
+fn split_keyword(line: &str) -> (&str, &str) {
+    let end = line.find(|c: char| c == '=' || c == ':' || c.is_whitespace())
+        .unwrap_or(line.len());
+    let (first, rest) = line.split_at(end);
+
+    let rest = rest.trim_start();
+    let rest = if rest.starts_with('=') || rest.starts_with(':') {
+        &rest[1..]
+    } else {
+        rest
+    };
+
+    (first, rest.trim())
+}
+
+#[derive(Fail, Debug)]
+pub enum Error {
+    #[fail(display = "Projection is missing a site.")] MissingSite,
+    #[fail(display = "Projection `{}` is missing angular momenta.", _0)]
+    MissingAngularMomentum(String),
+    #[fail(display = "Invalid angular momentum `{}`.", _0)] InvalidAngularMomentum(String),
+    #[fail(display = "Unknown projection option `{}`.", _0)] UnknownProjectionOption(String),
+    #[fail(display = "Projection option `{}` given more than once.", _0)]
+    DuplicateProjectionOption(String),
+    #[fail(display = "Expected three comma-separated components, got `{}`.", _0)]
+    InvalidVector(String),
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Invalid logical `{}`.", _0)] InvalidBool(String),
+    #[fail(display = "Invalid spin specifier in `{}`.", _0)] InvalidSpin(String),
+    #[fail(display = "Keyword `{}` has no value.", _0)] MissingValue(String),
+    #[fail(display = "Required keyword `{}` is missing.", _0)] MissingKeyword(String),
+    #[fail(display = "Block `{}` has no matching `end`.", _0)] UnterminatedBlock(String),
+    #[fail(display = "Unexpected block end `{}`.", _0)] UnexpectedBlockEnd(String),
+    #[fail(display = "Expected `kx ky kz radius`, got `{}`.", _0)] InvalidDisSphere(String),
+}
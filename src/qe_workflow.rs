@@ -1,20 +1,72 @@
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
 use qe::pw::input::Input as PwInput;
 use qe::pw::input::{Calculation, Ibrav, KPoints, Occupations, Smearing, SpinType};
 use qe::pw::input::LatticeUnits as PwLatticeUnits;
 use qe::pw::input::PositionCoordinateType as PwCoord;
 use qe::pw::input::AtomCoordinate as PwAtomCoordinate;
 use input::Input as W90Input;
-use input::{Disentanglement, MLWFIterationMode, Projection};
+use input::{Disentanglement, KPointMesh, MLWFIterationMode, Projection, SpinChannel};
 use input::LatticeUnits as W90LatticeUnits;
 use input::PositionCoordinateType as W90Coord;
 use input::AtomCoordinate as W90AtomCoordinate;
 use input::Positions as W90Positions;
 use input::Cell as W90Cell;
+use serialize::{fortran_bool, Field};
+use kpath::{KPath, SymmetryPoint};
+use eig::{read_eig, EigError};
+
+/// Default fraction of `num_wann` added as extra bands by `NumBands::Auto`.
+pub const DEFAULT_EXTRA_BANDS_FRACTION: f64 = 0.2;
+
+/// Default minimum number of extra bands added by `NumBands::Auto`.
+pub const DEFAULT_MIN_EXTRA_BANDS: u64 = 4;
+
+/// Suggested number of bands for the nscf calculation when constructing `num_wann`
+/// Wannier functions: `num_wann` plus `ceil(num_wann * extra_fraction)` extra bands, with
+/// at least `min_extra` extra bands.
+///
+/// Extra bands above the target manifold give disentanglement room to work with; too
+/// few bands is a common cause of disentanglement failures.
+pub fn suggest_num_bands(num_wann: u64, extra_fraction: f64, min_extra: u64) -> u64 {
+    let extra = (num_wann as f64 * extra_fraction).ceil() as u64;
+
+    num_wann + extra.max(min_extra)
+}
+
+/// Number of bands (`nbnd`) to use in the nscf calculation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumBands {
+    /// Use `suggest_num_bands` with `DEFAULT_EXTRA_BANDS_FRACTION` and
+    /// `DEFAULT_MIN_EXTRA_BANDS` for the `num_wann` of the calculation.
+    Auto,
+    Explicit(u64),
+}
+
+impl NumBands {
+    /// Number of bands for a calculation with `num_wann` Wannier functions.
+    pub fn value(&self, num_wann: u64) -> u64 {
+        match *self {
+            NumBands::Auto => suggest_num_bands(
+                num_wann,
+                DEFAULT_EXTRA_BANDS_FRACTION,
+                DEFAULT_MIN_EXTRA_BANDS,
+            ),
+            NumBands::Explicit(num_bands) => num_bands,
+        }
+    }
+}
 
 pub fn nscf_input(
     scf: &PwInput,
     diago_thr_init: f64,
-    num_bands: u64,
+    num_bands: &NumBands,
+    num_wann: u64,
     smearing_type: &Smearing,
     smearing_size: f64,
     nscf_nk: [u64; 3],
@@ -23,7 +75,7 @@ pub fn nscf_input(
         let mut nscf = scf.clone();
         nscf.calculation = Calculation::Nscf {
             diago_thr_init,
-            nbnd: Some(num_bands),
+            nbnd: Some(num_bands.value(num_wann)),
             nosym: Some(true),
         };
         nscf.system.occupations = Occupations::Smearing(smearing_type.clone(), smearing_size);
@@ -70,6 +122,31 @@ pub fn bands_input(nscf: &PwInput, bands_kpoints: &KPoints) -> Result<PwInput, E
     Ok(bands)
 }
 
+/// High-symmetry path through the Brillouin zone given by the pw.x `bands_kpoints`, so
+/// that the same path can be used for the bands calculation and for Wannier interpolation.
+/// Consecutive panel bounds become consecutive segments of a continuous path. pw.x does
+/// not label the panel bounds, so the labels of the returned points are empty.
+pub fn kpath_from_qe(bands_kpoints: &KPoints) -> Result<KPath, Error> {
+    match *bands_kpoints {
+        KPoints::CrystalBands {
+            ref panel_bounds,
+            ..
+        } => {
+            let points: Vec<SymmetryPoint> = panel_bounds
+                .iter()
+                .map(|&k| SymmetryPoint::new("", k))
+                .collect();
+
+            Ok(KPath::through(&points))
+        }
+        _ => Err(Error::WrongKPointsBands),
+    }
+}
+
+/// Construct the Wannier90 input corresponding to the given `nscf` calculation.
+///
+/// If `nscf` uses smearing, outer window bounds which are `None` in `disentanglement` are
+/// derived from the smearing width as described for `smearing_outer_window`.
 pub fn w90_input(
     nscf: &PwInput,
     num_wann: u64,
@@ -89,18 +166,121 @@ pub fn w90_input(
         _ => Err(Error::WrongCalculation),
     }?;
 
-    let spinors = match nscf.system.spin_type {
-        Some(ref spin_type) => match spin_type {
-            &SpinType::NonPolarized | &SpinType::CollinearPolarized => false,
-            &SpinType::Noncollinear { .. } => true,
-        },
-        None => false,
+    w90_input_with_num_bands(
+        nscf,
+        num_bands,
+        num_wann,
+        mlwf_iteration_mode,
+        disentanglement,
+        projection_units,
+        projections,
+    )
+}
+
+/// Construct the Wannier90 input corresponding to the given `nscf` calculation as done by
+/// `w90_input`, but with `num_bands` given explicitly instead of taken from `nbnd`. This
+/// allows an nscf calculation which did not set `nbnd` to be used, with `num_bands` taken
+/// from its output by `num_bands_from_eig`.
+pub fn w90_input_with_num_bands(
+    nscf: &PwInput,
+    num_bands: u64,
+    num_wann: u64,
+    mlwf_iteration_mode: &MLWFIterationMode,
+    disentanglement: &Disentanglement,
+    projection_units: Option<W90LatticeUnits>,
+    projections: Vec<Projection>,
+) -> Result<W90Input, Error> {
+    match nscf.calculation {
+        Calculation::Nscf { .. } => (),
+        _ => return Err(Error::WrongCalculation),
+    }
+
+    let spinors = is_noncollinear(nscf);
+
+    let (unit_cell_cart, positions) = positions_from_scf(nscf)?;
+
+    let disentanglement = match nscf.system.occupations {
+        Occupations::Smearing(_, smearing_size) => {
+            smearing_outer_window(disentanglement, smearing_size)
+        }
+        _ => disentanglement.clone(),
     };
 
-    let (lattice_units, cell) = match nscf.system.ibrav {
+    let kpoints = match nscf.k_points {
+        KPoints::CrystalUniform(nk) => Ok(KPointMesh::Uniform(nk)),
+        _ => Err(Error::WrongKPointsNscf),
+    }?;
+
+    Ok(W90Input {
+        num_bands,
+        num_wann,
+        write_hr: Some(true),
+        write_tb: None,
+        write_u_matrices: None,
+        use_ws_distance: None,
+        mlwf_iteration_mode: mlwf_iteration_mode.clone(),
+        num_print_cycles: None,
+        num_dump_cycles: None,
+        optimisation: None,
+        output_flags: None,
+        wvfn_formatted: None,
+        spn_formatted: None,
+        uhu_formatted: None,
+        restart: None,
+        guiding_centres: None,
+        num_guide_cycles: None,
+        num_no_guide_iter: None,
+        selective_localization: None,
+        site_symmetry: None,
+        symmetrize_eps: None,
+        exclude_bands: None,
+        disentanglement: Some(disentanglement),
+        dos: None,
+        berry: None,
+        boltzwann: None,
+        geninterp: None,
+        geninterp_alsofirstder: None,
+        geninterp_single_file: None,
+        bands_plot: None,
+        kpoint_path: None,
+        bands_plot_options: None,
+        use_bloch_phases: None,
+        spinors,
+        spin: None,
+        spin_axis_polar: None,
+        spin_axis_azimuth: None,
+        projection_units,
+        projections,
+        unit_cell_cart,
+        positions,
+        kpoints,
+        gamma_only: None,
+        kmesh_tol: None,
+        search_shells: None,
+        skip_b1_tests: None,
+        comments: HashMap::new(),
+    })
+}
+
+/// Number of bands in the `seedname.eig` file at `path`, as written by pw2wannier90.x for
+/// a completed nscf calculation.
+pub fn num_bands_from_eig<P: AsRef<Path>>(path: P) -> Result<u64, Error> {
+    let eig = read_eig(path)?;
+    if eig.num_bands == 0 {
+        return Err(Error::EmptyEig);
+    }
+
+    Ok(eig.num_bands as u64)
+}
+
+/// Unit cell and atomic positions of the pw.x calculation `scf`, for building a Wannier90
+/// input with projections and energy windows chosen separately. Lengths given in units of
+/// `alat` are converted to Bohr; other units are kept.
+pub fn positions_from_scf(scf: &PwInput) -> Result<(W90Cell, W90Positions), Error> {
+    let (lattice_units, cell) = match scf.system.ibrav {
         Ibrav::Free(ref cell) => match cell.units {
             PwLatticeUnits::Alat => {
-                let alat = nscf.system.alat;
+                let alat = scf.system.alat;
                 let lat_vecs = scale_cell(cell.cell, alat);
                 (W90LatticeUnits::Bohr, lat_vecs)
             }
@@ -108,7 +288,7 @@ pub fn w90_input(
             PwLatticeUnits::Angstrom => (W90LatticeUnits::Angstrom, cell.cell),
         }, // TODO - support other Ibrav cases.
            // Here we don't have a simple way to extract the lattice vectors from PwInput.
-           // May need to generate it by hand, or just leave unsupported for w90_input.
+           // May need to generate it by hand, or just leave unsupported here.
            // Another possibilty: extract from scf output. Then must run this step after
            // scf finishes, though.
     };
@@ -117,23 +297,23 @@ pub fn w90_input(
         cell,
     };
 
-    let (coordinate_type, coordinates) = match nscf.atomic_positions.coordinate_type {
+    let (coordinate_type, coordinates) = match scf.atomic_positions.coordinate_type {
         PwCoord::AlatCartesian => {
-            let alat = nscf.system.alat;
-            let coordinates = scale_coords(&nscf.atomic_positions.coordinates, alat);
+            let alat = scf.system.alat;
+            let coordinates = scale_coords(&scf.atomic_positions.coordinates, alat);
             Ok((W90Coord::BohrCartesian, coordinates))
         }
         PwCoord::BohrCartesian => Ok((
             W90Coord::BohrCartesian,
-            map_coords(&nscf.atomic_positions.coordinates),
+            map_coords(&scf.atomic_positions.coordinates),
         )),
         PwCoord::AngstromCartesian => Ok((
             W90Coord::AngstromCartesian,
-            map_coords(&nscf.atomic_positions.coordinates),
+            map_coords(&scf.atomic_positions.coordinates),
         )),
         PwCoord::Crystal => Ok((
             W90Coord::Crystal,
-            map_coords(&nscf.atomic_positions.coordinates),
+            map_coords(&scf.atomic_positions.coordinates),
         )),
         PwCoord::CrystalSG => Err(Error::CrystalSG),
     }?;
@@ -142,34 +322,404 @@ pub fn w90_input(
         coordinates,
     };
 
-    let k_points = match nscf.k_points {
-        KPoints::CrystalUniform(k_points) => Ok(k_points),
-        _ => Err(Error::WrongKPointsNscf),
-    }?;
+    Ok((unit_cell_cart, positions))
+}
 
-    Ok(W90Input {
-        num_bands,
+fn is_noncollinear(pw: &PwInput) -> bool {
+    match pw.system.spin_type {
+        Some(ref spin_type) => match spin_type {
+            &SpinType::NonPolarized | &SpinType::CollinearPolarized => false,
+            &SpinType::Noncollinear { .. } => true,
+        },
+        None => false,
+    }
+}
+
+/// Occupied bands in the `nscf` calculation of an insulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OccupiedBands {
+    /// Occupied bands of a non-polarized or noncollinear calculation.
+    Bands(u64),
+    /// Occupied bands in each spin channel of a collinear spin-polarized calculation.
+    Collinear { up: u64, down: u64 },
+}
+
+/// Occupied bands in the `nscf` calculation of an insulator.
+///
+/// The number of electrons is the sum of the valence charges `z_valence` of the species
+/// at each atomic position; see `num_valence_electrons`. The valence charges cannot be
+/// taken from `nscf` itself: the pw.x input only names the pseudopotential files, so they
+/// are passed in `z_valence`, as read from the files with `read_z_valence`.
+///
+/// For a collinear spin-polarized calculation, the split of the electrons between the
+/// spin channels is fixed by the total magnetization `tot_magnetization` (in Bohr
+/// magnetons per unit cell), which must then be given; it is ignored otherwise. The
+/// result is then a count for each channel rather than a single number of bands.
+///
+/// Failures are given as an `Error` rather than `None`, to distinguish a species missing
+/// from `z_valence` from electrons which do not fill a whole number of bands.
+pub fn num_occupied(
+    nscf: &PwInput,
+    z_valence: &HashMap<String, f64>,
+    tot_magnetization: Option<f64>,
+) -> Result<OccupiedBands, Error> {
+    let species = nscf.atomic_positions.coordinates.iter().map(|c| c.species.as_str());
+    let num_electrons = num_valence_electrons(species, z_valence)?;
+
+    occupied_bands(num_electrons, &nscf.system.spin_type, tot_magnetization)
+}
+
+/// Total valence charge of atoms of the given `species`, where the valence charge of each
+/// species is given in `z_valence`. Gives `Error::UnknownValence` for a species which is
+/// missing from `z_valence`.
+pub fn num_valence_electrons<'a, I>(species: I, z_valence: &HashMap<String, f64>)
+    -> Result<f64, Error>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    species.into_iter().try_fold(0.0, |total, s| match z_valence.get(s) {
+        Some(z) => Ok(total + z),
+        None => Err(Error::UnknownValence(s.to_string())),
+    })
+}
+
+/// Occupied bands given `num_electrons` valence electrons per unit cell, as described
+/// for `num_occupied`. Gives `Error::Occupation` if the electrons do not exactly fill a
+/// whole number of bands (in each spin channel), and `Error::NoMagnetization` if
+/// `spin_type` is collinear spin-polarized and `tot_magnetization` is not given.
+pub fn occupied_bands(
+    num_electrons: f64,
+    spin_type: &Option<SpinType>,
+    tot_magnetization: Option<f64>,
+) -> Result<OccupiedBands, Error> {
+    match *spin_type {
+        Some(SpinType::CollinearPolarized) => {
+            let m = tot_magnetization.ok_or(Error::NoMagnetization)?;
+            // Each spin channel holds one electron per band, so the `up` channel holds
+            // (N + M) / 2 bands: the number of bands given by N + M non-polarized electrons.
+            let up = num_occupied_bands(num_electrons + m, false);
+            let down = num_occupied_bands(num_electrons - m, false);
+
+            match (up, down) {
+                (Some(up), Some(down)) => Ok(OccupiedBands::Collinear { up, down }),
+                _ => Err(Error::Occupation),
+            }
+        }
+        Some(SpinType::Noncollinear { .. }) => num_occupied_bands(num_electrons, true)
+            .map(OccupiedBands::Bands)
+            .ok_or(Error::Occupation),
+        Some(SpinType::NonPolarized) | None => num_occupied_bands(num_electrons, false)
+            .map(OccupiedBands::Bands)
+            .ok_or(Error::Occupation),
+    }
+}
+
+/// Valence charge `z_valence` of the pseudopotential in the UPF file at `file_path`.
+pub fn read_z_valence<P: AsRef<Path>>(file_path: P) -> Result<f64, Error> {
+    let mut contents = String::new();
+    File::open(file_path)?.read_to_string(&mut contents)?;
+
+    parse_z_valence(&contents).ok_or(Error::ZValence)
+}
+
+/// Valence charge given in the contents of a UPF file: the `z_valence` attribute of
+/// `PP_HEADER` (UPF v2) or the header line ending in `Z valence` (UPF v1).
+pub fn parse_z_valence(contents: &str) -> Option<f64> {
+    let parse = |s: &str| s.trim().replace(|c| c == 'd' || c == 'D', "e").parse().ok();
+
+    if let Some(start) = contents.find("z_valence") {
+        let rest = contents[start + "z_valence".len()..].trim_start().trim_start_matches('=');
+        let value = rest.trim_start().trim_start_matches('"');
+        let end = value.find('"')?;
+
+        return parse(&value[..end]);
+    }
+
+    contents
+        .lines()
+        .find(|line| line.trim_end().ends_with("Z valence"))
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(parse)
+}
+
+/// Number of occupied bands given `num_electrons` valence electrons per unit cell. Each
+/// band holds one electron if `spinors` is true and two electrons otherwise. Returns `None`
+/// if the electrons do not exactly fill a whole number of bands.
+pub fn num_occupied_bands(num_electrons: f64, spinors: bool) -> Option<u64> {
+    let per_band = if spinors { 1.0 } else { 2.0 };
+    let bands = num_electrons / per_band;
+
+    if bands >= 0.0 && (bands - bands.round()).abs() < 1e-6 {
+        Some(bands.round() as u64)
+    } else {
+        None
+    }
+}
+
+/// Energy of one Rydberg in eV.
+pub const RYDBERG_TO_EV: f64 = 13.605693009;
+
+/// Number of smearing widths by which the default outer window extends beyond the
+/// frozen window.
+pub const SMEARING_WINDOW_FACTOR: f64 = 10.0;
+
+/// Fill in the outer disentanglement window bounds which are not given in `dis`, using the
+/// smearing width `smearing_size` (in Ry, as given to pw.x) of the nscf calculation.
+///
+/// States within a few smearing widths of the frozen window are partially occupied and
+/// contribute to the same physics as the frozen states, so the outer window must include
+/// at least these states. The missing bounds are set to
+/// `dis_froz_min - SMEARING_WINDOW_FACTOR * smearing_size` and
+/// `dis_froz_max + SMEARING_WINDOW_FACTOR * smearing_size`, with `smearing_size`
+/// converted to eV. Bounds which are given in `dis`, or whose frozen window bound is not
+/// given, are kept unchanged.
+pub fn smearing_outer_window(dis: &Disentanglement, smearing_size: f64) -> Disentanglement {
+    let margin = SMEARING_WINDOW_FACTOR * smearing_size * RYDBERG_TO_EV;
+
+    let mut dis = dis.clone();
+    if dis.dis_win_min.is_none() {
+        dis.dis_win_min = dis.dis_froz_min.map(|froz_min| froz_min - margin);
+    }
+    if dis.dis_win_max.is_none() {
+        dis.dis_win_max = dis.dis_froz_max.map(|froz_max| froz_max + margin);
+    }
+
+    dis
+}
+
+/// Construct the pair of Wannier90 inputs for the spin up and spin down channels of a
+/// collinear spin-polarized `nscf` calculation. Each channel must be Wannierized separately,
+/// so the two inputs should be written with distinct seednames.
+///
+/// pw.x uses the same `nbnd` for both spin channels, so both inputs are given the same
+/// `num_bands`. The number of bands in the energy window of interest may still differ
+/// between the channels; in that case, adjust `num_bands`, `exclude_bands`, or the
+/// disentanglement windows of each returned input as needed.
+pub fn w90_input_collinear(
+    nscf: &PwInput,
+    num_wann: u64,
+    mlwf_iteration_mode: &MLWFIterationMode,
+    disentanglement: &Disentanglement,
+    projection_units: Option<W90LatticeUnits>,
+    projections: Vec<Projection>,
+) -> Result<(W90Input, W90Input), Error> {
+    match nscf.system.spin_type {
+        Some(SpinType::CollinearPolarized) => {}
+        _ => return Err(Error::NotCollinear),
+    };
+
+    let mut up = w90_input(
+        nscf,
         num_wann,
-        write_hr: Some(true),
-        mlwf_iteration_mode: mlwf_iteration_mode.clone(),
-        disentanglement: Some(disentanglement.clone()),
-        spinors,
+        mlwf_iteration_mode,
+        disentanglement,
         projection_units,
         projections,
-        unit_cell_cart,
-        positions,
-        k_points,
-    })
+    )?;
+    let mut down = up.clone();
+
+    up.spin = Some(SpinChannel::Up);
+    down.spin = Some(SpinChannel::Down);
+
+    Ok((up, down))
 }
 
-#[derive(Fail, Debug)]
+/// Parameters of a full SCF -> NSCF -> Wannier90 workflow starting from `scf`.
+///
+/// The number of bands is given once, as `num_bands`, and propagated to the `nbnd` of the
+/// nscf and bands calculations and to the Wannier90 `num_bands`. `NumBands::Auto` is
+/// resolved from the `num_wann` given here.
+#[derive(Debug, Clone)]
+pub struct Workflow {
+    pub scf: PwInput,
+    /// `prefix` and `outdir` used by pw.x, which pw2wannier90.x must match.
+    pub prefix: String,
+    pub outdir: String,
+    pub seedname: String,
+    pub diago_thr_init: f64,
+    pub num_bands: NumBands,
+    pub smearing_type: Smearing,
+    pub smearing_size: f64,
+    pub nscf_nk: [u64; 3],
+    /// If given, also generate a bands calculation along these k-points.
+    pub bands_kpoints: Option<KPoints>,
+    pub num_wann: u64,
+    pub mlwf_iteration_mode: MLWFIterationMode,
+    pub disentanglement: Disentanglement,
+    pub projection_units: Option<W90LatticeUnits>,
+    pub projections: Vec<Projection>,
+}
+
+/// Inputs generated by `Workflow::generate`, in the order in which the corresponding
+/// programs must be run: pw.x (nscf), wannier90.x -pp, pw2wannier90.x, wannier90.x.
+/// The bands calculation may be run any time after the nscf calculation.
+#[derive(Debug, Clone)]
+pub struct WorkflowInputs {
+    pub nscf: PwInput,
+    pub bands: Option<PwInput>,
+    pub w90: W90Input,
+    pub pw2wannier90: Pw2Wannier90Input,
+}
+
+impl Workflow {
+    pub fn generate(&self) -> Result<WorkflowInputs, Error> {
+        if self.num_wann > self.num_bands.value(self.num_wann) {
+            return Err(Error::NumWannExceedsNumBands);
+        }
+
+        let nscf = nscf_input(
+            &self.scf,
+            self.diago_thr_init,
+            &self.num_bands,
+            self.num_wann,
+            &self.smearing_type,
+            self.smearing_size,
+            self.nscf_nk,
+        )?;
+
+        let bands = match self.bands_kpoints {
+            Some(ref bands_kpoints) => Some(bands_input(&nscf, bands_kpoints)?),
+            None => None,
+        };
+
+        let w90 = w90_input(
+            &nscf,
+            self.num_wann,
+            &self.mlwf_iteration_mode,
+            &self.disentanglement,
+            self.projection_units.clone(),
+            self.projections.clone(),
+        )?;
+
+        let pw2wannier90 = Pw2Wannier90Input {
+            prefix: self.prefix.clone(),
+            outdir: self.outdir.clone(),
+            seedname: self.seedname.clone(),
+            write_mmn: true,
+            write_amn: true,
+            write_unk: false,
+            write_spn: w90.spinors,
+            spin_component: None,
+        };
+
+        Ok(WorkflowInputs {
+            nscf,
+            bands,
+            w90,
+            pw2wannier90,
+        })
+    }
+}
+
+/// Input for pw2wannier90.x, which computes the overlaps needed by Wannier90 from the
+/// nscf wavefunctions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pw2Wannier90Input {
+    pub prefix: String,
+    pub outdir: String,
+    pub seedname: String,
+    pub write_mmn: bool,
+    pub write_amn: bool,
+    pub write_unk: bool,
+    /// Write `seedname.spn`, needed for spin-resolved postw90 calculations with spinors.
+    pub write_spn: bool,
+    /// Spin channel of a collinear spin-polarized calculation to compute the overlaps for.
+    /// Each channel given by `w90_input_collinear` needs its own pw2wannier90.x input,
+    /// with the matching `spin_component` and seedname.
+    pub spin_component: Option<SpinChannel>,
+}
+
+impl Pw2Wannier90Input {
+    pub fn make_input_file(&self) -> String {
+        let mut lines = vec![String::from("&inputpp")];
+        lines.push(format!("  prefix = '{}'", self.prefix));
+        lines.push(format!("  outdir = '{}'", self.outdir));
+        lines.push(format!("  seedname = '{}'", self.seedname));
+        lines.push(format!("  write_mmn = {}", fortran_bool(self.write_mmn)));
+        lines.push(format!("  write_amn = {}", fortran_bool(self.write_amn)));
+        lines.push(format!("  write_unk = {}", fortran_bool(self.write_unk)));
+        if self.write_spn {
+            lines.push(format!("  write_spn = {}", fortran_bool(self.write_spn)));
+        }
+        if let Some(ref spin) = self.spin_component {
+            lines.push(format!("  spin_component = '{}'", spin.value()));
+        }
+        lines.push(String::from("/"));
+
+        lines.join("\n") + "\n"
+    }
+}
+
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "Unexpected type of calculation input.")] WrongCalculation,
-    #[fail(display = "Must have `nosym = true` in nscf calculation.")] NoSym,
-    #[fail(display = "Must specify `nbnd` in nscf calculation.")] NumBands,
-    #[fail(display = "Must have `KPoints::CrystalUniform` in nscf calculation.")] WrongKPointsNscf,
-    #[fail(display = "Must input `KPoints::CrystalBands`.")] WrongKPointsBands,
-    #[fail(display = "`CrystalSG` positions unsupported.")] CrystalSG,
+    WrongCalculation,
+    NoSym,
+    NumBands,
+    WrongKPointsNscf,
+    WrongKPointsBands,
+    CrystalSG,
+    NotCollinear,
+    NumWannExceedsNumBands,
+    Eig(EigError),
+    EmptyEig,
+    UnknownValence(String),
+    Occupation,
+    NoMagnetization,
+    ZValence,
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WrongCalculation => write!(f, "Unexpected type of calculation input."),
+            Error::NoSym => write!(f, "Must have `nosym = true` in nscf calculation."),
+            Error::NumBands => write!(f, "Must specify `nbnd` in nscf calculation."),
+            Error::WrongKPointsNscf => {
+                write!(f, "Must have `KPoints::CrystalUniform` in nscf calculation.")
+            }
+            Error::WrongKPointsBands => write!(f, "Must input `KPoints::CrystalBands`."),
+            Error::CrystalSG => write!(f, "`CrystalSG` positions unsupported."),
+            Error::NotCollinear => {
+                write!(f, "Must have `SpinType::CollinearPolarized` in nscf calculation.")
+            }
+            Error::NumWannExceedsNumBands => write!(f, "`num_wann` must not exceed `num_bands`."),
+            Error::Eig(ref e) => write!(f, "{}", e),
+            Error::EmptyEig => write!(f, "`.eig` file contains no bands."),
+            Error::UnknownValence(ref species) => {
+                write!(f, "No valence charge given for species `{}`.", species)
+            }
+            Error::Occupation => write!(f, "Electrons do not fill a whole number of bands."),
+            Error::NoMagnetization => {
+                write!(f, "Must give the total magnetization for a collinear calculation.")
+            }
+            Error::ZValence => write!(f, "No `z_valence` found in pseudopotential file."),
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// `EigError` implements `Fail` but not `std::error::Error`, so it is not given as the
+// source of `Error::Eig`; its message is included in the display instead.
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<EigError> for Error {
+    fn from(e: EigError) -> Error {
+        Error::Eig(e)
+    }
 }
 
 fn scale_cell(cell: [[f64; 3]; 3], alat: f64) -> [[f64; 3]; 3] {
@@ -189,6 +739,7 @@ fn scale_coords(coordinates: &Vec<PwAtomCoordinate>, alat: f64) -> Vec<W90AtomCo
         .iter()
         .map(|c| W90AtomCoordinate {
             species: c.species.clone(),
+            label: None,
             r: [alat * c.r[0], alat * c.r[1], alat * c.r[2]],
         })
         .collect()
@@ -199,6 +750,7 @@ fn map_coords(coordinates: &Vec<PwAtomCoordinate>) -> Vec<W90AtomCoordinate> {
         .iter()
         .map(|c| W90AtomCoordinate {
             species: c.species.clone(),
+            label: None,
             r: c.r,
         })
         .collect()
@@ -0,0 +1,965 @@
+use std::path::Path;
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use num_complex::Complex64;
+use tokens::{next_complex, next_parsed, next_r_blocks, next_r_vector, parse_token, RBlockError,
+             TokenError};
+
+/// Band energies along a path in k-space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandStructure {
+    /// `energies[n][k]` is the energy of band `n` at the k-point `k` along the path.
+    pub energies: Vec<Vec<f64>>,
+    /// Cumulative distance along the path at each k-point.
+    pub distances: Vec<f64>,
+    /// k-points along the path in lattice coordinates.
+    pub kpoints: Vec<[f64; 3]>,
+}
+
+/// Read the `seedname_band.dat` and `seedname_band.kpt` files written by Wannier90 when
+/// `bands_plot = .true.`.
+pub fn read_band_dat<P: AsRef<Path>, Q: AsRef<Path>>(
+    dat_path: P,
+    kpt_path: Q,
+) -> Result<BandStructure, BandError> {
+    let dat = read_text(dat_path)?;
+    let kpt = read_text(kpt_path)?;
+
+    parse_band_dat(&dat, &kpt)
+}
+
+/// Parse the contents of `seedname_band.dat` and `seedname_band.kpt`.
+///
+/// `band.dat` holds one `distance energy` pair per line, with each band given as a block
+/// of lines separated from the next band by a blank line. `band.kpt` gives the number of
+/// k-points on its first line, followed by one `k1 k2 k3 weight` line per k-point.
+pub fn parse_band_dat(dat: &str, kpt: &str) -> Result<BandStructure, BandError> {
+    let mut energies: Vec<Vec<f64>> = Vec::new();
+    let mut distances: Vec<f64> = Vec::new();
+    let mut band_distances: Vec<f64> = Vec::new();
+    let mut band_energies: Vec<f64> = Vec::new();
+
+    for line in dat.lines().chain(Some("")) {
+        let vals = parse_floats(line).map_err(BandError::InvalidNumber)?;
+
+        if vals.len() == 0 {
+            if band_energies.len() == 0 {
+                continue;
+            }
+
+            if energies.len() == 0 {
+                distances = band_distances.clone();
+            } else if band_energies.len() != distances.len() {
+                return Err(BandError::BandLength {
+                    band: energies.len() + 1,
+                    len: band_energies.len(),
+                    expected: distances.len(),
+                });
+            }
+
+            energies.push(band_energies);
+            band_energies = Vec::new();
+            band_distances = Vec::new();
+        } else if vals.len() == 2 {
+            band_distances.push(vals[0]);
+            band_energies.push(vals[1]);
+        } else {
+            return Err(BandError::UnexpectedLine(String::from(line)));
+        }
+    }
+
+    let mut kpt_lines = kpt.lines().filter(|l| l.trim().len() > 0);
+
+    let num_kpts: usize = match kpt_lines.next() {
+        Some(line) => line.trim()
+            .parse()
+            .map_err(|_| BandError::InvalidNumber(String::from(line)))?,
+        None => return Err(BandError::UnexpectedEof),
+    };
+
+    let mut kpoints = Vec::with_capacity(num_kpts);
+    for line in kpt_lines.take(num_kpts) {
+        let vals = parse_floats(line).map_err(BandError::InvalidNumber)?;
+        if vals.len() < 3 {
+            return Err(BandError::UnexpectedLine(String::from(line)));
+        }
+        kpoints.push([vals[0], vals[1], vals[2]]);
+    }
+
+    if kpoints.len() != num_kpts || kpoints.len() != distances.len() {
+        return Err(BandError::KPointCount {
+            num_kpts: kpoints.len(),
+            expected: distances.len(),
+        });
+    }
+
+    Ok(BandStructure {
+        energies,
+        distances,
+        kpoints,
+    })
+}
+
+#[derive(Fail, Debug)]
+pub enum BandError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file.")] UnexpectedEof,
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected line `{}`.", _0)] UnexpectedLine(String),
+    #[fail(display = "Band {} has {} points, but expected {}.", band, len, expected)]
+    BandLength { band: usize, len: usize, expected: usize },
+    #[fail(display = "Got {} k-points, but bands have {} points.", num_kpts, expected)]
+    KPointCount { num_kpts: usize, expected: usize },
+}
+
+impl From<io::Error> for BandError {
+    fn from(e: io::Error) -> BandError {
+        BandError::Io(e)
+    }
+}
+
+/// Projections `A_mn(k) = <psi_mk|g_n>` of the Bloch states onto the initial guesses for
+/// the Wannier functions, as given in `seedname.amn`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Amn {
+    pub num_bands: usize,
+    pub num_kpts: usize,
+    pub num_wann: usize,
+    /// `a[k][m][n]` is `A_mn` at k-point `k`, with zero-based indices.
+    pub a: Vec<Vec<Vec<Complex64>>>,
+}
+
+pub fn read_amn<P: AsRef<Path>>(file_path: P) -> Result<Amn, AmnError> {
+    let text = read_text(file_path)?;
+
+    parse_amn(&text)
+}
+
+/// Parse the contents of `seedname.amn`: a comment line, a `num_bands num_kpts num_wann`
+/// line, and then one `m n k Re(A) Im(A)` line per matrix element, with one-based indices.
+pub fn parse_amn(text: &str) -> Result<Amn, AmnError> {
+    let mut lines = text.lines().skip(1).filter(|l| l.trim().len() > 0);
+
+    let dims = match lines.next() {
+        Some(line) => parse_usizes(line).map_err(AmnError::InvalidNumber)?,
+        None => return Err(AmnError::UnexpectedEof),
+    };
+    if dims.len() != 3 {
+        return Err(AmnError::InvalidHeader);
+    }
+    let (num_bands, num_kpts, num_wann) = (dims[0], dims[1], dims[2]);
+
+    let mut a = vec![vec![vec![Complex64::new(0.0, 0.0); num_wann]; num_bands]; num_kpts];
+    let mut count = 0;
+
+    for line in lines {
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        if toks.len() != 5 {
+            return Err(AmnError::UnexpectedLine(String::from(line)));
+        }
+
+        let m: usize = parse_token(toks[0]).map_err(AmnError::InvalidNumber)?;
+        let n: usize = parse_token(toks[1]).map_err(AmnError::InvalidNumber)?;
+        let k: usize = parse_token(toks[2]).map_err(AmnError::InvalidNumber)?;
+        if m < 1 || m > num_bands || n < 1 || n > num_wann || k < 1 || k > num_kpts {
+            return Err(AmnError::IndexOutOfRange(String::from(line)));
+        }
+
+        let re = parse_token(toks[3]).map_err(AmnError::InvalidNumber)?;
+        let im = parse_token(toks[4]).map_err(AmnError::InvalidNumber)?;
+        a[k - 1][m - 1][n - 1] = Complex64::new(re, im);
+        count += 1;
+    }
+
+    let expected = num_bands * num_wann * num_kpts;
+    if count != expected {
+        return Err(AmnError::ElementCount { count, expected });
+    }
+
+    Ok(Amn {
+        num_bands,
+        num_kpts,
+        num_wann,
+        a,
+    })
+}
+
+#[derive(Fail, Debug)]
+pub enum AmnError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file.")] UnexpectedEof,
+    #[fail(display = "Expected `num_bands num_kpts num_wann` header.")] InvalidHeader,
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected line `{}`.", _0)] UnexpectedLine(String),
+    #[fail(display = "Index out of range in line `{}`.", _0)] IndexOutOfRange(String),
+    #[fail(display = "Got {} matrix elements, but expected {}.", count, expected)]
+    ElementCount { count: usize, expected: usize },
+}
+
+impl From<io::Error> for AmnError {
+    fn from(e: io::Error) -> AmnError {
+        AmnError::Io(e)
+    }
+}
+
+/// Overlaps `M_mn(k, b) = <u_mk|u_n,k+b>` between the periodic parts of Bloch states at
+/// neighboring k-points, as given in `seedname.mmn`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mmn {
+    pub num_bands: usize,
+    pub num_kpts: usize,
+    pub nntot: usize,
+    /// `neighbors[k][nn]` gives the zero-based index of the `nn`th neighbor `k+b` of
+    /// k-point `k`, together with the reciprocal lattice vector `G` (in lattice coordinates)
+    /// which must be added to that k-point to obtain `k+b`.
+    pub neighbors: Vec<Vec<(usize, [i64; 3])>>,
+    /// `m[k][nn][m][n]` is `M_mn` between k-point `k` and its neighbor `neighbors[k][nn]`,
+    /// with zero-based band indices.
+    pub m: Vec<Vec<Vec<Vec<Complex64>>>>,
+}
+
+pub fn read_mmn<P: AsRef<Path>>(file_path: P) -> Result<Mmn, MmnError> {
+    let text = read_text(file_path)?;
+
+    parse_mmn(&text)
+}
+
+/// Parse the contents of `seedname.mmn`: a comment line, a `num_bands num_kpts nntot`
+/// line, and then `num_kpts * nntot` blocks. Each block starts with a `k k+b G1 G2 G3`
+/// line with one-based k-point indices, followed by `num_bands^2` lines of `Re(M) Im(M)`
+/// with the first band index varying fastest.
+pub fn parse_mmn(text: &str) -> Result<Mmn, MmnError> {
+    let mut lines = text.lines().skip(1).filter(|l| l.trim().len() > 0);
+
+    let dims = match lines.next() {
+        Some(line) => parse_usizes(line).map_err(MmnError::InvalidNumber)?,
+        None => return Err(MmnError::UnexpectedEof),
+    };
+    if dims.len() != 3 {
+        return Err(MmnError::InvalidHeader);
+    }
+    let (num_bands, num_kpts, nntot) = (dims[0], dims[1], dims[2]);
+
+    let mut neighbors = vec![Vec::with_capacity(nntot); num_kpts];
+    let mut m = vec![Vec::with_capacity(nntot); num_kpts];
+
+    for _ in 0..(num_kpts * nntot) {
+        let line = lines.next().ok_or(MmnError::UnexpectedEof)?;
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        if toks.len() != 5 {
+            return Err(MmnError::UnexpectedLine(String::from(line)));
+        }
+
+        let k: usize = parse_token(toks[0]).map_err(MmnError::InvalidNumber)?;
+        let kb: usize = parse_token(toks[1]).map_err(MmnError::InvalidNumber)?;
+        let mut g = [0; 3];
+        for i in 0..3 {
+            g[i] = parse_token(toks[2 + i]).map_err(MmnError::InvalidNumber)?;
+        }
+
+        if k < 1 || k > num_kpts || kb < 1 || kb > num_kpts {
+            return Err(MmnError::IndexOutOfRange(String::from(line)));
+        }
+        if neighbors[k - 1].len() == nntot {
+            return Err(MmnError::NeighborCount { k });
+        }
+
+        let mut m_kb = vec![vec![Complex64::new(0.0, 0.0); num_bands]; num_bands];
+        for n in 0..num_bands {
+            for row in 0..num_bands {
+                let line = lines.next().ok_or(MmnError::UnexpectedEof)?;
+                let vals = parse_floats(line).map_err(MmnError::InvalidNumber)?;
+                if vals.len() != 2 {
+                    return Err(MmnError::UnexpectedLine(String::from(line)));
+                }
+                m_kb[row][n] = Complex64::new(vals[0], vals[1]);
+            }
+        }
+
+        neighbors[k - 1].push((kb - 1, g));
+        m[k - 1].push(m_kb);
+    }
+
+    if let Some(line) = lines.next() {
+        return Err(MmnError::UnexpectedLine(String::from(line)));
+    }
+
+    Ok(Mmn {
+        num_bands,
+        num_kpts,
+        nntot,
+        neighbors,
+        m,
+    })
+}
+
+#[derive(Fail, Debug)]
+pub enum MmnError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file.")] UnexpectedEof,
+    #[fail(display = "Expected `num_bands num_kpts nntot` header.")] InvalidHeader,
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected line `{}`.", _0)] UnexpectedLine(String),
+    #[fail(display = "Index out of range in line `{}`.", _0)] IndexOutOfRange(String),
+    #[fail(display = "k-point {} has more than `nntot` neighbors.", k)]
+    NeighborCount { k: usize },
+}
+
+impl From<io::Error> for MmnError {
+    fn from(e: io::Error) -> MmnError {
+        MmnError::Io(e)
+    }
+}
+
+/// Tight-binding model written to `seedname_tb.dat` when `write_tb = .true.`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TbModel {
+    /// Lattice vectors (rows) in Angstrom.
+    pub lattice: [[f64; 3]; 3],
+    pub num_wann: usize,
+    /// Lattice vectors `R` in units of the lattice vectors of the unit cell.
+    pub r_vectors: Vec<[i64; 3]>,
+    /// Degeneracy of each Wigner-Seitz point in `r_vectors`.
+    pub degeneracies: Vec<u64>,
+    /// `hoppings[r][m][n]` is `H_mn(R) = <0m|H|Rn>` in eV for `R = r_vectors[r]`, with
+    /// zero-based `m`, `n`.
+    pub hoppings: Vec<Vec<Vec<Complex64>>>,
+    /// `positions[r][m][n][i]` is the Cartesian component `i` of `<0m|r|Rn>` in Angstrom.
+    pub positions: Vec<Vec<Vec<[Complex64; 3]>>>,
+}
+
+pub fn read_tb<P: AsRef<Path>>(file_path: P) -> Result<TbModel, TbError> {
+    let text = read_text(file_path)?;
+
+    parse_tb(&text)
+}
+
+/// Parse the contents of `seedname_tb.dat`: a comment line, the three lattice vectors,
+/// `num_wann`, `nrpts`, the `nrpts` degeneracies, and then two sets of `nrpts` blocks.
+/// Each block starts with an `R1 R2 R3` line. It is followed by `num_wann^2` lines of
+/// `m n Re(H) Im(H)` in the first set, and of `m n Re(x) Im(x) Re(y) Im(y) Re(z) Im(z)`
+/// in the second set, with `m` varying fastest.
+pub fn parse_tb(text: &str) -> Result<TbModel, TbError> {
+    let mut tokens = text.lines().skip(1).flat_map(|l| l.split_whitespace());
+
+    let mut lattice = [[0.0; 3]; 3];
+    for a in lattice.iter_mut() {
+        for x in a.iter_mut() {
+            *x = next_parsed(&mut tokens)?;
+        }
+    }
+
+    let num_wann: usize = next_parsed(&mut tokens)?;
+    let nrpts: usize = next_parsed(&mut tokens)?;
+
+    let mut degeneracies = Vec::with_capacity(nrpts);
+    for _ in 0..nrpts {
+        degeneracies.push(next_parsed(&mut tokens)?);
+    }
+
+    let zero = Complex64::new(0.0, 0.0);
+    let mut r_vectors = Vec::with_capacity(nrpts);
+    let mut hoppings = Vec::with_capacity(nrpts);
+
+    for _ in 0..nrpts {
+        let r = next_r_vector(&mut tokens)?;
+        let mut h_r = vec![vec![zero; num_wann]; num_wann];
+
+        for n in 0..num_wann {
+            for m in 0..num_wann {
+                check_mn(&mut tokens, r, m, n)?;
+                h_r[m][n] = next_complex(&mut tokens)?;
+            }
+        }
+
+        r_vectors.push(r);
+        hoppings.push(h_r);
+    }
+
+    let mut positions = Vec::with_capacity(nrpts);
+
+    for &expected_r in r_vectors.iter() {
+        let r = next_r_vector(&mut tokens)?;
+        if r != expected_r {
+            return Err(TbError::RVectorMismatch {
+                expected: expected_r,
+                found: r,
+            });
+        }
+        let mut r_r = vec![vec![[zero; 3]; num_wann]; num_wann];
+
+        for n in 0..num_wann {
+            for m in 0..num_wann {
+                check_mn(&mut tokens, r, m, n)?;
+                for i in 0..3 {
+                    r_r[m][n][i] = next_complex(&mut tokens)?;
+                }
+            }
+        }
+
+        positions.push(r_r);
+    }
+
+    if let Some(tok) = tokens.next() {
+        return Err(TbError::UnexpectedToken(String::from(tok)));
+    }
+
+    Ok(TbModel {
+        lattice,
+        num_wann,
+        r_vectors,
+        degeneracies,
+        hoppings,
+        positions,
+    })
+}
+
+/// Read the one-based `m n` indices of a matrix element line and check that they match
+/// the zero-based `m`, `n` expected at this point in the block for `R = r`.
+fn check_mn<'a, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+    r: [i64; 3],
+    m: usize,
+    n: usize,
+) -> Result<(), TbError> {
+    let this_m: usize = next_parsed(tokens)?;
+    let this_n: usize = next_parsed(tokens)?;
+
+    if this_m != m + 1 || this_n != n + 1 {
+        return Err(TbError::UnexpectedIndex {
+            r,
+            m: this_m,
+            n: this_n,
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Fail, Debug)]
+pub enum TbError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file.")] UnexpectedEof,
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected token `{}` after the last block.", _0)] UnexpectedToken(String),
+    #[fail(display = "Unexpected matrix element R = {:?}, m = {}, n = {}.", r, m, n)]
+    UnexpectedIndex { r: [i64; 3], m: usize, n: usize },
+    #[fail(display = "Position block R = {:?} does not match Hamiltonian block R = {:?}.", found,
+           expected)]
+    RVectorMismatch { expected: [i64; 3], found: [i64; 3] },
+}
+
+impl From<io::Error> for TbError {
+    fn from(e: io::Error) -> TbError {
+        TbError::Io(e)
+    }
+}
+
+impl From<TokenError> for TbError {
+    fn from(e: TokenError) -> TbError {
+        match e {
+            TokenError::Eof => TbError::UnexpectedEof,
+            TokenError::Invalid(tok) => TbError::InvalidNumber(tok),
+        }
+    }
+}
+
+/// Position operator matrix elements written to `seedname_r.dat` when
+/// `write_rmn = .true.`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionOperator {
+    pub num_wann: usize,
+    /// Lattice vectors `R` in units of the lattice vectors of the unit cell.
+    pub r_vectors: Vec<[i64; 3]>,
+    /// `positions[r][m][n][i]` is the Cartesian component `i` of `<0m|r|Rn>` in Angstrom.
+    pub positions: Vec<Vec<Vec<[Complex64; 3]>>>,
+}
+
+pub fn read_rmn<P: AsRef<Path>>(file_path: P) -> Result<PositionOperator, RmnError> {
+    let text = read_text(file_path)?;
+
+    parse_rmn(&text)
+}
+
+/// Parse the contents of `seedname_r.dat`: a comment line, `num_wann`, `nrpts`, and then
+/// `nrpts * num_wann^2` lines of `R1 R2 R3 m n Re(x) Im(x) Re(y) Im(y) Re(z) Im(z)`,
+/// with `m` varying fastest within each R-vector.
+pub fn parse_rmn(text: &str) -> Result<PositionOperator, RmnError> {
+    let mut tokens = text.lines().skip(1).flat_map(|l| l.split_whitespace());
+
+    let num_wann: usize = next_parsed(&mut tokens)?;
+    let nrpts: usize = next_parsed(&mut tokens)?;
+
+    let (r_vectors, positions) = next_r_blocks(&mut tokens, nrpts, num_wann, |tokens| {
+        Ok([next_complex(tokens)?, next_complex(tokens)?, next_complex(tokens)?])
+    })?;
+
+    if let Some(tok) = tokens.next() {
+        return Err(RmnError::UnexpectedToken(String::from(tok)));
+    }
+
+    Ok(PositionOperator {
+        num_wann,
+        r_vectors,
+        positions,
+    })
+}
+
+#[derive(Fail, Debug)]
+pub enum RmnError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file.")] UnexpectedEof,
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected token `{}` after the last R-vector.", _0)]
+    UnexpectedToken(String),
+    #[fail(display = "Unexpected matrix element R = {:?}, m = {}, n = {}.", r, m, n)]
+    UnexpectedIndex { r: [i64; 3], m: usize, n: usize },
+}
+
+impl From<io::Error> for RmnError {
+    fn from(e: io::Error) -> RmnError {
+        RmnError::Io(e)
+    }
+}
+
+impl From<TokenError> for RmnError {
+    fn from(e: TokenError) -> RmnError {
+        match e {
+            TokenError::Eof => RmnError::UnexpectedEof,
+            TokenError::Invalid(tok) => RmnError::InvalidNumber(tok),
+        }
+    }
+}
+
+impl From<RBlockError> for RmnError {
+    fn from(e: RBlockError) -> RmnError {
+        match e {
+            RBlockError::Token(e) => RmnError::from(e),
+            RBlockError::UnexpectedIndex { r, m, n } => RmnError::UnexpectedIndex { r, m, n },
+        }
+    }
+}
+
+/// Unitary matrices written to `seedname_u.mat`, or the rectangular matrices written to
+/// `seedname_u_dis.mat`, when `write_u_matrices = .true.`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UMatrices {
+    pub num_kpts: usize,
+    pub num_wann: usize,
+    /// Number of rows of each matrix: `num_bands` for `seedname_u_dis.mat`, or `num_wann`
+    /// for `seedname_u.mat`.
+    pub num_bands: usize,
+    /// k-points in lattice coordinates.
+    pub kpoints: Vec<[f64; 3]>,
+    /// `u[k][m][n]` is `U_mn(k)`, with zero-based indices.
+    pub u: Vec<Vec<Vec<Complex64>>>,
+}
+
+/// Read the gauge matrices `U(k)` from `seedname_u.mat`.
+pub fn read_umat<P: AsRef<Path>>(file_path: P) -> Result<UMatrices, UMatError> {
+    let text = read_text(file_path)?;
+
+    parse_umat(&text)
+}
+
+/// Read the disentanglement matrices `U_dis(k)` from `seedname_u_dis.mat`.
+pub fn read_u_dis_mat<P: AsRef<Path>>(file_path: P) -> Result<UMatrices, UMatError> {
+    let text = read_text(file_path)?;
+
+    parse_u_dis_mat(&text)
+}
+
+/// Parse the contents of `seedname_u.mat`, which has the format described for
+/// `parse_u_dis_mat` with `num_bands` equal to `num_wann`.
+pub fn parse_umat(text: &str) -> Result<UMatrices, UMatError> {
+    let u = parse_u_dis_mat(text)?;
+    if u.num_bands != u.num_wann {
+        return Err(UMatError::NotSquare {
+            num_wann: u.num_wann,
+            num_bands: u.num_bands,
+        });
+    }
+
+    Ok(u)
+}
+
+/// Parse the contents of `seedname_u_dis.mat`: a comment line, a
+/// `num_kpts num_wann num_bands` line, and then one block per k-point. Each block gives
+/// the k-point `k1 k2 k3` followed by the `num_bands * num_wann` elements `Re(U) Im(U)`,
+/// with the band index varying fastest.
+pub fn parse_u_dis_mat(text: &str) -> Result<UMatrices, UMatError> {
+    let mut tokens = text.lines().skip(1).flat_map(|l| l.split_whitespace());
+
+    let num_kpts: usize = next_parsed(&mut tokens)?;
+    let num_wann: usize = next_parsed(&mut tokens)?;
+    let num_bands: usize = next_parsed(&mut tokens)?;
+
+    let zero = Complex64::new(0.0, 0.0);
+    let mut kpoints = Vec::with_capacity(num_kpts);
+    let mut u = Vec::with_capacity(num_kpts);
+
+    for _ in 0..num_kpts {
+        kpoints.push([
+            next_parsed(&mut tokens)?,
+            next_parsed(&mut tokens)?,
+            next_parsed(&mut tokens)?,
+        ]);
+
+        let mut u_k = vec![vec![zero; num_wann]; num_bands];
+        for n in 0..num_wann {
+            for m in 0..num_bands {
+                u_k[m][n] = next_complex(&mut tokens)?;
+            }
+        }
+        u.push(u_k);
+    }
+
+    if let Some(tok) = tokens.next() {
+        return Err(UMatError::UnexpectedToken(String::from(tok)));
+    }
+
+    Ok(UMatrices {
+        num_kpts,
+        num_wann,
+        num_bands,
+        kpoints,
+        u,
+    })
+}
+
+#[derive(Fail, Debug)]
+pub enum UMatError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file.")] UnexpectedEof,
+    #[fail(display = "Invalid number `{}`.", _0)] InvalidNumber(String),
+    #[fail(display = "Unexpected token `{}` after the last k-point.", _0)] UnexpectedToken(String),
+    #[fail(display = "Expected square {0}x{0} matrices, but header gives {1} rows.", num_wann,
+           num_bands)]
+    NotSquare { num_wann: usize, num_bands: usize },
+}
+
+impl From<io::Error> for UMatError {
+    fn from(e: io::Error) -> UMatError {
+        UMatError::Io(e)
+    }
+}
+
+impl From<TokenError> for UMatError {
+    fn from(e: TokenError) -> UMatError {
+        match e {
+            TokenError::Eof => UMatError::UnexpectedEof,
+            TokenError::Invalid(tok) => UMatError::InvalidNumber(tok),
+        }
+    }
+}
+
+/// Length in bytes of the markers which precede and follow each record of a Fortran
+/// unformatted sequential file. This is the default for gfortran and ifort; files written
+/// with 8-byte markers (such as by old versions of g77) are not supported.
+pub const RECORD_MARKER_SIZE: usize = 4;
+
+/// Contents of the checkpoint file `seedname.chk`, which records the gauge found by
+/// Wannier90 and is read by postw90.x and for restarts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Header line recording the date and time at which the file was written.
+    pub header: String,
+    pub num_bands: usize,
+    /// One-based indices of the bands excluded from the calculation.
+    pub exclude_bands: Vec<usize>,
+    /// `real_lattice[i]` is the `i`th lattice vector, in Angstrom.
+    pub real_lattice: [[f64; 3]; 3],
+    /// `recip_lattice[i]` is the `i`th reciprocal lattice vector, in inverse Angstrom.
+    pub recip_lattice: [[f64; 3]; 3],
+    pub num_kpts: usize,
+    pub mp_grid: [usize; 3],
+    /// k-points in lattice coordinates.
+    pub kpoints: Vec<[f64; 3]>,
+    /// Number of neighbors `k+b` of each k-point.
+    pub nntot: usize,
+    pub num_wann: usize,
+    /// Stage of the calculation at which the file was written: `postdis` after
+    /// disentanglement or `postwann` after localization.
+    pub checkpoint: String,
+    pub disentanglement: Option<ChkDisentanglement>,
+    /// `u[k][m][n]` is `U_mn(k)`, with zero-based indices.
+    pub u: Vec<Vec<Vec<Complex64>>>,
+    /// `m[k][nn][m][n]` is `M_mn` between k-point `k` and its `nn`th neighbor, in the
+    /// Wannier gauge.
+    pub m: Vec<Vec<Vec<Vec<Complex64>>>>,
+    /// Wannier function centres in Cartesian coordinates, in Angstrom.
+    pub centres: Vec<[f64; 3]>,
+    /// Wannier function spreads, in Angstrom^2.
+    pub spreads: Vec<f64>,
+}
+
+/// Disentanglement data of a checkpoint file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChkDisentanglement {
+    /// Gauge-invariant part of the spread.
+    pub omega_invariant: f64,
+    /// `lwindow[k][m]` is true if band `m` is inside the outer window at k-point `k`.
+    pub lwindow: Vec<Vec<bool>>,
+    /// Number of bands inside the outer window at each k-point.
+    pub ndimwin: Vec<usize>,
+    /// `u_dis[k][m][n]` is `U^dis_mn(k)`, with `m` running over `num_bands`.
+    pub u_dis: Vec<Vec<Vec<Complex64>>>,
+}
+
+/// Read the checkpoint file `seedname.chk`, written as a little-endian Fortran unformatted
+/// sequential file with record markers of `RECORD_MARKER_SIZE` bytes.
+pub fn read_chk<P: AsRef<Path>>(file_path: P) -> Result<Checkpoint, ChkError> {
+    let mut data = Vec::new();
+    File::open(file_path)?.read_to_end(&mut data)?;
+
+    parse_chk(&data)
+}
+
+/// Parse the contents of `seedname.chk`. The records are, in order:
+///
+/// * `header` (33 characters), `num_bands`, `num_exclude_bands`, `exclude_bands`
+/// * `real_lattice`, `recip_lattice` (3x3 reals each)
+/// * `num_kpts`, `mp_grid`, `kpt_latt(3, num_kpts)`, `nntot`, `num_wann`
+/// * `checkpoint` (20 characters), `have_disentangled`
+/// * if `have_disentangled`: `omega_invariant`, `lwindow(num_bands, num_kpts)`,
+///   `ndimwin(num_kpts)`, `u_matrix_opt(num_bands, num_wann, num_kpts)`
+/// * `u_matrix(num_wann, num_wann, num_kpts)`, `m_matrix(num_wann, num_wann, nntot,
+///   num_kpts)`, `wannier_centres(3, num_wann)`, `wannier_spreads(num_wann)`
+///
+/// Arrays are stored with the first index varying fastest. Integers and logicals are 4
+/// bytes, reals are 8 bytes, and complex numbers are pairs of reals.
+pub fn parse_chk(data: &[u8]) -> Result<Checkpoint, ChkError> {
+    let mut records = Records { data };
+
+    let header = records.text("header", CHK_HEADER_LEN)?;
+    let num_bands = records.count("num_bands")?;
+    let num_exclude_bands = records.count("num_exclude_bands")?;
+    let exclude_bands = records.counts("exclude_bands", num_exclude_bands)?;
+    let real_lattice = vectors(&records.reals("real_lattice", 9)?);
+    let recip_lattice = vectors(&records.reals("recip_lattice", 9)?);
+    let num_kpts = records.count("num_kpts")?;
+    let mp_grid = records.counts("mp_grid", 3)?;
+    let kpoints = columns(&records.reals("kpt_latt", dims(&[3, num_kpts])?)?);
+    let nntot = records.count("nntot")?;
+    let num_wann = records.count("num_wann")?;
+    let checkpoint = records.text("checkpoint", CHK_CHECKPOINT_LEN)?;
+
+    let disentanglement = if records.logicals("have_disentangled", 1)?[0] {
+        let omega_invariant = records.reals("omega_invariant", 1)?[0];
+        let lwindow = records.logicals("lwindow", dims(&[num_bands, num_kpts])?)?;
+        let ndimwin = records.counts("ndimwin", num_kpts)?;
+        let u_dis = records.complexes("u_matrix_opt", dims(&[num_bands, num_wann, num_kpts])?)?;
+
+        Some(ChkDisentanglement {
+            omega_invariant,
+            lwindow: (0..num_kpts)
+                .map(|k| lwindow[k * num_bands..(k + 1) * num_bands].to_vec())
+                .collect(),
+            ndimwin,
+            u_dis: matrices(&u_dis, num_bands, num_wann, num_kpts),
+        })
+    } else {
+        None
+    };
+
+    let u = records.complexes("u_matrix", dims(&[num_wann, num_wann, num_kpts])?)?;
+    let m = records.complexes("m_matrix", dims(&[num_wann, num_wann, nntot, num_kpts])?)?;
+    let centres = columns(&records.reals("wannier_centres", dims(&[3, num_wann])?)?);
+    let spreads = records.reals("wannier_spreads", num_wann)?;
+
+    if records.data.len() > 0 {
+        return Err(ChkError::TrailingData(records.data.len()));
+    }
+
+    let m_per_k = dims(&[num_wann, num_wann, nntot])?;
+    let m = (0..num_kpts)
+        .map(|k| matrices(&m[k * m_per_k..(k + 1) * m_per_k], num_wann, num_wann, nntot))
+        .collect();
+
+    Ok(Checkpoint {
+        header,
+        num_bands,
+        exclude_bands,
+        real_lattice,
+        recip_lattice,
+        num_kpts,
+        mp_grid: [mp_grid[0], mp_grid[1], mp_grid[2]],
+        kpoints,
+        nntot,
+        num_wann,
+        checkpoint,
+        disentanglement,
+        u: matrices(&u, num_wann, num_wann, num_kpts),
+        m,
+        centres,
+        spreads,
+    })
+}
+
+/// Lengths of the character records `header` and `checkpoint` of `seedname.chk`.
+pub const CHK_HEADER_LEN: usize = 33;
+pub const CHK_CHECKPOINT_LEN: usize = 20;
+
+/// Records remaining in a Fortran unformatted sequential file.
+struct Records<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+    /// Contents of the next record, which must be `len` bytes long.
+    fn next(&mut self, record: &'static str, len: usize) -> Result<&'a [u8], ChkError> {
+        if self.data.len() < RECORD_MARKER_SIZE {
+            return Err(ChkError::UnexpectedEof(record));
+        }
+        let start = le_u32(&self.data[..RECORD_MARKER_SIZE]) as usize;
+        let end_offset = RECORD_MARKER_SIZE + start;
+        if self.data.len() < end_offset + RECORD_MARKER_SIZE {
+            return Err(ChkError::UnexpectedEof(record));
+        }
+        let end = le_u32(&self.data[end_offset..end_offset + RECORD_MARKER_SIZE]) as usize;
+        if start != end {
+            return Err(ChkError::RecordMarkers { record, start, end });
+        }
+        if start != len {
+            return Err(ChkError::RecordLength {
+                record,
+                len: start,
+                expected: len,
+            });
+        }
+
+        let contents = &self.data[RECORD_MARKER_SIZE..end_offset];
+        self.data = &self.data[end_offset + RECORD_MARKER_SIZE..];
+
+        Ok(contents)
+    }
+
+    fn text(&mut self, record: &'static str, len: usize) -> Result<String, ChkError> {
+        let contents = self.next(record, len)?;
+
+        Ok(String::from_utf8_lossy(contents).trim_end().to_string())
+    }
+
+    fn counts(&mut self, record: &'static str, n: usize) -> Result<Vec<usize>, ChkError> {
+        self.next(record, dims(&[4, n])?)?
+            .chunks(4)
+            .map(|b| {
+                let value = le_u32(b) as i32;
+                if value < 0 {
+                    Err(ChkError::NegativeValue { record, value })
+                } else {
+                    Ok(value as usize)
+                }
+            })
+            .collect()
+    }
+
+    fn count(&mut self, record: &'static str) -> Result<usize, ChkError> {
+        Ok(self.counts(record, 1)?[0])
+    }
+
+    fn logicals(&mut self, record: &'static str, n: usize) -> Result<Vec<bool>, ChkError> {
+        let contents = self.next(record, dims(&[4, n])?)?;
+
+        Ok(contents.chunks(4).map(|b| le_u32(b) != 0).collect())
+    }
+
+    fn reals(&mut self, record: &'static str, n: usize) -> Result<Vec<f64>, ChkError> {
+        let contents = self.next(record, dims(&[8, n])?)?;
+
+        Ok(contents.chunks(8).map(le_f64).collect())
+    }
+
+    fn complexes(&mut self, record: &'static str, n: usize) -> Result<Vec<Complex64>, ChkError> {
+        let contents = self.next(record, dims(&[16, n])?)?;
+
+        Ok(contents
+            .chunks(16)
+            .map(|b| Complex64::new(le_f64(&b[..8]), le_f64(&b[8..])))
+            .collect())
+    }
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    b.iter().rev().fold(0, |x, &byte| (x << 8) | byte as u32)
+}
+
+fn le_f64(b: &[u8]) -> f64 {
+    f64::from_bits(b.iter().rev().fold(0, |x, &byte| (x << 8) | byte as u64))
+}
+
+/// Number of elements of an array with dimensions `d`, or an error if it overflows.
+fn dims(d: &[usize]) -> Result<usize, ChkError> {
+    d.iter()
+        .fold(Some(1usize), |n, &x| n.and_then(|n| n.checked_mul(x)))
+        .ok_or(ChkError::TooLarge)
+}
+
+/// Split the Fortran array `a(3, n)` into its `n` columns.
+fn columns(a: &[f64]) -> Vec<[f64; 3]> {
+    a.chunks(3).map(|c| [c[0], c[1], c[2]]).collect()
+}
+
+/// Rows of the Fortran array `a(3, 3)`: `vectors(a)[i][j]` is `a(i, j)`.
+fn vectors(a: &[f64]) -> [[f64; 3]; 3] {
+    let mut v = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            v[i][j] = a[i + 3 * j];
+        }
+    }
+    v
+}
+
+/// Split the Fortran array `a(rows, cols, count)` into `count` matrices indexed as
+/// `[k][m][n]`.
+fn matrices(a: &[Complex64], rows: usize, cols: usize, count: usize) -> Vec<Vec<Vec<Complex64>>> {
+    (0..count)
+        .map(|k| {
+            (0..rows)
+                .map(|m| (0..cols).map(|n| a[m + rows * (n + cols * k)]).collect())
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Fail, Debug)]
+pub enum ChkError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Unexpected end of file in record `{}`.", _0)] UnexpectedEof(&'static str),
+    #[fail(display = "Record `{}` starts with length {} but ends with length {}.", record, start,
+           end)]
+    RecordMarkers { record: &'static str, start: usize, end: usize },
+    #[fail(display = "Record `{}` has length {}, but expected {}.", record, len, expected)]
+    RecordLength { record: &'static str, len: usize, expected: usize },
+    #[fail(display = "Record `{}` contains the negative value {}.", record, value)]
+    NegativeValue { record: &'static str, value: i32 },
+    #[fail(display = "Array dimensions are too large.")] TooLarge,
+    #[fail(display = "{} bytes after the last record.", _0)] TrailingData(usize),
+}
+
+impl From<io::Error> for ChkError {
+    fn from(e: io::Error) -> ChkError {
+        ChkError::Io(e)
+    }
+}
+
+fn read_text<P: AsRef<Path>>(file_path: P) -> Result<String, io::Error> {
+    let mut contents = String::new();
+    File::open(file_path)?.read_to_string(&mut contents)?;
+
+    Ok(contents)
+}
+
+/// Parse all whitespace-separated values on `line` as floats. On failure, gives the token
+/// which could not be parsed.
+fn parse_floats(line: &str) -> Result<Vec<f64>, String> {
+    line.split_whitespace().map(parse_token).collect()
+}
+
+/// Parse all whitespace-separated values on `line` as non-negative integers. On failure,
+/// gives the token which could not be parsed.
+fn parse_usizes(line: &str) -> Result<Vec<usize>, String> {
+    line.split_whitespace().map(parse_token).collect()
+}
@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::process::{Command, ExitStatus};
+
+/// Executable used by `run_postproc_setup` and `run_wannierize`.
+pub const DEFAULT_EXECUTABLE: &str = "wannier90.x";
+
+/// Invoker for the wannier90.x executable at `executable`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wannier90 {
+    pub executable: PathBuf,
+}
+
+impl Default for Wannier90 {
+    fn default() -> Wannier90 {
+        Wannier90::new(DEFAULT_EXECUTABLE)
+    }
+}
+
+impl Wannier90 {
+    pub fn new<P: AsRef<Path>>(executable: P) -> Wannier90 {
+        Wannier90 {
+            executable: executable.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Run `wannier90.x -pp seedname` in `workdir`, which writes `seedname.nnkp` for
+    /// use by pw2wannier90.x.
+    pub fn run_postproc_setup<P: AsRef<Path>>(
+        &self,
+        seedname: &str,
+        workdir: P,
+    ) -> Result<(), RunError> {
+        self.run(&["-pp", seedname], workdir.as_ref())
+    }
+
+    /// Run `wannier90.x seedname` in `workdir`, then check `seedname.wout` for
+    /// convergence failures.
+    pub fn run_wannierize<P: AsRef<Path>>(
+        &self,
+        seedname: &str,
+        workdir: P,
+    ) -> Result<(), RunError> {
+        let workdir = workdir.as_ref();
+        self.run(&[seedname], workdir)?;
+
+        let mut wout = String::new();
+        File::open(workdir.join(format!("{}.wout", seedname)))?.read_to_string(&mut wout)?;
+
+        check_wout(&wout)
+    }
+
+    fn run(&self, args: &[&str], workdir: &Path) -> Result<(), RunError> {
+        let output = Command::new(&self.executable)
+            .args(args)
+            .current_dir(workdir)
+            .output()?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(RunError::Failed {
+                status: output.status,
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            })
+        }
+    }
+}
+
+/// Run `wannier90.x -pp seedname` in `workdir` using the default executable.
+pub fn run_postproc_setup<P: AsRef<Path>>(seedname: &str, workdir: P) -> Result<(), RunError> {
+    Wannier90::default().run_postproc_setup(seedname, workdir)
+}
+
+/// Run `wannier90.x seedname` in `workdir` using the default executable.
+pub fn run_wannierize<P: AsRef<Path>>(seedname: &str, workdir: P) -> Result<(), RunError> {
+    Wannier90::default().run_wannierize(seedname, workdir)
+}
+
+/// Check the contents of a `seedname.wout` file for signs that the calculation
+/// did not converge or was aborted.
+pub fn check_wout(wout: &str) -> Result<(), RunError> {
+    if wout.contains("Maximum number of disentanglement iterations reached") {
+        return Err(RunError::DisentanglementNotConverged);
+    }
+    // Written by wannier90's io_error before stopping.
+    if wout.contains("Exiting.......") {
+        return Err(RunError::Aborted);
+    }
+
+    Ok(())
+}
+
+#[derive(Fail, Debug)]
+pub enum RunError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "wannier90.x exited with {}.\nstdout:\n{}\nstderr:\n{}", status, stdout,
+           stderr)]
+    Failed {
+        status: ExitStatus,
+        stdout: String,
+        stderr: String,
+    },
+    #[fail(display = "Disentanglement did not converge within `dis_num_iter` iterations.")]
+    DisentanglementNotConverged,
+    #[fail(display = "wannier90.x stopped with an error; see the .wout and .werr files.")]
+    Aborted,
+}
+
+impl From<io::Error> for RunError {
+    fn from(err: io::Error) -> RunError {
+        RunError::Io(err)
+    }
+}
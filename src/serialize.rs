@@ -1,76 +1,727 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
 use std::io;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::fs;
 use std::fs::File;
-use qe::pw::input::generate_uniform_kpoints;
+use std::time::{SystemTime, UNIX_EPOCH};
 use input;
-use input::{AngularMomentum, Disentanglement, Input, LatticeUnits, MLWFIterationMode,
-            PositionCoordinateType, Projection, ProjectionSite};
+use input::{AngularMomentum, BandsPlotFormat, BandsPlotMode, BandsPlotOptions, Berry,
+            BerryTask, BoltzWann, Disentanglement, Dos, Input, LatticeUnits, MLWFIterationMode,
+            PositionCoordinateType, Projection, ProjectionSite, Restart, SpinChannel,
+            SpinProjection};
+use kpath::KPath;
 
+/// Generate the input file for `input`, formatting numbers with
+/// `SerializeOptions::default()`.
 pub fn make_input_file(input: &Input) -> Result<String, Error> {
+    make_input_file_with_options(input, &SerializeOptions::default())
+}
+
+/// Generate the input file for `input`, formatting numbers according to `options`.
+pub fn make_input_file_with_options(
+    input: &Input,
+    options: &SerializeOptions,
+) -> Result<String, Error> {
+    let mut input_text = Vec::new();
+    write_input_with_options(input, options, &mut input_text)?;
+
+    Ok(String::from_utf8(input_text).expect("input file text is valid UTF-8"))
+}
+
+/// Default number of significant digits given by `SerializeOptions::default()`.
+pub const DEFAULT_FLOAT_PRECISION: usize = 10;
+
+/// Settings controlling how values are formatted in the input file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerializeOptions {
+    /// Number of significant digits written for coordinates: the lattice vectors, atomic
+    /// positions, k-points, `kpoint_path`, projection centres and axes, and `dis_spheres`,
+    /// in fixed-point notation. If `None`, each number is written with the fewest digits
+    /// that read back as the same `f64`.
+    pub float_precision: Option<usize>,
+    pub bool_style: BoolStyle,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> SerializeOptions {
+        SerializeOptions {
+            float_precision: Some(DEFAULT_FLOAT_PRECISION),
+            bool_style: BoolStyle::Fortran,
+        }
+    }
+}
+
+impl SerializeOptions {
+    /// Options writing each number with the fewest digits that read back as the same
+    /// `f64`. The output then depends on the exact values, which may differ in the last
+    /// bits between platforms; `SerializeOptions::default()` avoids this.
+    pub fn exact() -> SerializeOptions {
+        SerializeOptions {
+            float_precision: None,
+            bool_style: BoolStyle::Fortran,
+        }
+    }
+
+    fn float(&self, x: f64) -> String {
+        match self.float_precision {
+            Some(digits) => format_significant(x, digits),
+            None => format!("{}", x),
+        }
+    }
+
+    /// Components of `v` formatted by `float` and joined by `separator`.
+    fn vector(&self, v: [f64; 3], separator: &str) -> String {
+        [self.float(v[0]), self.float(v[1]), self.float(v[2])].join(separator)
+    }
+}
+
+/// Spelling of logical values in the input file. Wannier90 reads logicals with Fortran
+/// list-directed input, which accepts either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoolStyle {
+    /// `.true.` and `.false.`
+    Fortran,
+    /// `T` and `F`
+    Short,
+}
+
+impl BoolStyle {
+    pub fn format(&self, b: bool) -> &'static str {
+        match *self {
+            BoolStyle::Fortran => fortran_bool(b),
+            BoolStyle::Short if b => "T",
+            BoolStyle::Short => "F",
+        }
+    }
+}
+
+/// Format `x` in fixed-point notation with `digits` significant digits. Zero is written
+/// with `digits - 1` decimal places.
+fn format_significant(x: f64, digits: usize) -> String {
+    let digits = digits.max(1) as i64;
+    if x == 0.0 || !x.is_finite() {
+        // Avoid writing `-0.0`.
+        let x = if x == 0.0 { 0.0 } else { x };
+        return format!("{:.*}", (digits - 1) as usize, x);
+    }
+
+    let decimals = |x: f64| (digits - 1 - x.abs().log10().floor() as i64).max(0) as usize;
+    let text = format!("{:.*}", decimals(x), x);
+
+    // Rounding may carry into a new leading digit, as 9.99 rounds to 10.0 with three
+    // digits; the decimals are then counted from the rounded value.
+    let rounded: f64 = text.parse().expect("formatted float is valid");
+    if decimals(rounded) < decimals(x) {
+        format!("{:.*}", decimals(rounded), x)
+    } else {
+        text
+    }
+}
+
+/// Text of the input file for `self`, as given by `make_input_file`, but without
+/// validation: an invalid input is formatted as far as possible instead of giving an
+/// error. Use `make_input_file` to generate files for Wannier90.
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = SerializeOptions::default();
+        for section in make_sections_before_kpoints(self, &options).before_kpoints() {
+            writeln!(f, "{}", section)?;
+        }
+
+        let mut kpoints = Vec::new();
+        write_kpoints_block(self, &options, &mut kpoints).map_err(|_| fmt::Error)?;
+        write!(f, "{}", String::from_utf8_lossy(&kpoints))
+    }
+}
+
+/// Generate the input file for `input`, preceded by the comment lines given by
+/// `provenance_header` for the current time.
+pub fn make_input_file_with_header(input: &Input, note: Option<&str>) -> Result<String, Error> {
+    let header = provenance_header(SystemTime::now(), note);
+
+    Ok(header + &make_input_file(input)?)
+}
+
+/// Comment lines recording that the input file was generated by this crate at `time`:
+///
+/// ```text
+/// ! Generated by w90-rs v0.1.0 on 2018-06-01T12:00:00Z
+/// ! <each line of note>
+/// ```
+///
+/// Wannier90 treats everything after either `!` or `#` as a comment. `!` is used since it
+/// is also the Fortran comment character, which keeps the file readable for tools that
+/// expect Fortran namelist conventions. The returned text ends with a newline.
+pub fn provenance_header(time: SystemTime, note: Option<&str>) -> String {
+    let mut lines = vec![format!(
+        "! Generated by w90-rs v{} on {}",
+        env!("CARGO_PKG_VERSION"),
+        rfc3339_utc(time)
+    )];
+    if let Some(note) = note {
+        for line in note.lines() {
+            lines.push(String::from(format!("! {}", line).trim_end()));
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Format `time` as an RFC 3339 timestamp in UTC with one-second resolution. Times before
+/// the Unix epoch are clamped to the epoch.
+fn rfc3339_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Convert days since 1970-01-01 to a proleptic Gregorian date, counting years from
+    // March 1 so that the leap day falls at the end of the year.
+    let z = days as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Write the input file for `input` to `w` section by section. The list of k-points is
+/// written one k-point at a time, so the text of the full file is never held in memory.
+pub fn write_input<W: Write>(input: &Input, w: &mut W) -> Result<(), Error> {
+    write_input_with_options(input, &SerializeOptions::default(), w)
+}
+
+/// Write the input file for `input` to `w` as `write_input` does, formatting numbers
+/// according to `options`.
+pub fn write_input_with_options<W: Write>(
+    input: &Input,
+    options: &SerializeOptions,
+    w: &mut W,
+) -> Result<(), Error> {
     input::validate(&input)?;
 
-    let header = make_header(&input);
+    let sections = make_sections_before_kpoints(input, options);
+
+    for section in sections.before_kpoints() {
+        w.write_all(section.as_bytes())?;
+        w.write_all(b"\n")?;
+    }
+
+    write_kpoints_block(input, options, w)?;
+
+    Ok(())
+}
+
+/// Text of each section of the input file, for callers who want to assemble the file
+/// themselves. Absent optional sections are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSections {
+    pub header: String,
+    pub disentanglement: Option<String>,
+    pub dos: Option<String>,
+    pub berry: Option<String>,
+    pub boltzwann: Option<String>,
+    pub geninterp: Option<String>,
+    pub bands_plot: Option<String>,
+    pub projections: String,
+    pub unit_cell: String,
+    pub positions: String,
+    pub kmesh_settings: String,
+    pub kpoints: String,
+}
+
+impl InputSections {
+    /// Non-empty sections preceding the `kpoints` block, in input file order.
+    fn before_kpoints(&self) -> Vec<&str> {
+        let mut sections = vec![self.header.as_str()];
+        if let Some(ref dis) = self.disentanglement {
+            sections.push(dis);
+        }
+        if let Some(ref dos) = self.dos {
+            sections.push(dos);
+        }
+        if let Some(ref berry) = self.berry {
+            sections.push(berry);
+        }
+        if let Some(ref boltzwann) = self.boltzwann {
+            sections.push(boltzwann);
+        }
+        if let Some(ref geninterp) = self.geninterp {
+            sections.push(geninterp);
+        }
+        if let Some(ref bands_plot) = self.bands_plot {
+            sections.push(bands_plot);
+        }
+        sections.extend(vec![
+            self.projections.as_str(),
+            self.unit_cell.as_str(),
+            self.positions.as_str(),
+            self.kmesh_settings.as_str(),
+        ]);
+
+        sections.into_iter().filter(|s| s.len() > 0).collect()
+    }
 
-    let mut input_sections = vec![header];
+    /// Assemble the sections in the order used by `make_input_file`.
+    pub fn join(&self) -> String {
+        let mut sections = self.before_kpoints();
+        sections.push(&self.kpoints);
 
-    if let Some(ref disentanglement) = input.disentanglement {
-        input_sections.push(make_disentanglement(disentanglement));
+        sections.join("\n")
     }
+}
 
-    let projections = make_projections(&input);
-    let cell = make_unit_cell(&input);
-    let positions = make_positions(&input);
-    let k_points = make_kpoints(&input);
+/// Generate the text of each section of the input file for `input`.
+pub fn make_sections(input: &Input) -> Result<InputSections, Error> {
+    input::validate(&input)?;
+
+    let options = SerializeOptions::default();
+    let mut sections = make_sections_before_kpoints(input, &options);
 
-    input_sections.extend(vec![projections, cell, positions, k_points]);
+    let mut kpoints = Vec::new();
+    write_kpoints_block(input, &options, &mut kpoints)?;
+    sections.kpoints = String::from_utf8(kpoints).expect("input file text is valid UTF-8");
 
-    let input_text = input_sections.join("\n");
+    Ok(sections)
+}
 
-    return Ok(input_text);
+fn make_sections_before_kpoints(input: &Input, options: &SerializeOptions) -> InputSections {
+    let annotate = |section: String| with_comments(&section, &input.comments);
+
+    InputSections {
+        header: annotate(make_header(input, options)),
+        disentanglement: input
+            .disentanglement
+            .as_ref()
+            .map(|dis| annotate(make_disentanglement(dis, options))),
+        dos: input
+            .dos
+            .as_ref()
+            .map(|dos| annotate(make_dos(dos, options))),
+        berry: input
+            .berry
+            .as_ref()
+            .map(|berry| annotate(make_berry(berry, options))),
+        boltzwann: input
+            .boltzwann
+            .as_ref()
+            .map(|boltz| annotate(make_boltzwann(boltz, options))),
+        geninterp: input
+            .geninterp
+            .map(|_| annotate(make_geninterp(input, options))),
+        bands_plot: match (input.bands_plot, input.kpoint_path.as_ref()) {
+            (Some(true), Some(path)) => Some(annotate(make_bands_plot(
+                path,
+                input.bands_plot_options.as_ref(),
+                options,
+            ))),
+            _ => None,
+        },
+        projections: annotate(make_projections(input, options)),
+        unit_cell: annotate(make_unit_cell(input, options)),
+        positions: annotate(make_positions(input, options)),
+        kmesh_settings: annotate(make_kmesh_settings(input, options)),
+        kpoints: String::new(),
+    }
 }
 
-fn make_header(input: &Input) -> String {
+/// Append the comment in `comments` for the keyword or block started on each line of
+/// `section`, if there is one. Lines inside blocks are left unchanged.
+fn with_comments(section: &str, comments: &HashMap<String, String>) -> String {
+    if comments.is_empty() {
+        return String::from(section);
+    }
+
+    let mut in_block = false;
+    let lines: Vec<String> = section
+        .lines()
+        .map(|line| {
+            let first = line.split(|c: char| c == '=' || c == ':' || c.is_whitespace())
+                .next()
+                .unwrap_or("")
+                .to_lowercase();
+            let key = match first.as_str() {
+                "begin" => {
+                    in_block = true;
+                    Some(line["begin".len()..].trim().to_lowercase())
+                }
+                "end" => {
+                    in_block = false;
+                    None
+                }
+                _ if in_block => None,
+                _ => Some(first),
+            };
+
+            match key.and_then(|k| comments.get(&k)) {
+                Some(comment) => format!("{} ! {}", line, comment),
+                None => String::from(line),
+            }
+        })
+        .collect();
+
+    lines.join("\n")
+}
+
+/// Number of k-points formatted at once when the `parallel` feature is enabled.
+const PARALLEL_KPOINT_CHUNK: usize = 4096;
+
+fn write_kpoints_block<W: Write>(
+    input: &Input,
+    options: &SerializeOptions,
+    w: &mut W,
+) -> io::Result<()> {
+    if cfg!(feature = "parallel") {
+        // Format the k-points in parallel one chunk at a time, so that memory use stays
+        // bounded for large grids.
+        let mut kpoints = input.kpoint_iter();
+        let mut chunk = Vec::with_capacity(PARALLEL_KPOINT_CHUNK);
+        writeln!(w, "begin kpoints")?;
+        loop {
+            chunk.clear();
+            chunk.extend(kpoints.by_ref().take(PARALLEL_KPOINT_CHUNK));
+            if chunk.is_empty() {
+                break;
+            }
+            w.write_all(format_kpoints_parallel(&chunk, options).as_bytes())?;
+        }
+        write!(w, "end kpoints")
+    } else {
+        make_kpoints(input.kpoint_iter(), options, w)
+    }
+}
+
+/// Compare the input file generated from `input` to the expected text `golden`. On a
+/// mismatch, describes the first line at which they differ.
+pub fn golden_compare(input: &Input, golden: &str) -> Result<(), String> {
+    let input_text = make_input_file(input).map_err(|e| format!("{}", e))?;
+
+    let mut got_lines = input_text.lines();
+    let mut expected_lines = golden.lines();
+    let mut line_number = 1;
+
+    loop {
+        match (got_lines.next(), expected_lines.next()) {
+            (None, None) => return Ok(()),
+            (Some(got), Some(expected)) => {
+                if got != expected {
+                    return Err(format!(
+                        "line {}: expected `{}`, got `{}`",
+                        line_number, expected, got
+                    ));
+                }
+            }
+            (Some(got), None) => {
+                return Err(format!("line {}: expected end of file, got `{}`", line_number, got))
+            }
+            (None, Some(expected)) => {
+                return Err(format!(
+                    "line {}: expected `{}`, got end of file",
+                    line_number, expected
+                ))
+            }
+        }
+        line_number += 1;
+    }
+}
+
+pub fn make_header(input: &Input, options: &SerializeOptions) -> String {
     let mut lines = Vec::new();
 
     lines.push(format!("num_bands = {}", input.num_bands));
     lines.push(format!("num_wann = {}", input.num_wann));
     lines.push(format!("num_iter = {}", input.mlwf_iteration_mode.value()));
+    if let Some(num_print_cycles) = input.num_print_cycles {
+        lines.push(format!("num_print_cycles = {}", num_print_cycles));
+    }
+    if let Some(num_dump_cycles) = input.num_dump_cycles {
+        lines.push(format!("num_dump_cycles = {}", num_dump_cycles));
+    }
+    if let Some(optimisation) = input.optimisation {
+        lines.push(format!("optimisation = {}", optimisation));
+    }
+
+    if let Some(ref restart) = input.restart {
+        lines.push(format!("restart = {}", restart.value()));
+    }
+
+    push_bool_field(&mut lines, "guiding_centres", input.guiding_centres, options);
+    if let Some(num_guide_cycles) = input.num_guide_cycles {
+        lines.push(format!("num_guide_cycles = {}", num_guide_cycles));
+    }
+    if let Some(num_no_guide_iter) = input.num_no_guide_iter {
+        lines.push(format!("num_no_guide_iter = {}", num_no_guide_iter));
+    }
+    if let Some(ref slwf) = input.selective_localization {
+        lines.push(format!("slwf_num = {}", slwf.slwf_num));
+        push_bool_field(&mut lines, "slwf_constrain", slwf.slwf_constrain, options);
+        if let Some(slwf_lambda) = slwf.slwf_lambda {
+            lines.push(format!("slwf_lambda = {}", slwf_lambda));
+        }
+    }
+    push_bool_field(&mut lines, "site_symmetry", input.site_symmetry, options);
+    if let Some(symmetrize_eps) = input.symmetrize_eps {
+        lines.push(format!("symmetrize_eps = {}", symmetrize_eps));
+    }
+    push_bool_field(&mut lines, "write_hr", input.write_hr, options);
+    push_bool_field(&mut lines, "write_tb", input.write_tb, options);
+    push_bool_field(&mut lines, "write_u_matrices", input.write_u_matrices, options);
+    push_bool_field(&mut lines, "use_ws_distance", input.use_ws_distance, options);
+
+    push_bool_field(&mut lines, "wvfn_formatted", input.wvfn_formatted, options);
+    push_bool_field(&mut lines, "spn_formatted", input.spn_formatted, options);
+    push_bool_field(&mut lines, "uHu_formatted", input.uhu_formatted, options);
+
+    if let Some(ref flags) = input.output_flags {
+        push_bool_field(&mut lines, "translate_home_cell", flags.translate_home_cell, options);
+        push_bool_field(&mut lines, "write_xyz", flags.write_xyz, options);
+        push_bool_field(&mut lines, "write_vdw_data", flags.write_vdw_data, options);
+        push_bool_field(&mut lines, "write_hr_diag", flags.write_hr_diag, options);
+    }
+    push_bool_field(&mut lines, "gamma_only", input.gamma_only, options);
 
-    push_bool_field(&mut lines, "num_iter", input.write_hr);
+    if let Some(ref exclude_bands) = input.exclude_bands {
+        lines.push(format!("exclude_bands = {}", range_list(exclude_bands)));
+    }
 
     lines.join("\n")
 }
 
-fn push_bool_field(lines: &mut Vec<String>, name: &str, b: Option<bool>) {
-    if let Some(b) = b {
-        let val = if b {
-            String::from(".true.")
-        } else {
-            String::from(".false.")
+/// Format a sorted list of indices, collapsing runs of consecutive indices into `a-b`
+/// form: `[1, 2, 3, 4, 7]` becomes `1-4,7`.
+fn range_list(indices: &[u64]) -> String {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+    for &n in indices {
+        let extends_last = match ranges.last() {
+            Some(&(_, end)) => n == end + 1,
+            None => false,
         };
 
-        lines.push(format!("{}={}", name, val));
+        if extends_last {
+            ranges.last_mut().unwrap().1 = n;
+        } else {
+            ranges.push((n, n));
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                format!("{}", start)
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Fortran logical literal for `b`. All boolean keywords should be written through this
+/// function so that the input file uses a single consistent spelling.
+pub fn fortran_bool(b: bool) -> &'static str {
+    if b {
+        ".true."
+    } else {
+        ".false."
+    }
+}
+
+fn push_bool_field(
+    lines: &mut Vec<String>,
+    name: &str,
+    b: Option<bool>,
+    options: &SerializeOptions,
+) {
+    if let Some(b) = b {
+        lines.push(format!("{}={}", name, options.bool_style.format(b)));
     };
 }
 
-fn make_disentanglement(dis: &Disentanglement) -> String {
+pub fn make_disentanglement(dis: &Disentanglement, options: &SerializeOptions) -> String {
     let mut lines = Vec::new();
 
-    lines.push(format!("dis_win_min = {}", dis.dis_win_min));
-    lines.push(format!("dis_win_max = {}", dis.dis_win_max));
-    lines.push(format!("dis_froz_min = {}", dis.dis_froz_min));
-    lines.push(format!("dis_froz_max = {}", dis.dis_froz_max));
+    if let Some(dis_win_min) = dis.dis_win_min {
+        lines.push(format!("dis_win_min = {}", dis_win_min));
+    }
+    if let Some(dis_win_max) = dis.dis_win_max {
+        lines.push(format!("dis_win_max = {}", dis_win_max));
+    }
+    if let Some(dis_froz_min) = dis.dis_froz_min {
+        lines.push(format!("dis_froz_min = {}", dis_froz_min));
+    }
+    if let Some(dis_froz_max) = dis.dis_froz_max {
+        lines.push(format!("dis_froz_max = {}", dis_froz_max));
+    }
+    push_bool_field(&mut lines, "dis_froz_proj", dis.dis_froz_proj, options);
+    if let Some(dis_proj_min) = dis.dis_proj_min {
+        lines.push(format!("dis_proj_min = {}", dis_proj_min));
+    }
+    if let Some(dis_proj_max) = dis.dis_proj_max {
+        lines.push(format!("dis_proj_max = {}", dis_proj_max));
+    }
     lines.push(format!("dis_num_iter = {}", dis.dis_num_iter));
     lines.push(format!("dis_mix_ratio = {}", dis.dis_mix_ratio));
 
+    if let Some(ref spheres) = dis.dis_spheres {
+        lines.push(format!("dis_spheres_num = {}", spheres.dis_spheres_num));
+        if let Some(first_wann) = spheres.dis_spheres_first_wann {
+            lines.push(format!("dis_spheres_first_wann = {}", first_wann));
+        }
+        lines.push(String::from("begin dis_spheres"));
+        for sphere in &spheres.spheres {
+            lines.push(format!(
+                "{} {}",
+                options.vector(sphere.center, " "),
+                options.float(sphere.radius)
+            ));
+        }
+        lines.push(String::from("end dis_spheres"));
+    }
+
+    lines.join("\n")
+}
+
+pub fn make_dos(dos: &Dos, options: &SerializeOptions) -> String {
+    let mut lines = Vec::new();
+
+    push_bool_field(&mut lines, "dos", Some(true), options);
+
+    let nk = dos.dos_kmesh;
+    lines.push(format!("dos_kmesh = {} {} {}", nk[0], nk[1], nk[2]));
+    lines.push(format!("dos_energy_min = {}", dos.dos_energy_min));
+    lines.push(format!("dos_energy_max = {}", dos.dos_energy_max));
+    lines.push(format!("dos_energy_step = {}", dos.dos_energy_step));
+    push_bool_field(&mut lines, "dos_adpt_smr", dos.dos_adpt_smr, options);
+
+    lines.join("\n")
+}
+
+pub fn make_berry(berry: &Berry, options: &SerializeOptions) -> String {
+    let mut lines = Vec::new();
+
+    push_bool_field(&mut lines, "berry", Some(true), options);
+
+    lines.push(format!("berry_task = {}", berry.berry_task.value()));
+    let nk = berry.berry_kmesh;
+    lines.push(format!("berry_kmesh = {} {} {}", nk[0], nk[1], nk[2]));
+    if let Some(adpt_kmesh) = berry.berry_curv_adpt_kmesh {
+        lines.push(format!("berry_curv_adpt_kmesh = {}", adpt_kmesh));
+    }
+
+    lines.join("\n")
+}
+
+pub fn make_boltzwann(boltz: &BoltzWann, options: &SerializeOptions) -> String {
+    let mut lines = Vec::new();
+
+    push_bool_field(&mut lines, "boltzwann", Some(true), options);
+
+    let nk = boltz.boltz_kmesh;
+    lines.push(format!("boltz_kmesh = {} {} {}", nk[0], nk[1], nk[2]));
+    if let Some(step) = boltz.boltz_tdf_energy_step {
+        lines.push(format!("boltz_tdf_energy_step = {}", step));
+    }
+    lines.push(format!("boltz_mu_min = {}", boltz.boltz_mu_min));
+    lines.push(format!("boltz_mu_max = {}", boltz.boltz_mu_max));
+    lines.push(format!("boltz_mu_step = {}", boltz.boltz_mu_step));
+    lines.push(format!("boltz_temp_min = {}", boltz.boltz_temp_min));
+    lines.push(format!("boltz_temp_max = {}", boltz.boltz_temp_max));
+    lines.push(format!("boltz_temp_step = {}", boltz.boltz_temp_step));
+    if let Some(relax_time) = boltz.boltz_relax_time {
+        lines.push(format!("boltz_relax_time = {}", relax_time));
+    }
+
     lines.join("\n")
 }
 
-fn make_projections(input: &Input) -> String {
+pub fn make_geninterp(input: &Input, options: &SerializeOptions) -> String {
     let mut lines = Vec::new();
 
-    push_bool_field(&mut lines, "spinors", Some(input.spinors));
+    push_bool_field(&mut lines, "geninterp", input.geninterp, options);
+    push_bool_field(
+        &mut lines,
+        "geninterp_alsofirstder",
+        input.geninterp_alsofirstder,
+        options,
+    );
+    push_bool_field(
+        &mut lines,
+        "geninterp_single_file",
+        input.geninterp_single_file,
+        options,
+    );
+
+    lines.join("\n")
+}
+
+pub fn make_bands_plot(
+    path: &KPath,
+    plot_options: Option<&BandsPlotOptions>,
+    options: &SerializeOptions,
+) -> String {
+    let mut lines = Vec::new();
+
+    push_bool_field(&mut lines, "bands_plot", Some(true), options);
+
+    if let Some(plot_options) = plot_options {
+        if !plot_options.format.is_empty() {
+            let formats: Vec<String> = plot_options.format.iter().map(|f| f.value()).collect();
+            lines.push(format!("bands_plot_format = {}", formats.join(" ")));
+        }
+        if let Some(ref mode) = plot_options.mode {
+            lines.push(format!("bands_plot_mode = {}", mode.value()));
+        }
+        if let Some(ref project) = plot_options.project {
+            lines.push(format!("bands_plot_project = {}", range_list(project)));
+        }
+    }
+
+    lines.push(String::from("begin kpoint_path"));
+    for &(ref start, ref end) in &path.segments {
+        lines.push(format!(
+            "{} {} {} {}",
+            start.label,
+            options.vector(start.k, " "),
+            end.label,
+            options.vector(end.k, " ")
+        ));
+    }
+    lines.push(String::from("end kpoint_path"));
+
+    lines.join("\n")
+}
+
+pub fn make_projections(input: &Input, options: &SerializeOptions) -> String {
+    let mut lines = Vec::new();
+
+    push_bool_field(&mut lines, "use_bloch_phases", input.use_bloch_phases, options);
+    push_bool_field(&mut lines, "spinors", Some(input.spinors), options);
+
+    if let Some(ref spin) = input.spin {
+        lines.push(format!("spin = {}", spin.value()));
+    }
+    if let Some(theta) = input.spin_axis_polar {
+        lines.push(format!("spin_axis_polar = {}", theta));
+    }
+    if let Some(phi) = input.spin_axis_azimuth {
+        lines.push(format!("spin_axis_azimuth = {}", phi));
+    }
 
     lines.push(String::from("begin projections"));
 
@@ -78,7 +729,7 @@ fn make_projections(input: &Input) -> String {
         lines.push(units.value());
     }
     for proj in &input.projections {
-        lines.push(proj.value());
+        lines.push(format_projection(proj, options));
     }
 
     lines.push(String::from("end projections"));
@@ -86,13 +737,18 @@ fn make_projections(input: &Input) -> String {
     lines.join("\n")
 }
 
-fn make_unit_cell(input: &Input) -> String {
+pub fn make_unit_cell(input: &Input, options: &SerializeOptions) -> String {
     let cell = &input.unit_cell_cart;
     let mut lines = vec![String::from("begin unit_cell_cart"), cell.units.value()];
 
     for i in 0..3 {
         let a = cell.cell[i];
-        lines.push(format!("  {}  {}  {}", a[0], a[1], a[2]));
+        lines.push(format!(
+            "  {}  {}  {}",
+            options.float(a[0]),
+            options.float(a[1]),
+            options.float(a[2])
+        ));
     }
 
     lines.push(String::from("end unit_cell_cart"));
@@ -100,7 +756,7 @@ fn make_unit_cell(input: &Input) -> String {
     lines.join("\n")
 }
 
-fn make_positions(input: &Input) -> String {
+pub fn make_positions(input: &Input, options: &SerializeOptions) -> String {
     let mut lines = Vec::new();
 
     let pos = &input.positions;
@@ -120,7 +776,13 @@ fn make_positions(input: &Input) -> String {
 
     for coord in &pos.coordinates {
         let r = &coord.r;
-        lines.push(format!(" {} {} {} {}", coord.species, r[0], r[1], r[2]));
+        lines.push(format!(
+            " {} {} {} {}",
+            coord.site_label(),
+            options.float(r[0]),
+            options.float(r[1]),
+            options.float(r[2])
+        ));
     }
 
     match pos.coordinate_type {
@@ -135,35 +797,260 @@ fn make_positions(input: &Input) -> String {
     lines.join("\n")
 }
 
-fn make_kpoints(input: &Input) -> String {
-    let nk = input.k_points;
+/// Settings for the k-point grid and for finding b-vectors, including the `mp_grid` line.
+pub fn make_kmesh_settings(input: &Input, options: &SerializeOptions) -> String {
+    let mut lines = Vec::new();
 
-    let mut lines = vec![
-        format!("mp_grid = {} {} {}", nk[0], nk[1], nk[2]),
-        String::from("begin kpoints"),
-    ];
+    if let Some(kmesh_tol) = input.kmesh_tol {
+        lines.push(format!("kmesh_tol = {}", kmesh_tol));
+    }
+    if let Some(search_shells) = input.search_shells {
+        lines.push(format!("search_shells = {}", search_shells));
+    }
+    push_bool_field(&mut lines, "skip_b1_tests", input.skip_b1_tests, options);
 
-    for k in generate_uniform_kpoints(nk) {
-        lines.push(format!("{} {} {}", k[0], k[1], k[2]));
+    if let Some(mp_grid) = make_mp_grid(input) {
+        lines.push(mp_grid);
     }
 
-    lines.push(String::from("end kpoints"));
     lines.join("\n")
 }
 
+/// The `mp_grid` line giving the dimensions of the k-point grid. This does not require
+/// enumerating the k-points, except to infer the grid from an explicit list.
+pub fn make_mp_grid(input: &Input) -> Option<String> {
+    input
+        .kpoints
+        .mp_grid()
+        .map(|nk| format!("mp_grid = {} {} {}", nk[0], nk[1], nk[2]))
+}
+
+/// Write the `kpoints` block listing `kpoints`, formatting each k-point as it is
+/// produced by the iterator.
+pub fn make_kpoints<I, W>(kpoints: I, options: &SerializeOptions, w: &mut W) -> io::Result<()>
+where
+    I: IntoIterator<Item = [f64; 3]>,
+    W: Write,
+{
+    writeln!(w, "begin kpoints")?;
+
+    for k in kpoints {
+        w.write_all(format_kpoint(&k, options).as_bytes())?;
+    }
+
+    write!(w, "end kpoints")
+}
+
+/// Lines listing `kpoints`, as they appear in the `kpoints` block.
+pub fn format_kpoints(kpoints: &[[f64; 3]], options: &SerializeOptions) -> String {
+    let mut text = String::new();
+    for k in kpoints {
+        text.push_str(&format_kpoint(k, options));
+    }
+    text
+}
+
+/// Lines listing `kpoints`, as they appear in the `kpoints` block. The lines are formatted
+/// in parallel; the result is identical to `format_kpoints`.
+#[cfg(feature = "parallel")]
+pub fn format_kpoints_parallel(kpoints: &[[f64; 3]], options: &SerializeOptions) -> String {
+    use rayon::prelude::*;
+
+    kpoints
+        .par_iter()
+        .map(|k| format_kpoint(k, options))
+        .collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn format_kpoints_parallel(kpoints: &[[f64; 3]], options: &SerializeOptions) -> String {
+    format_kpoints(kpoints, options)
+}
+
+fn format_kpoint(k: &[f64; 3], options: &SerializeOptions) -> String {
+    format!(
+        "{} {} {}\n",
+        options.float(k[0]),
+        options.float(k[1]),
+        options.float(k[2])
+    )
+}
+
+/// Write the input file for `input` to `file_path`. The file is not created if `input`
+/// fails validation.
 pub fn write_input_file<P: AsRef<Path>>(input: &Input, file_path: P) -> Result<(), Error> {
-    let input_text = make_input_file(input)?;
+    input::validate(input)?;
+
+    let mut file = BufWriter::new(File::create(file_path)?);
+    write_input(input, &mut file)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+/// Coordinates of the k-points in the `seedname_geninterp.kpt` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeninterpCoordinates {
+    /// Fractional coordinates in the basis of the reciprocal lattice vectors.
+    Crystal,
+    /// Cartesian coordinates in inverse Angstrom.
+    Cartesian,
+}
+
+impl GeninterpCoordinates {
+    fn value(&self) -> &'static str {
+        match *self {
+            GeninterpCoordinates::Crystal => "crystal",
+            GeninterpCoordinates::Cartesian => "cart",
+        }
+    }
+}
+
+/// Text of the `seedname_geninterp.kpt` file listing the k-points at which postw90.x
+/// interpolates the bands when `geninterp` is set:
+///
+/// ```text
+/// ! comment
+/// crystal
+/// <number of k-points>
+/// 1 kx ky kz
+/// 2 kx ky kz
+/// ...
+/// ```
+pub fn make_geninterp_kpt(
+    kpoints: &[[f64; 3]],
+    coordinates: GeninterpCoordinates,
+    options: &SerializeOptions,
+) -> Result<String, Error> {
+    if kpoints.is_empty() {
+        return Err(Error::EmptyGeninterpKPoints);
+    }
+
+    let mut lines = vec![
+        format!("! Generated by w90-rs v{}", env!("CARGO_PKG_VERSION")),
+        String::from(coordinates.value()),
+        format!("{}", kpoints.len()),
+    ];
+    for (i, k) in kpoints.iter().enumerate() {
+        lines.push(format!("{} {}", i + 1, format_kpoint(k, options).trim_end()));
+    }
+    lines.push(String::new());
+
+    Ok(lines.join("\n"))
+}
+
+/// Write the `seedname_geninterp.kpt` file given by `make_geninterp_kpt` to `file_path`.
+/// The file is not created if `kpoints` is empty.
+pub fn write_geninterp_kpt<P: AsRef<Path>>(
+    kpoints: &[[f64; 3]],
+    coordinates: GeninterpCoordinates,
+    options: &SerializeOptions,
+    file_path: P,
+) -> Result<(), Error> {
+    let text = make_geninterp_kpt(kpoints, coordinates, options)?;
 
     let mut file = File::create(file_path)?;
-    file.write_all(input_text.as_bytes())?;
+    file.write_all(text.as_bytes())?;
 
     Ok(())
 }
 
-#[derive(Fail, Debug)]
+/// Name of the subdirectory used by `write_kmesh_series` for the k-point grid `nk`, such
+/// as `9x9x1`.
+pub fn kmesh_dir_name(nk: [u64; 3]) -> String {
+    format!("{}x{}x{}", nk[0], nk[1], nk[2])
+}
+
+/// Write the input file for `base` with each of the k-point grids in `grids` to
+/// `dir/<kmesh_dir_name>/seedname.win`, creating the subdirectories as needed. Returns the
+/// paths of the files written, in the order of `grids`.
+///
+/// Every grid is validated before any file is written. If any grid is invalid, nothing is
+/// written and the error for each invalid grid is returned. Errors while writing are
+/// collected in the same way, after attempting to write all of the files.
+pub fn write_kmesh_series<P: AsRef<Path>>(
+    base: &Input,
+    seedname: &str,
+    grids: &[[u64; 3]],
+    dir: P,
+) -> Result<Vec<PathBuf>, Vec<([u64; 3], Error)>> {
+    let inputs: Vec<([u64; 3], Input)> = grids
+        .iter()
+        .map(|&nk| (nk, base.with_kpoints(nk)))
+        .collect();
+
+    let invalid: Vec<([u64; 3], Error)> = inputs
+        .iter()
+        .filter_map(|&(nk, ref input)| {
+            input::validate(input)
+                .err()
+                .map(|errs| (nk, Error::from(errs)))
+        })
+        .collect();
+    if invalid.len() > 0 {
+        return Err(invalid);
+    }
+
+    let mut paths = Vec::new();
+    let mut errs = Vec::new();
+    for (nk, input) in inputs {
+        let subdir = dir.as_ref().join(kmesh_dir_name(nk));
+        let path = subdir.join(format!("{}.win", seedname));
+
+        let result = fs::create_dir_all(&subdir)
+            .map_err(Error::from)
+            .and_then(|_| write_input_file(&input, &path));
+        match result {
+            Ok(()) => paths.push(path),
+            Err(e) => errs.push((nk, e)),
+        }
+    }
+
+    if errs.len() == 0 {
+        Ok(paths)
+    } else {
+        Err(errs)
+    }
+}
+
+#[derive(Debug)]
 pub enum Error {
-    #[fail(display = "{}", _0)] Input(input::ErrorList),
-    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    Input(input::ErrorList),
+    Io(io::Error),
+    EmptyGeninterpKPoints,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Input(ref errs) => write!(f, "{}", errs),
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::EmptyGeninterpKPoints => write!(f, "The geninterp k-point list is empty."),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Input(_) | Error::EmptyGeninterpKPoints => None,
+        }
+    }
+}
+
+impl Error {
+    /// Process exit code identifying the class of this error. Validation errors use the
+    /// code given by `input::Error::exit_code` for the first error in the list; I/O
+    /// errors give 74 and an empty geninterp k-point list gives 9, which is not used by
+    /// any class of validation error.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Input(ref errs) => errs.errs.first().map_or(1, |e| e.exit_code()),
+            Error::Io(_) => 74,
+            Error::EmptyGeninterpKPoints => 9,
+        }
+    }
 }
 
 impl From<input::ErrorList> for Error {
@@ -205,60 +1092,191 @@ impl Field for LatticeUnits {
     }
 }
 
+impl Field for Restart {
+    fn value(&self) -> String {
+        String::from(match *self {
+            Restart::Default => "default",
+            Restart::Wannierise => "wannierise",
+            Restart::Plot => "plot",
+            Restart::Transport => "transport",
+        })
+    }
+}
+
+impl Field for SpinChannel {
+    fn value(&self) -> String {
+        String::from(match *self {
+            SpinChannel::Up => "up",
+            SpinChannel::Down => "down",
+        })
+    }
+}
+
+impl Field for BerryTask {
+    fn value(&self) -> String {
+        String::from(match *self {
+            BerryTask::Ahc => "ahc",
+            BerryTask::Morb => "morb",
+            BerryTask::Kubo => "kubo",
+            BerryTask::Shc => "shc",
+        })
+    }
+}
+
+impl Field for BandsPlotFormat {
+    fn value(&self) -> String {
+        String::from(match *self {
+            BandsPlotFormat::Gnuplot => "gnuplot",
+            BandsPlotFormat::Xmgrace => "xmgrace",
+        })
+    }
+}
+
+impl Field for BandsPlotMode {
+    fn value(&self) -> String {
+        String::from(match *self {
+            BandsPlotMode::SlaterKoster => "s-k",
+            BandsPlotMode::Cut => "cut",
+        })
+    }
+}
+
 impl Field for Projection {
     fn value(&self) -> String {
-        match *self {
-            Projection::Random => String::from("random"),
-            Projection::Site {
-                ref site,
-                ref ang_mtm,
-                zaxis,
-                xaxis,
-                radial,
-                zona,
-            } => {
-                let mut proj = format!("{}:", site.value());
-                for (i, ang_mtm) in ang_mtm.iter().enumerate() {
-                    if i != 0 {
-                        proj.push_str(";");
-                    }
-                    proj.push_str(&ang_mtm.value());
+        format_projection(self, &SerializeOptions::exact())
+    }
+}
+
+/// Projection line(s) for `proj`, with centres and axes formatted according to `options`.
+///
+/// Wannier90 accepts a single `r` and `zona` per projection line, so a site whose
+/// orbitals have different radial settings is written as one line per run of consecutive
+/// orbitals sharing the same settings.
+fn format_projection(proj: &Projection, options: &SerializeOptions) -> String {
+    match *proj {
+        Projection::Random => String::from("random"),
+        Projection::Site {
+            ref site,
+            ref ang_mtm,
+            zaxis,
+            xaxis,
+            radial,
+            zona,
+            ref spin,
+            quant_dir,
+            ..
+        } => {
+            let mut groups: Vec<(Vec<&AngularMomentum>, (Option<u64>, Option<f64>))> =
+                Vec::new();
+            for l in ang_mtm.iter() {
+                let radial = proj.radial_for(l);
+                let same = groups.last().map_or(false, |g| g.1 == radial);
+                if same {
+                    groups.last_mut().unwrap().0.push(l);
+                } else {
+                    groups.push((vec![l], radial));
                 }
-                if let Some(zaxis) = zaxis {
-                    proj.push_str(&format!(":z={},{},{}", zaxis[0], zaxis[1], zaxis[2]));
-                };
-                if let Some(xaxis) = xaxis {
-                    proj.push_str(&format!(":x={},{},{}", xaxis[0], xaxis[1], xaxis[2]));
-                };
-                if let Some(radial) = radial {
-                    proj.push_str(&format!(":r={}", radial));
-                };
-                if let Some(zona) = zona {
-                    proj.push_str(&format!(":zona={}", zona));
-                };
-                proj
             }
+            if groups.is_empty() {
+                groups.push((Vec::new(), (radial, zona)));
+            }
+
+            let lines: Vec<String> = groups
+                .iter()
+                .map(|&(ref ls, (radial, zona))| {
+                    let mut line = format!("{}:", format_site(site, options));
+                    line.push_str(&angular_momenta(ls, spin, quant_dir, options));
+                    line.push_str(&projection_options(zaxis, xaxis, radial, zona, options));
+                    line
+                })
+                .collect();
+            lines.join("\n")
         }
     }
 }
 
-impl Field for ProjectionSite {
-    fn value(&self) -> String {
-        match *self {
-            ProjectionSite::Species(ref species) => species.clone(),
-            ProjectionSite::CenterCartesian(r) => format!("c = {}, {}, {}", r[0], r[1], r[2]),
-            ProjectionSite::CenterCrystal(r) => format!("f = {}, {}, {}", r[0], r[1], r[2]),
+/// Angular momentum part of a projection line, followed by the spin channel and
+/// quantisation axis for spinor projections, as in `l=1;l=2(u)[0,0,1]`.
+fn angular_momenta(
+    ang_mtm: &[&AngularMomentum],
+    spin: &Option<SpinProjection>,
+    quant_dir: Option<[f64; 3]>,
+    options: &SerializeOptions,
+) -> String {
+    let mut part = ang_mtm
+        .iter()
+        .map(|l| l.value())
+        .collect::<Vec<String>>()
+        .join(";");
+
+    if let Some(ref spin) = *spin {
+        part.push_str(&format!("({})", spin.value()));
+        if let Some(q) = quant_dir {
+            part.push_str(&format!("[{}]", options.vector(q, ",")));
         }
     }
+    part
 }
 
-impl Field for AngularMomentum {
+fn projection_options(
+    zaxis: Option<[f64; 3]>,
+    xaxis: Option<[f64; 3]>,
+    radial: Option<u64>,
+    zona: Option<f64>,
+    options: &SerializeOptions,
+) -> String {
+    let mut part = String::new();
+    if let Some(zaxis) = zaxis {
+        part.push_str(&format!(":z={}", options.vector(zaxis, ",")));
+    };
+    if let Some(xaxis) = xaxis {
+        part.push_str(&format!(":x={}", options.vector(xaxis, ",")));
+    };
+    if let Some(radial) = radial {
+        part.push_str(&format!(":r={}", radial));
+    };
+    if let Some(zona) = zona {
+        part.push_str(&format!(":zona={}", zona));
+    };
+    part
+}
+
+impl Field for SpinProjection {
     fn value(&self) -> String {
         String::from(match *self {
-            AngularMomentum::S => "l=0",
-            AngularMomentum::P => "l=1",
-            AngularMomentum::D => "l=2",
-            AngularMomentum::F => "l=3",
+            SpinProjection::Up => "u",
+            SpinProjection::Down => "d",
+            SpinProjection::Both => "u,d",
         })
     }
 }
+
+impl Field for ProjectionSite {
+    fn value(&self) -> String {
+        format_site(self, &SerializeOptions::exact())
+    }
+}
+
+fn format_site(site: &ProjectionSite, options: &SerializeOptions) -> String {
+    match *site {
+        ProjectionSite::Species(ref species) => species.clone(),
+        ProjectionSite::CenterCartesian(r) => format!("c = {}", options.vector(r, ", ")),
+        ProjectionSite::CenterCrystal(r) => format!("f = {}", options.vector(r, ", ")),
+    }
+}
+
+impl Field for AngularMomentum {
+    fn value(&self) -> String {
+        match *self {
+            AngularMomentum::Orbitals { l, ref mr } => format!(
+                "l={},mr={}",
+                l,
+                mr.iter()
+                    .map(|m| m.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            _ => format!("l={}", self.l()),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Reading values from the whitespace-separated tokens of Wannier90 output files, shared
+//! by the readers in `read` and `hr`.
+use std::str::FromStr;
+use num_complex::Complex64;
+
+/// Parse a single token. On failure, gives the token which could not be parsed.
+pub fn parse_token<T: FromStr>(tok: &str) -> Result<T, String> {
+    tok.parse().map_err(|_| String::from(tok))
+}
+
+/// Failure to read a value from a stream of whitespace-separated tokens.
+pub enum TokenError {
+    Eof,
+    Invalid(String),
+}
+
+/// Parse the next token from `tokens`.
+pub fn next_parsed<'a, T: FromStr, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+) -> Result<T, TokenError> {
+    let tok = tokens.next().ok_or(TokenError::Eof)?;
+
+    parse_token(tok).map_err(TokenError::Invalid)
+}
+
+pub fn next_r_vector<'a, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+) -> Result<[i64; 3], TokenError> {
+    Ok([
+        next_parsed(tokens)?,
+        next_parsed(tokens)?,
+        next_parsed(tokens)?,
+    ])
+}
+
+pub fn next_complex<'a, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+) -> Result<Complex64, TokenError> {
+    let re = next_parsed(tokens)?;
+    let im = next_parsed(tokens)?;
+
+    Ok(Complex64::new(re, im))
+}
+
+/// Failure to read the blocks of matrix elements given by `next_r_blocks`.
+pub enum RBlockError {
+    Token(TokenError),
+    UnexpectedIndex { r: [i64; 3], m: usize, n: usize },
+}
+
+impl From<TokenError> for RBlockError {
+    fn from(e: TokenError) -> RBlockError {
+        RBlockError::Token(e)
+    }
+}
+
+/// R-vectors and matrices `[m][n]` of `nrpts` blocks of `num_wann^2` matrix elements.
+/// Each element is given by `R1 R2 R3 m n` followed by the value read by `next_value`,
+/// with `R` fixed within each block and the one-based `m` varying fastest.
+pub fn next_r_blocks<'a, I, T, F>(
+    tokens: &mut I,
+    nrpts: usize,
+    num_wann: usize,
+    mut next_value: F,
+) -> Result<(Vec<[i64; 3]>, Vec<Vec<Vec<T>>>), RBlockError>
+where
+    I: Iterator<Item = &'a str>,
+    T: Clone + Default,
+    F: FnMut(&mut I) -> Result<T, TokenError>,
+{
+    let mut r_vectors = Vec::with_capacity(nrpts);
+    let mut blocks = Vec::with_capacity(nrpts);
+
+    for _ in 0..nrpts {
+        let mut r = [0; 3];
+        let mut block = vec![vec![T::default(); num_wann]; num_wann];
+
+        for n in 0..num_wann {
+            for m in 0..num_wann {
+                let this_r = next_r_vector(tokens)?;
+                let this_m: usize = next_parsed(tokens)?;
+                let this_n: usize = next_parsed(tokens)?;
+
+                if m == 0 && n == 0 {
+                    r = this_r;
+                }
+                if this_r != r || this_m != m + 1 || this_n != n + 1 {
+                    return Err(RBlockError::UnexpectedIndex {
+                        r: this_r,
+                        m: this_m,
+                        n: this_n,
+                    });
+                }
+
+                block[m][n] = next_value(tokens)?;
+            }
+        }
+
+        r_vectors.push(r);
+        blocks.push(block);
+    }
+
+    Ok((r_vectors, blocks))
+}
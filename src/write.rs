@@ -0,0 +1,229 @@
+use std::path::Path;
+use std::io;
+use std::io::Write;
+use std::fs::File;
+use num_complex::Complex64;
+use read::{Checkpoint, CHK_CHECKPOINT_LEN, CHK_HEADER_LEN, RECORD_MARKER_SIZE};
+
+/// Write `chk` to `file_path` as a checkpoint file `seedname.chk`, which can then be read
+/// by postw90.x or used to restart Wannier90.
+pub fn write_chk<P: AsRef<Path>>(chk: &Checkpoint, file_path: P) -> Result<(), ChkWriteError> {
+    let data = make_chk(chk)?;
+    File::create(file_path)?.write_all(&data)?;
+
+    Ok(())
+}
+
+/// Contents of the checkpoint file for `chk`, in the format described for
+/// `read::parse_chk`. The character records are padded with spaces, so that a file read by
+/// `parse_chk` is reproduced byte for byte if its character records were padded in the
+/// same way, as done by Wannier90.
+///
+/// Fails if the dimensions of the arrays in `chk` do not match `num_kpts`, `num_bands`,
+/// `num_wann` and `nntot`.
+pub fn make_chk(chk: &Checkpoint) -> Result<Vec<u8>, ChkWriteError> {
+    let mut w = Records { data: Vec::new() };
+
+    w.text("header", &chk.header, CHK_HEADER_LEN)?;
+    w.counts("num_bands", &[chk.num_bands])?;
+    w.counts("num_exclude_bands", &[chk.exclude_bands.len()])?;
+    w.counts("exclude_bands", &chk.exclude_bands)?;
+    w.reals("real_lattice", &fortran_vectors(&chk.real_lattice))?;
+    w.reals("recip_lattice", &fortran_vectors(&chk.recip_lattice))?;
+    w.counts("num_kpts", &[chk.num_kpts])?;
+    w.counts("mp_grid", &chk.mp_grid)?;
+    check_len("kpt_latt", chk.kpoints.len(), chk.num_kpts)?;
+    w.reals("kpt_latt", &flatten_columns(&chk.kpoints))?;
+    w.counts("nntot", &[chk.nntot])?;
+    w.counts("num_wann", &[chk.num_wann])?;
+    w.text("checkpoint", &chk.checkpoint, CHK_CHECKPOINT_LEN)?;
+
+    match chk.disentanglement {
+        Some(ref dis) => {
+            w.logicals("have_disentangled", &[true])?;
+            w.reals("omega_invariant", &[dis.omega_invariant])?;
+
+            check_len("lwindow", dis.lwindow.len(), chk.num_kpts)?;
+            let mut lwindow = Vec::new();
+            for lwindow_k in &dis.lwindow {
+                check_len("lwindow", lwindow_k.len(), chk.num_bands)?;
+                lwindow.extend(lwindow_k);
+            }
+            w.logicals("lwindow", &lwindow)?;
+
+            check_len("ndimwin", dis.ndimwin.len(), chk.num_kpts)?;
+            w.counts("ndimwin", &dis.ndimwin)?;
+
+            check_len("u_matrix_opt", dis.u_dis.len(), chk.num_kpts)?;
+            let u_dis =
+                flatten_matrices("u_matrix_opt", &dis.u_dis, chk.num_bands, chk.num_wann)?;
+            w.complexes("u_matrix_opt", &u_dis)?;
+        }
+        None => w.logicals("have_disentangled", &[false])?,
+    }
+
+    check_len("u_matrix", chk.u.len(), chk.num_kpts)?;
+    let u = flatten_matrices("u_matrix", &chk.u, chk.num_wann, chk.num_wann)?;
+    w.complexes("u_matrix", &u)?;
+
+    check_len("m_matrix", chk.m.len(), chk.num_kpts)?;
+    let mut m = Vec::new();
+    for m_k in &chk.m {
+        check_len("m_matrix", m_k.len(), chk.nntot)?;
+        m.extend(flatten_matrices("m_matrix", m_k, chk.num_wann, chk.num_wann)?);
+    }
+    w.complexes("m_matrix", &m)?;
+
+    check_len("wannier_centres", chk.centres.len(), chk.num_wann)?;
+    w.reals("wannier_centres", &flatten_columns(&chk.centres))?;
+    check_len("wannier_spreads", chk.spreads.len(), chk.num_wann)?;
+    w.reals("wannier_spreads", &chk.spreads)?;
+
+    Ok(w.data)
+}
+
+/// Records of a Fortran unformatted sequential file, with little-endian record markers of
+/// `RECORD_MARKER_SIZE` bytes.
+struct Records {
+    data: Vec<u8>,
+}
+
+impl Records {
+    fn next(&mut self, record: &'static str, contents: &[u8]) -> Result<(), ChkWriteError> {
+        if contents.len() > u32::max_value() as usize {
+            return Err(ChkWriteError::TooLarge(record));
+        }
+        let marker = le_bytes(contents.len() as u64, RECORD_MARKER_SIZE);
+
+        self.data.extend(&marker);
+        self.data.extend(contents);
+        self.data.extend(&marker);
+
+        Ok(())
+    }
+
+    fn text(&mut self, record: &'static str, text: &str, len: usize) -> Result<(), ChkWriteError> {
+        if text.len() > len {
+            return Err(ChkWriteError::TextTooLong { record, len });
+        }
+
+        self.next(record, format!("{:1$}", text, len).as_bytes())
+    }
+
+    fn counts(&mut self, record: &'static str, values: &[usize]) -> Result<(), ChkWriteError> {
+        let mut contents = Vec::with_capacity(4 * values.len());
+        for &value in values {
+            if value > i32::max_value() as usize {
+                return Err(ChkWriteError::TooLarge(record));
+            }
+            contents.extend(le_bytes(value as u64, 4));
+        }
+
+        self.next(record, &contents)
+    }
+
+    fn logicals(&mut self, record: &'static str, values: &[bool]) -> Result<(), ChkWriteError> {
+        let contents: Vec<u8> = values
+            .iter()
+            .flat_map(|&b| le_bytes(b as u64, 4))
+            .collect();
+
+        self.next(record, &contents)
+    }
+
+    fn reals(&mut self, record: &'static str, values: &[f64]) -> Result<(), ChkWriteError> {
+        let contents: Vec<u8> = values
+            .iter()
+            .flat_map(|x| le_bytes(x.to_bits(), 8))
+            .collect();
+
+        self.next(record, &contents)
+    }
+
+    fn complexes(
+        &mut self,
+        record: &'static str,
+        values: &[Complex64],
+    ) -> Result<(), ChkWriteError> {
+        let contents: Vec<u8> = values
+            .iter()
+            .flat_map(|z| {
+                let mut bytes = le_bytes(z.re.to_bits(), 8);
+                bytes.extend(le_bytes(z.im.to_bits(), 8));
+                bytes
+            })
+            .collect();
+
+        self.next(record, &contents)
+    }
+}
+
+/// The lowest `len` bytes of `x`, least significant first.
+fn le_bytes(x: u64, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (x >> (8 * i)) as u8).collect()
+}
+
+fn check_len(record: &'static str, len: usize, expected: usize) -> Result<(), ChkWriteError> {
+    if len == expected {
+        Ok(())
+    } else {
+        Err(ChkWriteError::Dimension {
+            record,
+            len,
+            expected,
+        })
+    }
+}
+
+/// The Fortran array `a(3, 3)` with `a(i, j) = v[i][j]`, first index fastest.
+fn fortran_vectors(v: &[[f64; 3]; 3]) -> Vec<f64> {
+    (0..3)
+        .flat_map(|j| (0..3).map(move |i| v[i][j]))
+        .collect()
+}
+
+/// The Fortran array `a(3, n)` with columns `v`.
+fn flatten_columns(v: &[[f64; 3]]) -> Vec<f64> {
+    v.iter().flat_map(|c| c.iter().cloned()).collect()
+}
+
+/// The Fortran array `a(rows, cols, count)` from the `count` matrices `matrices[k][m][n]`,
+/// first index fastest.
+fn flatten_matrices(
+    record: &'static str,
+    matrices: &[Vec<Vec<Complex64>>],
+    rows: usize,
+    cols: usize,
+) -> Result<Vec<Complex64>, ChkWriteError> {
+    let mut a = Vec::with_capacity(matrices.len() * rows * cols);
+    for matrix in matrices {
+        check_len(record, matrix.len(), rows)?;
+        for row in matrix {
+            check_len(record, row.len(), cols)?;
+        }
+
+        for n in 0..cols {
+            for m in 0..rows {
+                a.push(matrix[m][n]);
+            }
+        }
+    }
+
+    Ok(a)
+}
+
+#[derive(Fail, Debug)]
+pub enum ChkWriteError {
+    #[fail(display = "{}", _0)] Io(#[cause] io::Error),
+    #[fail(display = "Record `{}` is longer than {} characters.", record, len)]
+    TextTooLong { record: &'static str, len: usize },
+    #[fail(display = "Record `{}` has dimension {}, but expected {}.", record, len, expected)]
+    Dimension { record: &'static str, len: usize, expected: usize },
+    #[fail(display = "Record `{}` is too large.", _0)] TooLarge(&'static str),
+}
+
+impl From<io::Error> for ChkWriteError {
+    fn from(e: io::Error) -> ChkWriteError {
+        ChkWriteError::Io(e)
+    }
+}
@@ -0,0 +1,77 @@
+extern crate num_complex;
+extern crate w90;
+
+use num_complex::Complex64;
+use w90::hr;
+
+#[test]
+fn parse_hr() {
+    let text = " written on 14Oct2026 at 12:00:00
+          2
+          1
+    1
+    0    0    0    1    1   -1.000000    0.000000
+    0    0    0    2    1    0.500000    0.250000
+    0    0    0    1    2    0.500000   -0.250000
+    0    0    0    2    2    1.000000    0.000000
+";
+
+    let ham = hr::parse_hr(text).unwrap();
+
+    assert_eq!(ham.num_wann, 2);
+    assert_eq!(ham.r_vectors, vec![[0, 0, 0]]);
+    assert_eq!(ham.degeneracies, vec![1]);
+    assert_eq!(ham.hoppings[0][1][0], Complex64::new(0.5, 0.25));
+    assert_eq!(ham.hoppings[0][0][1], Complex64::new(0.5, -0.25));
+
+    assert!(hr::check_nrpts(&ham, [1, 1, 1]).is_ok());
+    assert!(hr::check_nrpts(&ham, [2, 1, 1]).is_err());
+
+    match hr::parse_hr(&format!("{}    0    0    0    1    1    0.1    0.0\n", text)) {
+        Err(hr::Error::UnexpectedToken(ref tok)) => assert_eq!(tok, "0"),
+        r => panic!("unexpected result {:?}", r),
+    }
+    let wrong_r = text.replacen("    0    0    0    2    1", "    1    0    0    2    1", 1);
+    match hr::parse_hr(&wrong_r) {
+        Err(hr::Error::UnexpectedIndex { r, m, n }) => assert_eq!((r, m, n), ([1, 0, 0], 2, 1)),
+        r => panic!("unexpected result {:?}", r),
+    }
+    match hr::parse_hr(&text[..text.len() - 10]) {
+        Err(hr::Error::UnexpectedEof) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn expected_nrpts_444() {
+    assert_eq!(hr::expected_nrpts([4, 4, 4]), 64);
+
+    let mut r_vectors = Vec::new();
+    for i in -1..3 {
+        for j in -1..3 {
+            for k in -1..3 {
+                r_vectors.push([i, j, k]);
+            }
+        }
+    }
+
+    let mut ham = hr::HamiltonianR {
+        num_wann: 1,
+        degeneracies: vec![1; r_vectors.len()],
+        hoppings: vec![vec![vec![Complex64::new(0.0, 0.0)]]; r_vectors.len()],
+        r_vectors,
+    };
+    assert!(hr::check_nrpts(&ham, [4, 4, 4]).is_ok());
+
+    // [2, 0, 0] lies on the supercell boundary, where it is equivalent to
+    // [-2, 0, 0] = [2, 0, 0] - [4, 0, 0]: include both, each shared with degeneracy 2.
+    let boundary = ham.r_vectors.iter().position(|&r| r == [2, 0, 0]).unwrap();
+    ham.degeneracies[boundary] = 2;
+    ham.r_vectors.push([-2, 0, 0]);
+    ham.degeneracies.push(2);
+    ham.hoppings.push(vec![vec![Complex64::new(0.0, 0.0)]]);
+    assert!(hr::check_nrpts(&ham, [4, 4, 4]).is_ok());
+
+    ham.degeneracies[0] = 2;
+    assert!(hr::check_nrpts(&ham, [4, 4, 4]).is_err());
+}
@@ -0,0 +1,101 @@
+extern crate num_complex;
+extern crate w90;
+
+use std::f64::consts::PI;
+use num_complex::Complex64;
+use w90::hr::HamiltonianR;
+use w90::input::{Cell, LatticeUnits};
+use w90::interpolate;
+use w90::kpath::{KPath, SymmetryPoint};
+
+fn chain_cell() -> Cell {
+    Cell {
+        units: LatticeUnits::Angstrom,
+        cell: [[2.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]],
+    }
+}
+
+fn c(re: f64) -> Complex64 {
+    Complex64::new(re, 0.0)
+}
+
+#[test]
+fn reciprocal_lattice() {
+    let cell = Cell {
+        units: LatticeUnits::Bohr,
+        cell: [[1.0, 1.0, 0.0], [0.0, 1.0, 1.0], [1.0, 0.0, 1.0]],
+    };
+    let recip = cell.reciprocal().unwrap();
+
+    for i in 0..3 {
+        for j in 0..3 {
+            let a_dot_b: f64 = (0..3).map(|x| cell.cell[i][x] * recip[j][x]).sum();
+            let expected = if i == j { 2.0 * PI } else { 0.0 };
+            assert!((a_dot_b - expected).abs() < 1e-12);
+        }
+    }
+}
+
+#[test]
+fn chain_band_path() {
+    // Nearest-neighbor chain: E(k) = e0 + 2 t cos(2 pi k).
+    let (e0, t) = (0.5, -1.0);
+    let ham = HamiltonianR {
+        num_wann: 1,
+        r_vectors: vec![[-1, 0, 0], [0, 0, 0], [1, 0, 0]],
+        degeneracies: vec![1, 1, 1],
+        hoppings: vec![vec![vec![c(t)]], vec![vec![c(e0)]], vec![vec![c(t)]]],
+    };
+    let path = KPath::through(&[
+        SymmetryPoint::new("G", [0.0, 0.0, 0.0]),
+        SymmetryPoint::new("X", [0.5, 0.0, 0.0]),
+    ]);
+
+    let bands = interpolate::band_path(&ham, &chain_cell(), &path, 10);
+
+    assert_eq!(bands.kpoints.len(), 11);
+    assert_eq!(bands.distances.len(), 11);
+    // |X - G| = 0.5 * 2 pi / a.
+    assert!((bands.distances[10] - PI / 2.0).abs() < 1e-12);
+
+    for (k, e) in bands.kpoints.iter().zip(bands.energies[0].iter()) {
+        let expected = e0 + 2.0 * t * (2.0 * PI * k[0]).cos();
+        assert!((e - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn two_band_chain() {
+    // Two-site chain with intracell hopping v and intercell hopping w:
+    // E(k) = +/- |v + w exp(-2 pi i k)|.
+    let (v, w) = (1.0, 0.4);
+    let zero = c(0.0);
+    let ham = HamiltonianR {
+        num_wann: 2,
+        r_vectors: vec![[-1, 0, 0], [0, 0, 0], [1, 0, 0]],
+        degeneracies: vec![1, 1, 1],
+        hoppings: vec![
+            vec![vec![zero, c(w)], vec![zero, zero]],
+            vec![vec![zero, c(v)], vec![c(v), zero]],
+            vec![vec![zero, zero], vec![c(w), zero]],
+        ],
+    };
+    // Disconnected path: the jump from X back to G does not add distance.
+    let gamma = SymmetryPoint::new("G", [0.0, 0.0, 0.0]);
+    let x = SymmetryPoint::new("X", [0.5, 0.0, 0.0]);
+    let path = KPath {
+        segments: vec![(gamma.clone(), x.clone()), (gamma.clone(), x.clone())],
+    };
+
+    let bands = interpolate::band_path(&ham, &chain_cell(), &path, 8);
+
+    assert_eq!(bands.kpoints.len(), 18);
+    assert_eq!(bands.distances[8], bands.distances[9]);
+    assert_eq!(bands.kpoints[9], [0.0, 0.0, 0.0]);
+
+    for (i, k) in bands.kpoints.iter().enumerate() {
+        let gap = (Complex64::new(v, 0.0) + w * Complex64::new(0.0, -2.0 * PI * k[0]).exp()).norm();
+        assert!((bands.energies[0][i] + gap).abs() < 1e-10);
+        assert!((bands.energies[1][i] - gap).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,98 @@
+extern crate w90;
+
+use std::f64::consts::PI;
+use w90::input::{Cell, LatticeUnits};
+use w90::kpath;
+use w90::kpath::BravaisLattice;
+
+fn cell(cell: [[f64; 3]; 3]) -> Cell {
+    Cell {
+        units: LatticeUnits::Bohr,
+        cell,
+    }
+}
+
+fn cartesian_length(cell: &Cell, k: [f64; 3]) -> f64 {
+    let b = cell.reciprocal().unwrap();
+    let v: Vec<f64> = (0..3)
+        .map(|j| (0..3).map(|i| k[i] * b[i][j]).sum())
+        .collect();
+
+    v.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+#[test]
+fn hexagonal_path() {
+    // WSe2 cell from tests/w90_input.rs.
+    let hex = cell([
+        [3.13603975949, -5.43178019799, 0.0],
+        [3.13603975949, 5.43178019799, 0.0],
+        [0.0, 0.0, 68.6629186029],
+    ]);
+    assert_eq!(kpath::bravais_lattice(&hex).unwrap(), BravaisLattice::Hexagonal);
+
+    let path = kpath::standard_path(&hex).unwrap();
+    let labels: Vec<(&str, &str)> = path.segments
+        .iter()
+        .map(|&(ref a, ref b)| (a.label.as_str(), b.label.as_str()))
+        .collect();
+    assert_eq!(
+        labels,
+        vec![
+            ("G", "M"),
+            ("M", "K"),
+            ("K", "G"),
+            ("G", "A"),
+            ("A", "L"),
+            ("L", "H"),
+            ("H", "A"),
+            ("L", "M"),
+            ("K", "H"),
+        ]
+    );
+
+    // |K| = 4 pi / (3 a) and |M| = 2 pi / (sqrt(3) a).
+    let a = 2.0 * 3.13603975949;
+    let k = path.segments[1].1.k;
+    let m = path.segments[0].1.k;
+    assert!((cartesian_length(&hex, k) - 4.0 * PI / (3.0 * a)).abs() < 1e-10);
+    assert!((cartesian_length(&hex, m) - 2.0 * PI / (3.0f64.sqrt() * a)).abs() < 1e-10);
+}
+
+#[test]
+fn cubic_paths() {
+    let a = 10.2;
+    let fcc = cell([
+        [0.0, a / 2.0, a / 2.0],
+        [a / 2.0, 0.0, a / 2.0],
+        [a / 2.0, a / 2.0, 0.0],
+    ]);
+    assert_eq!(
+        kpath::bravais_lattice(&fcc).unwrap(),
+        BravaisLattice::FaceCenteredCubic
+    );
+    let path = kpath::standard_path(&fcc).unwrap();
+    let x = path.segments[0].1.clone();
+    assert_eq!(x.label, "X");
+    assert!((cartesian_length(&fcc, x.k) - 2.0 * PI / a).abs() < 1e-10);
+
+    let bcc = cell([
+        [-a / 2.0, a / 2.0, a / 2.0],
+        [a / 2.0, -a / 2.0, a / 2.0],
+        [a / 2.0, a / 2.0, -a / 2.0],
+    ]);
+    assert_eq!(
+        kpath::bravais_lattice(&bcc).unwrap(),
+        BravaisLattice::BodyCenteredCubic
+    );
+    let h = kpath::standard_path(&bcc).unwrap().segments[0].1.clone();
+    assert_eq!(h.label, "H");
+    assert!((cartesian_length(&bcc, h.k) - 2.0 * PI / a).abs() < 1e-10);
+
+    let cub = cell([[a, 0.0, 0.0], [0.0, a, 0.0], [0.0, 0.0, a]]);
+    assert_eq!(kpath::bravais_lattice(&cub).unwrap(), BravaisLattice::Cubic);
+    assert_eq!(kpath::standard_path(&cub).unwrap().segments.len(), 6);
+
+    let orthorhombic = cell([[a, 0.0, 0.0], [0.0, 1.1 * a, 0.0], [0.0, 0.0, 1.2 * a]]);
+    assert!(kpath::standard_path(&orthorhombic).is_err());
+}
@@ -0,0 +1,442 @@
+extern crate qe;
+extern crate w90;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use qe::pw::input::Input as PwInput;
+use qe::pw::input::{AtomicPositions, Calculation, Ibrav, KPoints, Occupations, Smearing,
+                    SpinType, System};
+use qe::pw::input::AtomCoordinate as PwAtomCoordinate;
+use qe::pw::input::Cell as PwCell;
+use qe::pw::input::LatticeUnits as PwLatticeUnits;
+use qe::pw::input::PositionCoordinateType as PwCoord;
+use w90::input::{Disentanglement, LatticeUnits, MLWFIterationMode, PositionCoordinateType,
+                 SpinChannel};
+use w90::qe_workflow;
+use w90::qe_workflow::{NumBands, OccupiedBands, Workflow};
+
+fn energy_window(dis_win_min: Option<f64>, dis_win_max: Option<f64>) -> Disentanglement {
+    Disentanglement {
+        dis_win_min,
+        dis_win_max,
+        dis_froz_min: Some(-4.0),
+        dis_froz_max: Some(6.0),
+        dis_num_iter: 1000,
+        dis_mix_ratio: 0.5,
+        dis_spheres: None,
+        dis_froz_proj: None,
+        dis_proj_min: None,
+        dis_proj_max: None,
+    }
+}
+
+const ALAT: f64 = 6.2;
+
+/// scf calculation for a WSe2 monolayer, with the cell and the atomic positions given in
+/// units of `alat`.
+fn scf_input() -> PwInput {
+    let atom = |species: &str, r| PwAtomCoordinate {
+        species: species.to_string(),
+        r,
+    };
+
+    PwInput {
+        calculation: Calculation::Scf { conv_thr: 1e-10 },
+        system: System {
+            ibrav: Ibrav::Free(PwCell {
+                units: PwLatticeUnits::Alat,
+                cell: [[1.0, 0.0, 0.0], [-0.5, 0.75f64.sqrt(), 0.0], [0.0, 0.0, 8.0]],
+            }),
+            alat: ALAT,
+            occupations: Occupations::Fixed,
+            spin_type: None,
+        },
+        atomic_positions: AtomicPositions {
+            coordinate_type: PwCoord::AlatCartesian,
+            coordinates: vec![
+                atom("W", [0.0, 0.0, 0.0]),
+                atom("Se", [0.5, 0.25, 0.5]),
+                atom("Se", [0.5, 0.25, -0.5]),
+            ],
+        },
+        k_points: KPoints::CrystalUniform([12, 12, 1]),
+    }
+}
+
+#[test]
+fn positions_from_scf() {
+    let scf = scf_input();
+    let (cell, positions) = qe_workflow::positions_from_scf(&scf).unwrap();
+
+    // Lengths in units of `alat` are converted to Bohr.
+    assert_eq!(cell.units, LatticeUnits::Bohr);
+    assert_eq!(cell.cell[1], [-0.5 * ALAT, 0.75f64.sqrt() * ALAT, 0.0]);
+    assert_eq!(cell.cell[2], [0.0, 0.0, 8.0 * ALAT]);
+
+    assert_eq!(positions.coordinate_type, PositionCoordinateType::BohrCartesian);
+    let species: Vec<&str> = positions.coordinates.iter().map(|c| c.species.as_str()).collect();
+    assert_eq!(species, vec!["W", "Se", "Se"]);
+    assert_eq!(positions.coordinates[1].r, [0.5 * ALAT, 0.25 * ALAT, 0.5 * ALAT]);
+    assert_eq!(positions.coordinates[2].r, [0.5 * ALAT, 0.25 * ALAT, -0.5 * ALAT]);
+    assert!(positions.coordinates.iter().all(|c| c.label.is_none()));
+
+    let mut sg = scf_input();
+    sg.atomic_positions.coordinate_type = PwCoord::CrystalSG;
+    match qe_workflow::positions_from_scf(&sg) {
+        Err(qe_workflow::Error::CrystalSG) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+fn workflow(num_bands: NumBands, bands_kpoints: Option<KPoints>) -> Workflow {
+    Workflow {
+        scf: scf_input(),
+        prefix: String::from("wse2"),
+        outdir: String::from("./work"),
+        seedname: String::from("wse2"),
+        diago_thr_init: 1e-6,
+        num_bands,
+        smearing_type: Smearing::MarzariVanderbilt,
+        smearing_size: 0.01,
+        nscf_nk: [9, 9, 1],
+        bands_kpoints,
+        num_wann: 22,
+        mlwf_iteration_mode: MLWFIterationMode::MLWF { num_iter: 100 },
+        disentanglement: energy_window(None, None),
+        projection_units: None,
+        projections: Vec::new(),
+    }
+}
+
+fn nbnd(pw: &PwInput) -> Option<u64> {
+    match pw.calculation {
+        Calculation::Nscf { nbnd, .. } | Calculation::Bands { nbnd, .. } => nbnd,
+        Calculation::Scf { .. } => None,
+    }
+}
+
+#[test]
+fn workflow_generate() {
+    let bands_kpoints = KPoints::CrystalBands {
+        nk_per_panel: 20,
+        panel_bounds: vec![[0.0, 0.0, 0.0], [0.5, 0.0, 0.0]],
+    };
+
+    // `num_bands` is propagated to `nbnd` of both pw.x calculations and to Wannier90.
+    for &(ref num_bands, expected) in &[(NumBands::Explicit(30), 30), (NumBands::Auto, 27)] {
+        let inputs = workflow(num_bands.clone(), Some(bands_kpoints.clone()))
+            .generate()
+            .unwrap();
+        let bands = inputs.bands.unwrap();
+
+        assert_eq!(nbnd(&inputs.nscf), Some(expected));
+        assert_eq!(nbnd(&bands), Some(expected));
+        assert_eq!(inputs.w90.num_bands, expected);
+        assert_eq!(bands.k_points, bands_kpoints);
+        assert_eq!(inputs.pw2wannier90.seedname, "wse2");
+    }
+
+    let inputs = workflow(NumBands::Explicit(30), None).generate().unwrap();
+    assert!(inputs.bands.is_none());
+
+    match workflow(NumBands::Explicit(20), None).generate() {
+        Err(qe_workflow::Error::NumWannExceedsNumBands) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn smearing_outer_window() {
+    let smearing_size = 0.01;
+    let margin = 10.0 * smearing_size * 13.605693009;
+
+    let dis = qe_workflow::smearing_outer_window(&energy_window(None, None), smearing_size);
+    assert!((dis.dis_win_min.unwrap() - (-4.0 - margin)).abs() < 1e-12);
+    assert!((dis.dis_win_max.unwrap() - (6.0 + margin)).abs() < 1e-12);
+
+    let given = energy_window(Some(-10.0), None);
+    let dis = qe_workflow::smearing_outer_window(&given, smearing_size);
+    assert_eq!(dis.dis_win_min, Some(-10.0));
+    assert!((dis.dis_win_max.unwrap() - (6.0 + margin)).abs() < 1e-12);
+
+    // Without a frozen window bound, the outer bound is left to Wannier90.
+    let mut no_froz_max = energy_window(None, None);
+    no_froz_max.dis_froz_max = None;
+    let dis = qe_workflow::smearing_outer_window(&no_froz_max, smearing_size);
+    assert!(dis.dis_win_min.is_some());
+    assert_eq!(dis.dis_win_max, None);
+}
+
+#[test]
+fn num_occupied_bands() {
+    // Non-polarized WSe2 monolayer: 26 valence electrons fill 13 bands.
+    assert_eq!(qe_workflow::num_occupied_bands(26.0, false), Some(13));
+    assert_eq!(qe_workflow::num_occupied_bands(25.0, false), None);
+
+    // With spinors, each band holds a single electron.
+    assert_eq!(qe_workflow::num_occupied_bands(26.0, true), Some(26));
+    assert_eq!(qe_workflow::num_occupied_bands(25.5, true), None);
+}
+
+#[test]
+fn occupied_bands() {
+    let mut z_valence = HashMap::new();
+    z_valence.insert("W".to_string(), 14.0);
+    z_valence.insert("Se".to_string(), 6.0);
+
+    let species = vec!["W", "Se", "Se"];
+    let num_electrons = qe_workflow::num_valence_electrons(species, &z_valence).unwrap();
+    assert_eq!(num_electrons, 26.0);
+
+    match qe_workflow::num_valence_electrons(vec!["W", "S"], &z_valence) {
+        Err(qe_workflow::Error::UnknownValence(ref s)) => assert_eq!(s, "S"),
+        e => panic!("unexpected result {:?}", e),
+    }
+
+    let occupied = |spin_type, m| qe_workflow::occupied_bands(26.0, &spin_type, m).unwrap();
+    assert_eq!(occupied(None, None), OccupiedBands::Bands(13));
+    assert_eq!(occupied(Some(SpinType::NonPolarized), None), OccupiedBands::Bands(13));
+    assert_eq!(
+        occupied(Some(SpinType::Noncollinear { spin_orbit: true }), None),
+        OccupiedBands::Bands(26)
+    );
+
+    // Collinear: the magnetization fixes the split between the spin channels.
+    let collinear = Some(SpinType::CollinearPolarized);
+    assert_eq!(
+        occupied(Some(SpinType::CollinearPolarized), Some(0.0)),
+        OccupiedBands::Collinear { up: 13, down: 13 }
+    );
+    assert_eq!(
+        occupied(Some(SpinType::CollinearPolarized), Some(2.0)),
+        OccupiedBands::Collinear { up: 14, down: 12 }
+    );
+
+    match qe_workflow::occupied_bands(26.0, &collinear, None) {
+        Err(qe_workflow::Error::NoMagnetization) => (),
+        e => panic!("unexpected result {:?}", e),
+    }
+    match qe_workflow::occupied_bands(26.0, &collinear, Some(1.0)) {
+        Err(qe_workflow::Error::Occupation) => (),
+        e => panic!("unexpected result {:?}", e),
+    }
+    match qe_workflow::occupied_bands(25.0, &None, None) {
+        Err(qe_workflow::Error::Occupation) => (),
+        e => panic!("unexpected result {:?}", e),
+    }
+}
+
+#[test]
+fn num_occupied() {
+    let mut z_valence = HashMap::new();
+    z_valence.insert("W".to_string(), 14.0);
+    z_valence.insert("Se".to_string(), 6.0);
+
+    // Non-polarized: 26 valence electrons fill 13 bands.
+    let mut nscf = scf_input();
+    assert_eq!(
+        qe_workflow::num_occupied(&nscf, &z_valence, None).unwrap(),
+        OccupiedBands::Bands(13)
+    );
+    nscf.system.spin_type = Some(SpinType::NonPolarized);
+    assert_eq!(
+        qe_workflow::num_occupied(&nscf, &z_valence, None).unwrap(),
+        OccupiedBands::Bands(13)
+    );
+
+    // Spinors: each band holds a single electron.
+    nscf.system.spin_type = Some(SpinType::Noncollinear { spin_orbit: true });
+    assert_eq!(
+        qe_workflow::num_occupied(&nscf, &z_valence, None).unwrap(),
+        OccupiedBands::Bands(26)
+    );
+
+    nscf.system.spin_type = Some(SpinType::CollinearPolarized);
+    assert_eq!(
+        qe_workflow::num_occupied(&nscf, &z_valence, Some(2.0)).unwrap(),
+        OccupiedBands::Collinear { up: 14, down: 12 }
+    );
+
+    z_valence.remove("Se");
+    match qe_workflow::num_occupied(&nscf, &z_valence, Some(2.0)) {
+        Err(qe_workflow::Error::UnknownValence(ref s)) => assert_eq!(s, "Se"),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn z_valence() {
+    let v2 = r#"<PP_HEADER
+    generated="Generated using ONCVPSP code"
+    element="Se"
+    z_valence="    6.000000000000000E+000"
+    />"#;
+    assert_eq!(qe_workflow::parse_z_valence(v2), Some(6.0));
+
+    let v1 = "<PP_HEADER>\n   0                   Version Number\n  W                    Element\n\
+              14.00000000000      Z valence\n</PP_HEADER>";
+    assert_eq!(qe_workflow::parse_z_valence(v1), Some(14.0));
+
+    assert_eq!(qe_workflow::parse_z_valence("<PP_HEADER />"), None);
+
+    let path = env::temp_dir().join("w90_z_valence_test.UPF");
+    File::create(&path).unwrap().write_all(v2.as_bytes()).unwrap();
+    assert_eq!(qe_workflow::read_z_valence(&path).unwrap(), 6.0);
+}
+
+#[test]
+fn pw2wannier90_input() {
+    let input = qe_workflow::Pw2Wannier90Input {
+        prefix: String::from("wse2"),
+        outdir: String::from("./work"),
+        seedname: String::from("wse2"),
+        write_mmn: true,
+        write_amn: true,
+        write_unk: false,
+        write_spn: true,
+        spin_component: None,
+    };
+    let expected = "&inputpp
+  prefix = 'wse2'
+  outdir = './work'
+  seedname = 'wse2'
+  write_mmn = .true.
+  write_amn = .true.
+  write_unk = .false.
+  write_spn = .true.
+/
+";
+    assert_eq!(input.make_input_file(), expected);
+
+    let collinear = qe_workflow::Pw2Wannier90Input {
+        write_spn: false,
+        ..input
+    };
+    assert!(!collinear.make_input_file().contains("write_spn"));
+    assert!(!collinear.make_input_file().contains("spin_component"));
+
+    let down = qe_workflow::Pw2Wannier90Input {
+        seedname: String::from("wse2_down"),
+        spin_component: Some(SpinChannel::Down),
+        ..collinear
+    };
+    let text = down.make_input_file();
+    assert!(text.contains("  seedname = 'wse2_down'\n"));
+    assert!(text.ends_with("  write_unk = .false.\n  spin_component = 'down'\n/\n"));
+}
+
+#[test]
+fn w90_input_collinear() {
+    let nscf_from = |spin_type| {
+        let mut scf = scf_input();
+        scf.system.spin_type = spin_type;
+        let smearing = Smearing::MarzariVanderbilt;
+        let num_bands = NumBands::Explicit(30);
+        qe_workflow::nscf_input(&scf, 1e-6, &num_bands, 22, &smearing, 0.01, [9, 9, 1]).unwrap()
+    };
+    let collinear = |nscf: &PwInput| {
+        let mode = MLWFIterationMode::MLWF { num_iter: 100 };
+        let dis = energy_window(None, None);
+        qe_workflow::w90_input_collinear(nscf, 22, &mode, &dis, None, Vec::new())
+    };
+
+    let nscf = nscf_from(Some(SpinType::CollinearPolarized));
+    let (up, down) = collinear(&nscf).unwrap();
+    assert_eq!(up.spin, Some(SpinChannel::Up));
+    assert_eq!(down.spin, Some(SpinChannel::Down));
+    assert_eq!(up.num_bands, 30);
+    assert_eq!(down.num_bands, up.num_bands);
+    assert!(!up.spinors && !down.spinors);
+
+    // Apart from the spin channel, the two inputs are identical.
+    let mut down_as_up = down.clone();
+    down_as_up.spin = Some(SpinChannel::Up);
+    assert_eq!(down_as_up, up);
+
+    for spin_type in vec![None, Some(SpinType::NonPolarized)] {
+        match collinear(&nscf_from(spin_type)) {
+            Err(qe_workflow::Error::NotCollinear) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
+
+#[test]
+fn suggest_num_bands() {
+    use qe_workflow::NumBands;
+
+    // 20% of 22 rounds up to 5 extra bands.
+    assert_eq!(qe_workflow::suggest_num_bands(22, 0.2, 4), 27);
+    // The minimum applies for small num_wann.
+    assert_eq!(qe_workflow::suggest_num_bands(5, 0.2, 4), 9);
+    assert_eq!(qe_workflow::suggest_num_bands(10, 0.0, 0), 10);
+
+    assert_eq!(NumBands::Auto.value(22), 27);
+    assert_eq!(NumBands::Auto.value(5), 9);
+    assert_eq!(NumBands::Explicit(30).value(22), 30);
+}
+
+#[test]
+fn nscf_input() {
+    let scf = scf_input();
+    let smearing = Smearing::MarzariVanderbilt;
+    let nscf = qe_workflow::nscf_input(&scf, 1e-6, &NumBands::Auto, 22, &smearing, 0.01, [9, 9, 1])
+        .unwrap();
+
+    let nbnd = qe_workflow::suggest_num_bands(
+        22,
+        qe_workflow::DEFAULT_EXTRA_BANDS_FRACTION,
+        qe_workflow::DEFAULT_MIN_EXTRA_BANDS,
+    );
+    assert_eq!(
+        nscf.calculation,
+        Calculation::Nscf {
+            diago_thr_init: 1e-6,
+            nbnd: Some(nbnd),
+            nosym: Some(true),
+        }
+    );
+    assert_eq!(nscf.system.occupations, Occupations::Smearing(smearing.clone(), 0.01));
+    assert_eq!(nscf.k_points, KPoints::CrystalUniform([9, 9, 1]));
+
+    // The nscf calculation must start from an scf calculation.
+    match qe_workflow::nscf_input(&nscf, 1e-6, &NumBands::Auto, 22, &smearing, 0.01, [9, 9, 1]) {
+        Err(qe_workflow::Error::WrongCalculation) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn kpath_from_qe() {
+    let bands_kpoints = KPoints::CrystalBands {
+        nk_per_panel: 20,
+        panel_bounds: vec![[0.0, 0.0, 0.0], [0.5, 0.0, 0.0], [1.0 / 3.0, 1.0 / 3.0, 0.0]],
+    };
+
+    let path = qe_workflow::kpath_from_qe(&bands_kpoints).unwrap();
+    assert_eq!(path.segments.len(), 2);
+    assert_eq!(path.segments[0].0.k, [0.0, 0.0, 0.0]);
+    assert_eq!(path.segments[0].1.k, [0.5, 0.0, 0.0]);
+    assert_eq!(path.segments[1].0, path.segments[0].1);
+    assert_eq!(path.segments[1].1.k, [1.0 / 3.0, 1.0 / 3.0, 0.0]);
+
+    assert!(qe_workflow::kpath_from_qe(&KPoints::CrystalUniform([8, 8, 1])).is_err());
+}
+
+#[test]
+fn num_bands_from_eig() {
+    let path = env::temp_dir().join("w90_num_bands_from_eig.eig");
+    File::create(&path)
+        .unwrap()
+        .write_all(b"1 1 -1.5\n2 1 0.5\n3 1 2.0\n1 2 -1.2\n2 2 0.7\n3 2 2.2\n")
+        .unwrap();
+    assert_eq!(qe_workflow::num_bands_from_eig(&path).unwrap(), 3);
+
+    File::create(&path).unwrap();
+    assert!(qe_workflow::num_bands_from_eig(&path).is_err());
+
+    let missing = env::temp_dir().join("w90_num_bands_from_eig_missing.eig");
+    assert!(qe_workflow::num_bands_from_eig(&missing).is_err());
+}
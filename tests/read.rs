@@ -0,0 +1,394 @@
+extern crate num_complex;
+extern crate w90;
+
+use std::env;
+use num_complex::Complex64;
+use w90::read;
+use w90::write;
+use w90::kslice;
+use w90::eig;
+use w90::eig::{BandGap, EigError};
+
+#[test]
+fn parse_band_dat() {
+    let dat = "  0.0000 -1.5000
+  0.5000 -1.0000
+  1.0000 -0.5000
+
+  0.0000  1.5000
+  0.5000  1.0000
+  1.0000  0.5000
+
+";
+    let kpt = "       3
+  0.00000000  0.00000000  0.00000000   1.0
+  0.25000000  0.00000000  0.00000000   1.0
+  0.50000000  0.00000000  0.00000000   1.0
+";
+
+    let bands = read::parse_band_dat(dat, kpt).unwrap();
+
+    assert_eq!(bands.energies, vec![vec![-1.5, -1.0, -0.5], vec![1.5, 1.0, 0.5]]);
+    assert_eq!(bands.distances, vec![0.0, 0.5, 1.0]);
+    assert_eq!(bands.kpoints[1], [0.25, 0.0, 0.0]);
+
+    let bad_dat = "0.0 -1.5\n0.5 -1.0\n\n0.0 1.5\n";
+    assert!(read::parse_band_dat(bad_dat, kpt).is_err());
+
+    let bad_kpt = "2\n0.0 0.0 0.0 1.0\n0.5 0.0 0.0 1.0\n";
+    assert!(read::parse_band_dat(dat, bad_kpt).is_err());
+}
+
+#[test]
+fn parse_fermi_lines() {
+    let text = "  0.10  0.00
+  0.00  0.10
+ -0.10  0.00
+
+
+  0.50  0.40
+  0.40  0.50
+";
+
+    let contours = kslice::parse_fermi_lines(text).unwrap();
+
+    assert_eq!(
+        contours,
+        vec![
+            vec![[0.1, 0.0], [0.0, 0.1], [-0.1, 0.0]],
+            vec![[0.5, 0.4], [0.4, 0.5]],
+        ]
+    );
+
+    assert!(kslice::parse_fermi_lines("0.1 0.2 0.3\n").is_err());
+}
+
+#[test]
+fn parse_amn() {
+    let text = "Created on 14Oct2026 at 12:00:00
+    2    2    1
+    1    1    1   0.500000000000   0.100000000000
+    2    1    1  -0.250000000000   0.000000000000
+    1    1    2   0.700000000000  -0.200000000000
+    2    1    2   0.000000000000   0.300000000000
+";
+
+    let amn = read::parse_amn(text).unwrap();
+
+    assert_eq!((amn.num_bands, amn.num_kpts, amn.num_wann), (2, 2, 1));
+    assert_eq!(amn.a[0][1][0], Complex64::new(-0.25, 0.0));
+    assert_eq!(amn.a[1][0][0], Complex64::new(0.7, -0.2));
+
+    let truncated: String = text.lines().take(4).collect::<Vec<&str>>().join("\n");
+    assert!(read::parse_amn(&truncated).is_err());
+}
+
+#[test]
+fn parse_mmn() {
+    let text = "Created on 14Oct2026 at 12:00:00
+    2    2    1
+    1    2    0    0    0
+   1.0   0.0
+   0.0   0.5
+   0.0  -0.5
+   1.0   0.0
+    2    1   -1    0    0
+   0.9   0.1
+   0.0   0.0
+   0.0   0.0
+   0.8  -0.1
+";
+
+    let mmn = read::parse_mmn(text).unwrap();
+
+    assert_eq!((mmn.num_bands, mmn.num_kpts, mmn.nntot), (2, 2, 1));
+    assert_eq!(mmn.neighbors, vec![vec![(1, [0, 0, 0])], vec![(0, [-1, 0, 0])]]);
+    assert_eq!(mmn.m[0][0][1][0], Complex64::new(0.0, 0.5));
+    assert_eq!(mmn.m[0][0][0][1], Complex64::new(0.0, -0.5));
+    assert_eq!(mmn.m[1][0][1][1], Complex64::new(0.8, -0.1));
+
+    let truncated: String = text.lines().take(10).collect::<Vec<&str>>().join("\n");
+    assert!(read::parse_mmn(&truncated).is_err());
+}
+
+#[test]
+fn eig_band_gap() {
+    // Valence band maximum at k = 1, conduction band minimum at k = 2.
+    let text = "    1    1   -2.0
+    2    1   -0.5
+    3    1    1.5
+    1    2   -1.5
+    2    2   -0.8
+    3    2    0.7
+    1    3   -1.8
+    2    3   -0.7
+    3    3    1.0
+";
+
+    let eig = eig::parse_eig(text).unwrap();
+    assert_eq!((eig.num_bands, eig.num_kpts), (3, 3));
+
+    match eig::band_gap(&eig, 2).unwrap() {
+        BandGap::Gapped {
+            direct,
+            indirect,
+            vbm_k,
+            cbm_k,
+        } => {
+            assert!((indirect - 1.2).abs() < 1e-12);
+            assert!((direct - 1.5).abs() < 1e-12);
+            assert_eq!((vbm_k, cbm_k), (0, 1));
+        }
+        BandGap::Metallic => panic!("expected gapped band structure"),
+    }
+
+    let metal = eig::parse_eig("1 1 -1.0\n2 1 0.5\n1 2 0.8\n2 2 2.0\n").unwrap();
+    assert_eq!(eig::band_gap(&metal, 1).unwrap(), BandGap::Metallic);
+
+    // Occupations from a larger calculation may leave no band above the occupied ones.
+    for &num_occupied in &[0, 3, 10] {
+        match eig::band_gap(&eig, num_occupied) {
+            Err(EigError::NumOccupied { num_bands: 3, .. }) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
+
+#[test]
+fn parse_tb() {
+    let text = " written on 14Oct2026 at 12:00:00
+  3.0000000   0.0000000   0.0000000
+  0.0000000   3.0000000   0.0000000
+  0.0000000   0.0000000  10.0000000
+          1
+          2
+    2    2
+
+    0    0    0
+    1    1    -0.20000000E+01   0.00000000E+00
+
+    1    0    0
+    1    1    0.50000000E+00   0.10000000E+00
+
+    0    0    0
+    1    1    0.00000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00   0.25000000E+01   0.00000000E+00
+
+    1    0    0
+    1    1    0.10000000E+00  -0.20000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00
+";
+
+    let tb = read::parse_tb(text).unwrap();
+
+    assert_eq!(tb.lattice[2], [0.0, 0.0, 10.0]);
+    assert_eq!(tb.num_wann, 1);
+    assert_eq!(tb.r_vectors, vec![[0, 0, 0], [1, 0, 0]]);
+    assert_eq!(tb.degeneracies, vec![2, 2]);
+    assert_eq!(tb.hoppings[0][0][0], Complex64::new(-2.0, 0.0));
+    assert_eq!(tb.hoppings[1][0][0], Complex64::new(0.5, 0.1));
+    assert_eq!(tb.positions[0][0][0][2], Complex64::new(2.5, 0.0));
+    assert_eq!(tb.positions[1][0][0][0], Complex64::new(0.1, -0.2));
+
+    // The position blocks must list the same R-vectors as the Hamiltonian blocks.
+    let swapped = text.replacen(
+        "\n    1    0    0\n    1    1    0.1",
+        "\n    0    1    0\n    1    1    0.1",
+        1,
+    );
+    match read::parse_tb(&swapped) {
+        Err(read::TbError::RVectorMismatch { expected, found }) => {
+            assert_eq!(expected, [1, 0, 0]);
+            assert_eq!(found, [0, 1, 0]);
+        }
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    let truncated = &text[..text.len() - 20];
+    assert!(read::parse_tb(truncated).is_err());
+}
+
+#[test]
+fn parse_rmn() {
+    let text = " written on 14Oct2026 at 12:00:00
+          2
+          1
+    0    0    0    1    1    0.10000000E+01   0.00000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00
+    0    0    0    2    1    0.00000000E+00   0.50000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00
+    0    0    0    1    2    0.00000000E+00  -0.50000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00
+    0    0    0    2    2    0.00000000E+00   0.00000000E+00   0.00000000E+00   0.00000000E+00   0.20000000E+01   0.00000000E+00
+";
+
+    let rmn = read::parse_rmn(text).unwrap();
+
+    assert_eq!(rmn.num_wann, 2);
+    assert_eq!(rmn.r_vectors, vec![[0, 0, 0]]);
+    assert_eq!(rmn.positions[0][0][0][0], Complex64::new(1.0, 0.0));
+    assert_eq!(rmn.positions[0][1][0][0], Complex64::new(0.0, 0.5));
+    assert_eq!(rmn.positions[0][0][1][0], Complex64::new(0.0, -0.5));
+    assert_eq!(rmn.positions[0][1][1][2], Complex64::new(2.0, 0.0));
+
+    let out_of_order = text.replacen("    2    1    0.0", "    2    2    0.0", 1);
+    match read::parse_rmn(&out_of_order) {
+        Err(read::RmnError::UnexpectedIndex { m, n, .. }) => assert_eq!((m, n), (2, 2)),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn parse_u_matrices() {
+    let umat = " written on 14Oct2026 at 12:00:00
+           2           2           2
+
+  0.0000000000 +0.0000000000 +0.0000000000
+  1.0000000000 +0.0000000000
+  0.0000000000 +0.0000000000
+  0.0000000000 +0.0000000000
+  0.0000000000 -1.0000000000
+
+  0.5000000000 +0.0000000000 +0.0000000000
+  0.0000000000 +0.0000000000
+  1.0000000000 +0.0000000000
+  1.0000000000 +0.0000000000
+  0.0000000000 +0.0000000000
+";
+
+    let u = read::parse_umat(umat).unwrap();
+    assert_eq!((u.num_kpts, u.num_wann, u.num_bands), (2, 2, 2));
+    assert_eq!(u.kpoints, vec![[0.0, 0.0, 0.0], [0.5, 0.0, 0.0]]);
+    assert_eq!(u.u[0][0][0], Complex64::new(1.0, 0.0));
+    assert_eq!(u.u[0][1][1], Complex64::new(0.0, -1.0));
+    assert_eq!(u.u[1][1][0], Complex64::new(1.0, 0.0));
+    assert_eq!(u.u[1][0][1], Complex64::new(1.0, 0.0));
+
+    // The second k-point is missing its last element.
+    let truncated = umat.trim_end().rsplitn(2, '\n').nth(1).unwrap();
+    match read::parse_umat(truncated) {
+        Err(read::UMatError::UnexpectedEof) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+    assert!(read::parse_umat(&format!("{}  0.0 0.0\n", umat)).is_err());
+
+    let u_dis = " written on 14Oct2026 at 12:00:00
+           1           1           3
+
+  0.0000000000 +0.0000000000 +0.0000000000
+  0.6000000000 +0.0000000000
+  0.0000000000 +0.8000000000
+  0.0000000000 +0.0000000000
+";
+
+    let u = read::parse_u_dis_mat(u_dis).unwrap();
+    assert_eq!((u.num_kpts, u.num_wann, u.num_bands), (1, 1, 3));
+    assert_eq!(u.u[0].len(), 3);
+    assert_eq!(u.u[0][1][0], Complex64::new(0.0, 0.8));
+
+    match read::parse_umat(u_dis) {
+        Err(read::UMatError::NotSquare { num_wann, num_bands }) => {
+            assert_eq!((num_wann, num_bands), (1, 3))
+        }
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+/// Append a Fortran unformatted record with 4-byte little-endian markers.
+fn push_record(out: &mut Vec<u8>, contents: &[u8]) {
+    let marker: Vec<u8> = (0..4).map(|i| (contents.len() >> (8 * i)) as u8).collect();
+    out.extend(&marker);
+    out.extend(contents);
+    out.extend(&marker);
+}
+
+fn int_bytes(values: &[i32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|&v| (0..4).map(move |i| ((v as u32) >> (8 * i)) as u8))
+        .collect()
+}
+
+fn real_bytes(values: &[f64]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|&v| (0..8).map(move |i| (v.to_bits() >> (8 * i)) as u8))
+        .collect()
+}
+
+/// Checkpoint with two bands, one Wannier function, two k-points and one neighbor.
+fn synthetic_chk() -> Vec<u8> {
+    let mut data = Vec::new();
+    push_record(&mut data, format!("{:33}", "written on 1Jan2018 at 12:00:00").as_bytes());
+    push_record(&mut data, &int_bytes(&[2]));
+    push_record(&mut data, &int_bytes(&[0]));
+    push_record(&mut data, &[]);
+    // Column-major: the second lattice vector is (0.5, 2, 0).
+    push_record(&mut data, &real_bytes(&[2.0, 0.5, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 10.0]));
+    push_record(&mut data, &real_bytes(&[3.0, 0.0, 0.0, -0.75, 3.0, 0.0, 0.0, 0.0, 0.6]));
+    push_record(&mut data, &int_bytes(&[2]));
+    push_record(&mut data, &int_bytes(&[2, 1, 1]));
+    push_record(&mut data, &real_bytes(&[0.0, 0.0, 0.0, 0.5, 0.0, 0.0]));
+    push_record(&mut data, &int_bytes(&[1]));
+    push_record(&mut data, &int_bytes(&[1]));
+    push_record(&mut data, format!("{:20}", "postwann").as_bytes());
+    push_record(&mut data, &int_bytes(&[1]));
+    push_record(&mut data, &real_bytes(&[0.25]));
+    push_record(&mut data, &int_bytes(&[1, 1, 0, 1]));
+    push_record(&mut data, &int_bytes(&[2, 1]));
+    push_record(&mut data, &real_bytes(&[0.6, 0.0, 0.8, 0.0, 0.0, 0.0, 1.0, 0.0]));
+    push_record(&mut data, &real_bytes(&[1.0, 0.0, 0.0, -1.0]));
+    push_record(&mut data, &real_bytes(&[0.9, 0.1, 0.8, -0.2]));
+    push_record(&mut data, &real_bytes(&[0.5, 0.25, 1.0]));
+    push_record(&mut data, &real_bytes(&[1.5]));
+    data
+}
+
+#[test]
+fn parse_chk() {
+    let data = synthetic_chk();
+    let chk = read::parse_chk(&data).unwrap();
+
+    assert_eq!(chk.header, "written on 1Jan2018 at 12:00:00");
+    assert_eq!(chk.num_bands, 2);
+    assert_eq!(chk.exclude_bands, Vec::<usize>::new());
+    assert_eq!(chk.real_lattice[1], [0.5, 2.0, 0.0]);
+    assert_eq!(chk.recip_lattice[0], [3.0, -0.75, 0.0]);
+    assert_eq!((chk.num_kpts, chk.mp_grid), (2, [2, 1, 1]));
+    assert_eq!(chk.kpoints, vec![[0.0, 0.0, 0.0], [0.5, 0.0, 0.0]]);
+    assert_eq!((chk.nntot, chk.num_wann), (1, 1));
+    assert_eq!(chk.checkpoint, "postwann");
+
+    let dis = chk.disentanglement.as_ref().unwrap();
+    assert_eq!(dis.omega_invariant, 0.25);
+    assert_eq!(dis.lwindow, vec![vec![true, true], vec![false, true]]);
+    assert_eq!(dis.ndimwin, vec![2, 1]);
+    assert_eq!(dis.u_dis[0], vec![vec![Complex64::new(0.6, 0.0)], vec![Complex64::new(0.8, 0.0)]]);
+    assert_eq!(dis.u_dis[1][1][0], Complex64::new(1.0, 0.0));
+
+    assert_eq!(chk.u[1][0][0], Complex64::new(0.0, -1.0));
+    assert_eq!(chk.m[1][0][0][0], Complex64::new(0.8, -0.2));
+    assert_eq!(chk.centres, vec![[0.5, 0.25, 1.0]]);
+    assert_eq!(chk.spreads, vec![1.5]);
+
+    // A truncated file or mismatched record markers are rejected.
+    assert!(read::parse_chk(&data[..data.len() - 4]).is_err());
+    let mut corrupt = data.clone();
+    corrupt[0] = 32;
+    assert!(read::parse_chk(&corrupt).is_err());
+}
+
+#[test]
+fn write_chk_round_trip() {
+    let data = synthetic_chk();
+    let chk = read::parse_chk(&data).unwrap();
+    assert_eq!(write::make_chk(&chk).unwrap(), data);
+
+    let path = env::temp_dir().join("w90_read_round_trip.chk");
+    write::write_chk(&chk, &path).unwrap();
+    assert_eq!(read::read_chk(&path).unwrap(), chk);
+
+    let mut without_dis = chk.clone();
+    without_dis.disentanglement = None;
+    let bytes = write::make_chk(&without_dis).unwrap();
+    assert_eq!(read::parse_chk(&bytes).unwrap(), without_dis);
+
+    let mut inconsistent = chk.clone();
+    inconsistent.spreads.push(2.0);
+    assert!(write::make_chk(&inconsistent).is_err());
+}
@@ -0,0 +1,43 @@
+extern crate w90;
+
+use std::env;
+use w90::run;
+use w90::run::{RunError, Wannier90};
+
+#[test]
+fn check_wout() {
+    let converged = " Final State\n  WF centre and spread    1  (  0.000000,  0.000000,  0.000000 )     1.00000000\n";
+    assert!(run::check_wout(converged).is_ok());
+
+    let dis = " <<< Warning: Maximum number of disentanglement iterations reached >>>\n";
+    match run::check_wout(dis) {
+        Err(RunError::DisentanglementNotConverged) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    let aborted = " Exiting....... \n param_read: mismatch in system\n";
+    match run::check_wout(aborted) {
+        Err(RunError::Aborted) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn run_executable() {
+    let workdir = env::temp_dir();
+
+    let missing = Wannier90::new("w90-rs-test-nonexistent-executable");
+    match missing.run_postproc_setup("wse2", &workdir) {
+        Err(RunError::Io(_)) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    if cfg!(unix) {
+        assert!(Wannier90::new("true").run_postproc_setup("wse2", &workdir).is_ok());
+
+        match Wannier90::new("false").run_postproc_setup("wse2", &workdir) {
+            Err(RunError::Failed { status, .. }) => assert!(!status.success()),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
@@ -1,19 +1,40 @@
+extern crate qe;
 extern crate w90;
 
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use qe::pw::input::generate_uniform_kpoints;
+
 use w90::input;
-use w90::input::{AngularMomentum, AtomCoordinate, Cell, Disentanglement, LatticeUnits,
-                 MLWFIterationMode, PositionCoordinateType, Positions, Projection, ProjectionSite};
+use w90::input::{AngularMomentum, AtomCoordinate, BandsPlotFormat, BandsPlotMode,
+                 BandsPlotOptions, Berry, BerryTask, BoltzWann, Cell, DisSphere, DisSpheres,
+                 Disentanglement, Dos, KPointMesh, LatticeUnits, MLWFIterationMode,
+                 OrbitalRadial, OutputFlags, PositionCoordinateType, Positions, Projection,
+                 ProjectionSite, Restart, SelectiveLocalization, SpinChannel};
+use w90::kmesh;
+use w90::kpath::{KPath, SymmetryPoint};
 use w90::serialize;
+use w90::serialize::{BoolStyle, Field, GeninterpCoordinates, SerializeOptions};
 
-#[test]
-fn generate_input() {
+/// Input file for `input` with each number written exactly, for tests of the values
+/// written rather than of their formatting.
+fn make_exact(input: &input::Input) -> String {
+    serialize::make_input_file_with_options(input, &SerializeOptions::exact()).unwrap()
+}
+
+fn wse2_input() -> input::Input {
     let disentanglement = Some(Disentanglement {
-        dis_win_min: -6.5582,
-        dis_win_max: 8.4418,
-        dis_froz_min: -4.5582,
-        dis_froz_max: 6.4418,
+        dis_win_min: Some(-6.5582),
+        dis_win_max: Some(8.4418),
+        dis_froz_min: Some(-4.5582),
+        dis_froz_max: Some(6.4418),
         dis_num_iter: 1000,
         dis_mix_ratio: 0.5,
+        dis_spheres: None,
+        dis_froz_proj: None,
+        dis_proj_min: None,
+        dis_proj_max: None,
     });
 
     let projections = vec![
@@ -24,6 +45,9 @@ fn generate_input() {
             xaxis: None,
             radial: None,
             zona: None,
+            spin: None,
+            quant_dir: None,
+            per_orbital: Vec::new(),
         },
         Projection::Site {
             site: ProjectionSite::Species(String::from("W")),
@@ -32,6 +56,9 @@ fn generate_input() {
             xaxis: None,
             radial: None,
             zona: None,
+            spin: None,
+            quant_dir: None,
+            per_orbital: Vec::new(),
         },
     ];
 
@@ -49,34 +76,2342 @@ fn generate_input() {
         coordinates: vec![
             AtomCoordinate {
                 species: String::from("Se"),
+                label: None,
                 r: [0.0, 0.0, 0.275217856494],
             },
             AtomCoordinate {
                 species: String::from("W"),
+                label: None,
                 r: [0.333333333333, 0.666666666667, 0.321438654707],
             },
             AtomCoordinate {
                 species: String::from("Se"),
+                label: None,
                 r: [0.0, 0.0, 0.36765945292],
             },
         ],
     };
 
-    let test_input = input::Input {
+    input::Input {
         num_bands: 44,
         num_wann: 22,
         write_hr: Some(true),
+        write_tb: None,
+        write_u_matrices: None,
+        use_ws_distance: None,
         mlwf_iteration_mode: MLWFIterationMode::ProjectionOnly,
+        num_print_cycles: None,
+        num_dump_cycles: None,
+        optimisation: None,
+        output_flags: None,
+        wvfn_formatted: None,
+        spn_formatted: None,
+        uhu_formatted: None,
+        restart: None,
+        guiding_centres: None,
+        num_guide_cycles: None,
+        num_no_guide_iter: None,
+        selective_localization: None,
+        site_symmetry: None,
+        symmetrize_eps: None,
+        exclude_bands: None,
         disentanglement,
+        dos: None,
+        berry: None,
+        boltzwann: None,
+        geninterp: None,
+        geninterp_alsofirstder: None,
+        geninterp_single_file: None,
+        bands_plot: None,
+        kpoint_path: None,
+        bands_plot_options: None,
+        use_bloch_phases: None,
         spinors: true,
+        spin: None,
+        spin_axis_polar: None,
+        spin_axis_azimuth: None,
         projection_units: None,
         projections,
         unit_cell_cart,
         positions,
-        k_points: [9, 9, 1],
-    };
+        kpoints: KPointMesh::Uniform([9, 9, 1]),
+        gamma_only: None,
+        kmesh_tol: None,
+        search_shells: None,
+        skip_b1_tests: None,
+        comments: HashMap::new(),
+    }
+}
+
+#[test]
+fn generate_input() {
+    let test_input = wse2_input();
 
     let input_text = serialize::make_input_file(&test_input).unwrap();
 
     println!("{}", input_text);
 }
+
+#[test]
+fn fortran_bools() {
+    let mut test_input = wse2_input();
+    test_input.write_hr = Some(false);
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+
+    let mut bool_count = 0;
+    for line in input_text.lines() {
+        if let Some(eq) = line.find('=') {
+            let val = line[eq + 1..].trim();
+            let lower = val.to_lowercase();
+            if lower.contains("true") || lower.contains("false") || lower == "t" || lower == "f" {
+                assert!(val == ".true." || val == ".false.", "bad logical: {}", line);
+                bool_count += 1;
+            }
+        }
+    }
+    assert_eq!(bool_count, 2);
+
+    assert!(input_text.contains("write_hr=.false."));
+    assert!(input_text.contains("spinors=.true."));
+}
+
+#[test]
+fn diff_inputs() {
+    let original = wse2_input();
+    assert!(input::diff(&original, &original).is_empty());
+
+    let mut changed = wse2_input();
+    changed.num_wann = 20;
+    changed.kpoints = KPointMesh::Uniform([4, 4, 1]);
+    if let Some(ref mut dis) = changed.disentanglement {
+        dis.dis_win_max = None;
+    }
+    if let Projection::Site {
+        ref mut site,
+        ref mut zaxis,
+        ..
+    } = changed.projections[1]
+    {
+        *site = ProjectionSite::Species(String::from("Mo"));
+        *zaxis = Some([0.0, 0.0, 1.0]);
+    }
+    changed.projections.push(Projection::Random);
+    changed.positions.coordinates[2].r[2] = 0.4;
+
+    let diffs: Vec<String> = input::diff(&original, &changed)
+        .iter()
+        .map(|d| d.to_string())
+        .collect();
+    assert_eq!(
+        diffs,
+        vec![
+            "disentanglement.dis_win_max: 8.4418 -> none",
+            "kpoints: [9,9,1] -> [4,4,1]",
+            "num_wann: 22 -> 20",
+            "positions.coordinates[2].r: [0.0,0.0,0.36765945292] -> [0.0,0.0,0.4]",
+            "projections[1].site: W -> Mo",
+            "projections[1].zaxis: none -> [0.0,0.0,1.0]",
+            "projections[2]: none -> Random",
+        ]
+    );
+
+    // A change of variant replaces the whole value.
+    let mut explicit = wse2_input();
+    explicit.kpoints = KPointMesh::Explicit(vec![[0.0, 0.0, 0.0]]);
+    let diffs = input::diff(&original, &explicit);
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].keyword, "kpoints");
+    assert_eq!(diffs[0].old, r#"{"Uniform":[9,9,1]}"#);
+}
+
+#[test]
+fn cell_to_units() {
+    let bohr_cell = Cell {
+        units: LatticeUnits::Bohr,
+        cell: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    let ang_cell = bohr_cell.to_units(LatticeUnits::Angstrom);
+    assert_eq!(ang_cell.units, LatticeUnits::Angstrom);
+    assert!((ang_cell.cell[0][0] - 0.52917721067).abs() < 1e-12);
+    assert!((ang_cell.cell[2][2] - 0.52917721067).abs() < 1e-12);
+    assert_eq!(ang_cell.cell[0][1], 0.0);
+
+    assert_eq!(bohr_cell.to_units(LatticeUnits::Bohr), bohr_cell);
+
+    let back = ang_cell.to_units(LatticeUnits::Bohr);
+    assert!((back.cell[1][1] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn std_errors() {
+    use std::error::Error as StdError;
+
+    fn make(input: &input::Input) -> Result<String, Box<dyn StdError>> {
+        Ok(serialize::make_input_file(input)?)
+    }
+
+    let mut test_input = wse2_input();
+    assert!(make(&test_input).is_ok());
+
+    test_input.projections.push(Projection::Random);
+    test_input.projections.push(Projection::Random);
+    let err = make(&test_input).unwrap_err();
+    assert!(format!("{}", err).contains("`Random` may appear at most once"));
+
+    let std_err: Box<dyn StdError> = Box::new(input::Error::RandomCount);
+    assert_eq!(
+        format!("{}", std_err),
+        "`Random` may appear at most once in the list of projections."
+    );
+
+    let dir = env::temp_dir().join("w90_input_std_errors_missing");
+    let io_err = serialize::write_input_file(&wse2_input(), dir.join("wse2.win")).unwrap_err();
+    assert!(io_err.source().is_some());
+
+    let warning: Box<dyn StdError> = Box::new(input::Warning::RedundantWriteHr);
+    assert_eq!(format!("{}", warning), "`write_hr` is redundant when `write_tb` is given.");
+
+    let config_err = input::from_toml_file(dir.join("wse2.toml")).unwrap_err();
+    assert!(config_err.source().is_some());
+
+    let toml_path = env::temp_dir().join("w90_input_std_errors.toml");
+    fs::write(&toml_path, "num_wann = \"22\"\n").unwrap();
+    let config_err = input::from_toml_file(&toml_path).unwrap_err();
+    let source = format!("{}", config_err.source().unwrap());
+    assert_eq!(format!("{}", config_err), source);
+}
+
+#[test]
+fn species_counts() {
+    let test_input = wse2_input();
+    let counts: Vec<(String, usize)> = test_input.positions.species_counts().into_iter().collect();
+    assert_eq!(counts, vec![(String::from("Se"), 2), (String::from("W"), 1)]);
+}
+
+#[test]
+fn projections_from_species_map() {
+    let test_input = wse2_input();
+
+    let mut map = HashMap::new();
+    map.insert(String::from("W"), vec![AngularMomentum::D]);
+    map.insert(String::from("Se"), vec![AngularMomentum::P]);
+    let projections = Projection::from_species_map(&test_input.positions, &map);
+    assert_eq!(projections, test_input.projections);
+
+    map.insert(String::from("Mo"), vec![AngularMomentum::D]);
+    assert_eq!(
+        Projection::from_species_map(&test_input.positions, &map),
+        projections
+    );
+    assert!(Projection::from_species_map_strict(&test_input.positions, &map).is_err());
+
+    // With several missing species, the same one is reported on every run.
+    for species in &["Te", "S", "Cr", "Nb"] {
+        map.insert(species.to_string(), vec![AngularMomentum::D]);
+    }
+    match Projection::from_species_map_strict(&test_input.positions, &map) {
+        Err(input::Error::UnknownSpecies(ref species)) => assert_eq!(species, "Cr"),
+        r => panic!("unexpected result {:?}", r),
+    }
+    for species in &["Te", "S", "Cr", "Nb"] {
+        map.remove(*species);
+    }
+
+    map.remove("Mo");
+    map.insert(String::from("W"), vec![AngularMomentum::S, AngularMomentum::D]);
+    let mut with_s = test_input.clone();
+    with_s.projections = Projection::from_species_map_strict(&test_input.positions, &map).unwrap();
+    assert_eq!(with_s.projections.len(), 3);
+    assert_eq!(input::count_wannier(&with_s), input::count_wannier(&test_input) + 2);
+}
+
+#[test]
+fn lint_unused_disentanglement() {
+    let mut test_input = wse2_input();
+    assert_eq!(input::lint(&test_input), vec![]);
+
+    test_input.num_bands = test_input.num_wann;
+    assert_eq!(
+        input::lint(&test_input),
+        vec![input::Warning::UnusedDisentanglement]
+    );
+
+    test_input.disentanglement = None;
+    assert_eq!(input::lint(&test_input), vec![]);
+}
+
+#[test]
+fn validate_warnings() {
+    let mut test_input = wse2_input();
+    assert_eq!(input::validate(&test_input).unwrap(), vec![]);
+
+    if let Some(ref mut dis) = test_input.disentanglement {
+        dis.dis_num_iter = 50000;
+    }
+    test_input.num_bands = 100;
+    assert_eq!(
+        input::validate(&test_input).unwrap(),
+        vec![
+            input::Warning::LargeDisNumIter {
+                dis_num_iter: 50000,
+                max: input::LARGE_DIS_NUM_ITER,
+            },
+            input::Warning::ManyExtraBands {
+                num_bands: 100,
+                num_wann: 22,
+                ratio: input::LARGE_NUM_BANDS_RATIO,
+            },
+        ]
+    );
+    let messages: Vec<String> = input::validate(&test_input)
+        .unwrap()
+        .iter()
+        .map(|w| format!("{}", w))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "`dis_num_iter` = 50000 is larger than 10000.",
+            "`num_bands` = 100 is more than 4 times `num_wann` = 22.",
+        ]
+    );
+    // Warnings do not prevent generating the input file.
+    assert!(serialize::make_input_file(&test_input).is_ok());
+}
+
+#[test]
+fn positions_crystal_cartesian() {
+    let test_input = wse2_input();
+    let cell = &test_input.unit_cell_cart;
+    let crystal = &test_input.positions;
+
+    let cart = crystal.to_cartesian(cell, LatticeUnits::Angstrom);
+    assert_eq!(cart.coordinate_type, PositionCoordinateType::AngstromCartesian);
+    let z_ang = 0.275217856494 * 68.6629186029 * 0.52917721067;
+    assert!((cart.coordinates[0].r[2] - z_ang).abs() < 1e-9);
+
+    let bohr = cart.to_cartesian(cell, LatticeUnits::Bohr);
+    assert_eq!(bohr.coordinate_type, PositionCoordinateType::BohrCartesian);
+    assert!((bohr.coordinates[0].r[2] - 0.275217856494 * 68.6629186029).abs() < 1e-9);
+
+    for back in vec![cart.to_crystal(cell).unwrap(), bohr.to_crystal(cell).unwrap()] {
+        assert_eq!(back.coordinate_type, PositionCoordinateType::Crystal);
+        for (c, c0) in back.coordinates.iter().zip(crystal.coordinates.iter()) {
+            assert_eq!(c.species, c0.species);
+            for i in 0..3 {
+                assert!((c.r[i] - c0.r[i]).abs() < 1e-9);
+            }
+        }
+    }
+
+    assert_eq!(crystal.to_crystal(cell).unwrap(), *crystal);
+
+    let singular = Cell {
+        units: LatticeUnits::Bohr,
+        cell: [[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+    assert!(cart.to_crystal(&singular).is_err());
+}
+
+#[test]
+fn exclude_bands() {
+    let mut test_input = wse2_input();
+    test_input.exclude_bands = Some(vec![1, 2, 3, 4, 7]);
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.lines().any(|l| l == "exclude_bands = 1-4,7"));
+
+    test_input.exclude_bands = Some(vec![3, 5, 6, 44]);
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.lines().any(|l| l == "exclude_bands = 3,5-6,44"));
+
+    test_input.exclude_bands = Some(vec![0, 1]);
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.exclude_bands = Some(vec![1, 45]);
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.exclude_bands = Some(vec![2, 1]);
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.exclude_bands = Some(vec![1, 1]);
+    assert!(input::validate(&test_input).is_err());
+}
+
+#[test]
+fn parse_projection_separators() {
+    let semicolon: Projection = "Se:l=1;l=2".parse().unwrap();
+    let comma: Projection = "Se:l=1,l=2".parse().unwrap();
+
+    assert_eq!(semicolon, comma);
+    assert_eq!(comma.value(), "Se:l=1;l=2");
+
+    let with_axes: Projection = "f = 0.0, 0.5, 0.0 : l=0,l=1 : z=0,0,1".parse().unwrap();
+    assert_eq!(with_axes.value(), "f = 0, 0.5, 0:l=0;l=1:z=0,0,1");
+}
+
+#[test]
+fn spin_channel() {
+    let mut test_input = wse2_input();
+    test_input.spin = Some(SpinChannel::Down);
+
+    // `spin` is only valid for collinear calculations.
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.spinors = false;
+    test_input.num_wann = 11;
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.lines().any(|l| l == "spin = down"));
+}
+
+#[test]
+fn gamma_only() {
+    let mut test_input = wse2_input();
+    test_input.gamma_only = Some(true);
+
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.kpoints = KPointMesh::Uniform([1, 1, 1]);
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+
+    assert!(input_text.lines().any(|l| l == "gamma_only=.true."));
+    assert!(input_text.contains(
+        "mp_grid = 1 1 1\nbegin kpoints\n0.000000000 0.000000000 0.000000000\nend kpoints"
+    ));
+
+    // The Gamma point is formatted like any other k-point.
+    assert!(make_exact(&test_input).ends_with("begin kpoints\n0 0 0\nend kpoints"));
+}
+
+#[test]
+fn guiding_centres_projections() {
+    let mut test_input = wse2_input();
+    test_input.guiding_centres = Some(true);
+    assert!(input::validate(&test_input).is_ok());
+
+    test_input.projections = vec![Projection::Random];
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.projections = vec![];
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.guiding_centres = Some(false);
+    test_input.projections = vec![Projection::Random];
+    assert!(input::validate(&test_input).is_ok());
+}
+
+#[test]
+fn cell_cartesian_matrix() {
+    let cell = wse2_input().unit_cell_cart;
+    let lat = cell.to_cartesian_matrix(LatticeUnits::Angstrom);
+
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((lat[i][j] - cell.cell[i][j] * 0.52917721067).abs() < 1e-12);
+        }
+    }
+
+    assert_eq!(cell.to_cartesian_matrix(LatticeUnits::Bohr), cell.cell);
+}
+
+#[test]
+fn kpoint_meshes() {
+    let mut test_input = wse2_input();
+
+    test_input.kpoints = KPointMesh::UniformShifted([2, 2, 1], [0.5, 0.5, 0.0]);
+    let input_text = make_exact(&test_input);
+    assert!(input_text.contains(
+        "mp_grid = 2 2 1\nbegin kpoints\n0.25 0.25 0\n0.25 0.75 0\n0.75 0.25 0\n0.75 0.75 0\nend kpoints"
+    ));
+
+    let explicit = vec![
+        [0.0, 0.0, 0.0],
+        [0.0, 0.5, 0.0],
+        [0.5, 0.0, 0.0],
+        [0.5, 0.5, 0.0],
+    ];
+    test_input.kpoints = KPointMesh::Explicit(explicit);
+    assert_eq!(test_input.kpoints.mp_grid(), Some([2, 2, 1]));
+    let input_text = make_exact(&test_input);
+    assert!(input_text.contains(
+        "mp_grid = 2 2 1\nbegin kpoints\n0 0 0\n0 0.5 0\n0.5 0 0\n0.5 0.5 0\nend kpoints"
+    ));
+
+    test_input.kpoints = KPointMesh::Explicit(vec![[0.0, 0.0, 0.0], [0.1, 0.2, 0.0]]);
+    assert_eq!(test_input.kpoints.mp_grid(), None);
+    assert!(input::validate(&test_input).is_err());
+
+    // NaN components, as accepted by TOML, are rejected rather than sorted.
+    test_input.kpoints = KPointMesh::Explicit(vec![[0.0, 0.0, 0.0], [0.5, f64::NAN, 0.0]]);
+    assert_eq!(test_input.kpoints.mp_grid(), None);
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert!(match errs[0] {
+        input::Error::NonFiniteKPoint { index: 1 } => true,
+        _ => false,
+    });
+    assert_eq!(errs[0].exit_code(), 4);
+}
+
+#[test]
+fn exit_codes() {
+    let mut test_input = wse2_input();
+    test_input.projections = vec![Projection::Random, Projection::Random];
+
+    let err = serialize::make_input_file(&test_input).unwrap_err();
+    assert_eq!(err.exit_code(), 2);
+
+    let mut test_input = wse2_input();
+    test_input.gamma_only = Some(true);
+
+    let err = serialize::make_input_file(&test_input).unwrap_err();
+    assert_eq!(err.exit_code(), 4);
+
+    let mut test_input = wse2_input();
+    test_input.unit_cell_cart.cell[2] = test_input.unit_cell_cart.cell[1];
+
+    let err = serialize::make_input_file(&test_input).unwrap_err();
+    assert_eq!(err.exit_code(), 3);
+
+}
+
+#[test]
+fn error_keywords() {
+    assert_eq!(input::Error::RandomCount.keyword(), Some("projections"));
+    assert_eq!(input::Error::SingularCell.keyword(), Some("unit_cell_cart"));
+    assert_eq!(input::Error::GammaOnlyGrid.keyword(), Some("gamma_only"));
+    assert_eq!(input::Error::DosEnergyRange.keyword(), Some("dos_energy_min"));
+    assert_eq!(input::Error::UnknownSpecies(String::from("Mo")).keyword(), None);
+
+    let err = input::Error::NumWannProjections {
+        num_wann: 20,
+        count: 22,
+    };
+    assert_eq!(err.keyword(), Some("num_wann"));
+}
+
+#[test]
+fn output_flags() {
+    let mut test_input = wse2_input();
+    test_input.output_flags = Some(OutputFlags {
+        write_xyz: Some(true),
+        write_hr_diag: Some(false),
+        ..Default::default()
+    });
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+
+    assert!(input_text.lines().any(|l| l == "write_xyz=.true."));
+    assert!(input_text.lines().any(|l| l == "write_hr_diag=.false."));
+    assert!(!input_text.contains("translate_home_cell"));
+    assert!(!input_text.contains("write_vdw_data"));
+}
+
+#[test]
+fn golden_wse2() {
+    let golden = include_str!("golden/wse2.win");
+
+    serialize::golden_compare(&wse2_input(), golden).unwrap();
+
+    let mut changed = wse2_input();
+    changed.num_wann = 20;
+    changed.projections.push(Projection::Random);
+    let err = serialize::golden_compare(&changed, golden).unwrap_err();
+    assert_eq!(err, "line 2: expected `num_wann = 22`, got `num_wann = 20`");
+}
+
+#[test]
+fn write_input_matches_make_input_file() {
+    let mut shifted = wse2_input().with_kpoints([12, 12, 3]);
+    shifted.kpoints = KPointMesh::UniformShifted([12, 12, 3], [0.5, 0.5, 0.0]);
+    let mut gamma = wse2_input().with_kpoints([1, 1, 1]);
+    gamma.gamma_only = Some(true);
+
+    let cases = vec![wse2_input(), wse2_input().with_kpoints([12, 12, 3]), shifted, gamma];
+    let path = env::temp_dir().join("w90_write_input_matches.win");
+
+    for test_input in cases {
+        let expected = serialize::make_input_file(&test_input).unwrap();
+
+        let mut written = Vec::new();
+        serialize::write_input(&test_input, &mut written).unwrap();
+        assert_eq!(written, expected.as_bytes());
+
+        serialize::write_input_file(&test_input, &path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected.as_bytes());
+    }
+}
+
+#[test]
+fn config_round_trip() {
+    let mut test_input = wse2_input();
+    test_input.exclude_bands = Some(vec![1, 2]);
+    test_input.kpoints = KPointMesh::UniformShifted([9, 9, 1], [0.5, 0.5, 0.0]);
+
+    let json_path = env::temp_dir().join("w90_config_round_trip.json");
+    input::to_json_file(&test_input, &json_path).unwrap();
+    assert_eq!(input::from_json_file(&json_path).unwrap(), test_input);
+
+    let toml_path = env::temp_dir().join("w90_config_round_trip.toml");
+    input::to_toml_file(&test_input, &toml_path).unwrap();
+    assert_eq!(input::from_toml_file(&toml_path).unwrap(), test_input);
+}
+
+#[test]
+fn translate_home_cell_write_xyz() {
+    let mut test_input = wse2_input();
+    test_input.output_flags = Some(OutputFlags {
+        translate_home_cell: Some(true),
+        write_xyz: Some(false),
+        ..Default::default()
+    });
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+
+    assert!(input_text.lines().any(|l| l == "translate_home_cell=.true."));
+    assert!(input_text.lines().any(|l| l == "write_xyz=.false."));
+}
+
+#[test]
+fn trs_pairs() {
+    let mut test_input = wse2_input();
+    test_input.kpoints = KPointMesh::Uniform([2, 2, 1]);
+
+    // All points of a 2x2x1 Gamma-centered grid are time-reversal invariant.
+    let pairs = input::trs_pairs(&test_input);
+    assert_eq!(pairs, vec![(0, None), (1, None), (2, None), (3, None)]);
+
+    test_input.kpoints = KPointMesh::Uniform([3, 1, 1]);
+    let pairs = input::trs_pairs(&test_input);
+    assert_eq!(pairs, vec![(0, None), (1, Some(2)), (2, Some(1))]);
+}
+
+#[test]
+fn guide_cycles() {
+    let mut test_input = wse2_input();
+    test_input.num_guide_cycles = Some(5);
+    test_input.num_no_guide_iter = Some(100);
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.guiding_centres = Some(false);
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.guiding_centres = Some(true);
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains(
+        "guiding_centres=.true.\nnum_guide_cycles = 5\nnum_no_guide_iter = 100"
+    ));
+}
+
+#[test]
+fn formatted_io_flags() {
+    let mut test_input = wse2_input();
+    test_input.spn_formatted = Some(true);
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+
+    assert!(input_text.lines().any(|l| l == "spn_formatted=.true."));
+    assert!(!input_text.contains("wvfn_formatted"));
+    assert!(!input_text.contains("uHu_formatted"));
+}
+
+#[test]
+fn use_bloch_phases() {
+    let mut test_input = wse2_input();
+    test_input.projections = vec![];
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.use_bloch_phases = Some(true);
+    assert!(input::validate(&test_input).is_ok());
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.lines().any(|l| l == "use_bloch_phases=.true."));
+}
+
+#[test]
+fn empty_projections_with_mlwf() {
+    let mut test_input = wse2_input();
+    test_input.projections = vec![];
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert!(match errs[0] {
+        input::Error::NoProjectionsProjectionOnly => true,
+        _ => false,
+    });
+
+    test_input.mlwf_iteration_mode = MLWFIterationMode::MLWF { num_iter: 100 };
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert!(match errs[0] {
+        input::Error::NoProjections => true,
+        _ => false,
+    });
+    assert_eq!(errs[0].exit_code(), 2);
+}
+
+#[test]
+fn empty_projections_with_bloch_phases() {
+    let mut test_input = wse2_input();
+    test_input.projections = vec![];
+    test_input.mlwf_iteration_mode = MLWFIterationMode::MLWF { num_iter: 100 };
+    test_input.use_bloch_phases = Some(true);
+    assert!(input::validate(&test_input).is_ok());
+}
+
+#[test]
+fn exclude_bands_for_spinor() {
+    assert_eq!(
+        input::exclude_bands_for_spinor(&[1, 2, 5]),
+        vec![1, 2, 3, 4, 9, 10]
+    );
+    assert_eq!(input::exclude_bands_for_spinor(&[]), Vec::<u64>::new());
+
+    // The serializer writes `exclude_bands` as given.
+    let mut test_input = wse2_input();
+    test_input.exclude_bands = Some(vec![1, 2]);
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains("exclude_bands = 1-2\n"));
+}
+
+#[test]
+fn spin_axis() {
+    let mut test_input = wse2_input();
+    test_input.spin_axis_polar = Some(90.0);
+    test_input.spin_axis_azimuth = Some(45.0);
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains("spin_axis_polar = 90\nspin_axis_azimuth = 45\n"));
+
+    test_input.spin_axis_polar = Some(181.0);
+    test_input.spin_axis_azimuth = Some(-1.0);
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 2);
+
+    test_input.spin_axis_polar = Some(0.0);
+    test_input.spin_axis_azimuth = None;
+    test_input.spinors = false;
+    test_input.num_wann = 11;
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert!(errs.iter().any(|e| match *e {
+        input::Error::SpinAxisWithoutSpinors => true,
+        _ => false,
+    }));
+    assert!(errs.iter().all(|e| e.exit_code() == 5));
+}
+
+#[test]
+fn projections_exceed_num_bands() {
+    let mut test_input = wse2_input();
+    assert_eq!(input::count_wannier(&test_input), 22);
+
+    test_input.spinors = false;
+    test_input.projections = (0..10)
+        .map(|i| Projection::Site {
+            site: ProjectionSite::CenterCrystal([0.1 * (i as f64), 0.0, 0.0]),
+            ang_mtm: vec![AngularMomentum::D],
+            zaxis: None,
+            xaxis: None,
+            radial: None,
+            zona: None,
+            spin: None,
+            quant_dir: None,
+            per_orbital: Vec::new(),
+        })
+        .collect();
+    assert_eq!(input::count_wannier(&test_input), 50);
+
+    let errs = input::validate(&test_input).unwrap_err();
+    assert!(errs.errs.iter().any(|e| match *e {
+        input::Error::ProjectionsExceedNumBands => true,
+        _ => false,
+    }));
+}
+
+#[test]
+fn dos() {
+    let mut test_input = wse2_input();
+    test_input.dos = Some(Dos {
+        dos_kmesh: [50, 50, 1],
+        dos_energy_min: -5.0,
+        dos_energy_max: 5.0,
+        dos_energy_step: 0.01,
+        dos_adpt_smr: Some(true),
+    });
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains(
+        "dos=.true.\ndos_kmesh = 50 50 1\ndos_energy_min = -5\ndos_energy_max = 5\n\
+         dos_energy_step = 0.01\ndos_adpt_smr=.true."
+    ));
+
+    let mut bad = test_input.clone();
+    if let Some(ref mut dos) = bad.dos {
+        dos.dos_energy_max = -6.0;
+        dos.dos_energy_step = 0.0;
+        dos.dos_kmesh = [50, 0, 1];
+    }
+    assert_eq!(input::validate(&bad).unwrap_err().errs.len(), 3);
+}
+
+#[test]
+fn bands_plot() {
+    let mut test_input = wse2_input();
+    test_input.kpoint_path = Some(KPath::through(&[
+        SymmetryPoint::new("G", [0.0, 0.0, 0.0]),
+        SymmetryPoint::new("M", [0.5, 0.0, 0.0]),
+        SymmetryPoint::new("K", [1.0 / 3.0, 1.0 / 3.0, 0.0]),
+    ]));
+    test_input.bands_plot_options = Some(BandsPlotOptions {
+        format: vec![BandsPlotFormat::Gnuplot, BandsPlotFormat::Xmgrace],
+        mode: Some(BandsPlotMode::Cut),
+        project: None,
+    });
+
+    // Options without band plotting enabled.
+    let err = serialize::make_input_file(&test_input).unwrap_err();
+    assert_eq!(err.exit_code(), 8);
+
+    test_input.bands_plot = Some(true);
+    let input_text = make_exact(&test_input);
+    assert!(input_text.contains(
+        "bands_plot=.true.\nbands_plot_format = gnuplot xmgrace\nbands_plot_mode = cut\n\
+         begin kpoint_path\nG 0 0 0 M 0.5 0 0\n"
+    ));
+    assert!(input_text.contains("M 0.5 0 0 K 0.3333333333333333 0.3333333333333333 0\n"));
+
+    test_input.bands_plot = Some(false);
+    test_input.bands_plot_options = None;
+    let input_text = make_exact(&test_input);
+    assert!(!input_text.contains("bands_plot"));
+    assert!(!input_text.contains("kpoint_path"));
+
+    test_input.bands_plot = Some(true);
+    test_input.kpoint_path = None;
+    assert!(input::validate(&test_input).is_err());
+}
+
+#[test]
+fn bands_plot_project() {
+    let mut test_input = wse2_input();
+    test_input.bands_plot = Some(true);
+    test_input.kpoint_path = Some(KPath::through(&[
+        SymmetryPoint::new("G", [0.0, 0.0, 0.0]),
+        SymmetryPoint::new("M", [0.5, 0.0, 0.0]),
+    ]));
+    test_input.bands_plot_options = Some(BandsPlotOptions {
+        format: Vec::new(),
+        mode: None,
+        project: Some(vec![1, 2, 3, 5, 22]),
+    });
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains("bands_plot=.true.\nbands_plot_project = 1-3,5,22\n"));
+
+    let mut bad = test_input.clone();
+    if let Some(ref mut options) = bad.bands_plot_options {
+        options.project = Some(vec![0, 23]);
+    }
+    let errs = input::validate(&bad).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].exit_code(), 8);
+
+    if let Some(ref mut options) = bad.bands_plot_options {
+        options.project = Some(vec![2, 1]);
+    }
+    assert_eq!(input::validate(&bad).unwrap_err().errs.len(), 1);
+}
+
+#[test]
+fn labelled_atoms() {
+    let mut test_input = wse2_input();
+    test_input.positions.coordinates[0].label = Some(String::from("Se1"));
+    test_input.positions.coordinates[2].label = Some(String::from("Se2"));
+    test_input.projections[0] = site(
+        ProjectionSite::Species(String::from("Se1")),
+        vec![AngularMomentum::P],
+    );
+    test_input.projections.push(site(
+        ProjectionSite::Species(String::from("Se2")),
+        vec![AngularMomentum::S],
+    ));
+    test_input.num_wann = 2 * (3 + 5 + 1);
+
+    assert_eq!(input::count_wannier(&test_input), test_input.num_wann);
+    let input_text = make_exact(&test_input);
+    assert!(input_text.contains("begin atoms_frac\n Se1 0 0 0.275217856494\n"));
+    assert!(input_text.contains(" Se2 0 0 0.36765945292\nend atoms_frac"));
+
+    // Labelled atoms are no longer matched by their species.
+    test_input.projections.push(site(
+        ProjectionSite::Species(String::from("Se")),
+        vec![AngularMomentum::S],
+    ));
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].exit_code(), 2);
+}
+
+#[test]
+fn projection_site_conversion() {
+    let cell = Cell {
+        units: LatticeUnits::Angstrom,
+        cell: [[2.0, 0.0, 0.0], [1.0, 2.0, 0.0], [0.0, 0.0, 4.0]],
+    };
+
+    let crystal = ProjectionSite::CenterCrystal([0.5, 0.5, 0.25]);
+    let cartesian = crystal.to_cartesian(&cell);
+    assert_eq!(cartesian, ProjectionSite::CenterCartesian([1.5, 1.0, 1.0]));
+    match cartesian.to_crystal(&cell).unwrap() {
+        ProjectionSite::CenterCrystal(r) => {
+            for (x, y) in r.iter().zip([0.5, 0.5, 0.25].iter()) {
+                assert!((x - y).abs() < 1e-12);
+            }
+        }
+        site => panic!("expected a lattice coordinate site, got {:?}", site),
+    }
+
+    let species = ProjectionSite::Species(String::from("W"));
+    assert_eq!(species.to_cartesian(&cell), species);
+    assert_eq!(species.to_crystal(&cell).unwrap(), species);
+
+    let singular = Cell {
+        units: LatticeUnits::Angstrom,
+        cell: [[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+    assert!(cartesian.to_crystal(&singular).is_err());
+}
+
+#[test]
+fn serialize_options() {
+    let test_input = wse2_input();
+    let options = SerializeOptions::default();
+    let input_text = serialize::make_input_file_with_options(&test_input, &options).unwrap();
+
+    assert!(input_text.contains(
+        "begin unit_cell_cart\nbohr\n\
+         \x20 3.136039759  -5.431780198  0.000000000\n\
+         \x20 3.136039759  5.431780198  0.000000000\n\
+         \x20 0.000000000  0.000000000  68.66291860\n\
+         end unit_cell_cart"
+    ));
+    assert!(input_text.contains(
+        "begin atoms_frac\n\
+         \x20Se 0.000000000 0.000000000 0.2752178565\n\
+         \x20W 0.3333333333 0.6666666667 0.3214386547\n\
+         \x20Se 0.000000000 0.000000000 0.3676594529\n\
+         end atoms_frac"
+    ));
+
+    assert!(input_text.contains(
+        "begin kpoints\n\
+         0.000000000 0.000000000 0.000000000\n\
+         0.000000000 0.1111111111 0.000000000\n"
+    ));
+    assert!(input_text.contains("\nwrite_hr=.true.\n"));
+
+    let short = SerializeOptions {
+        float_precision: Some(4),
+        bool_style: BoolStyle::Short,
+    };
+    let input_text = serialize::make_input_file_with_options(&test_input, &short).unwrap();
+    assert!(input_text.contains("\nwrite_hr=T\n"));
+    assert!(input_text.contains("\nspinors=T\n"));
+    assert!(input_text.contains("\n  3.136  -5.432  0.000\n"));
+    assert!(input_text.contains("\n0.1111 0.2222 0.000\n"));
+    assert!(!input_text.contains(".true."));
+
+    // The default formatting is that of `make_input_file`.
+    assert_eq!(
+        serialize::make_input_file_with_options(&test_input, &SerializeOptions::default())
+            .unwrap(),
+        serialize::make_input_file(&test_input).unwrap()
+    );
+}
+
+#[test]
+fn serialize_options_coordinates() {
+    let mut test_input = wse2_input();
+    test_input.bands_plot = Some(true);
+    test_input.kpoint_path = Some(KPath::through(&[
+        SymmetryPoint::new("G", [0.0, 0.0, 0.0]),
+        SymmetryPoint::new("K", [1.0 / 3.0, 1.0 / 3.0, 0.0]),
+    ]));
+    test_input.projections[1] = "f=0.5,0.25,0:l=2:z=0,0,1".parse().unwrap();
+    if let Some(ref mut dis) = test_input.disentanglement {
+        dis.dis_spheres = Some(DisSpheres {
+            dis_spheres_num: 1,
+            dis_spheres_first_wann: None,
+            spheres: vec![DisSphere {
+                center: [1.0 / 3.0, 0.0, 0.0],
+                radius: 0.2,
+            }],
+        });
+    }
+
+    let short = SerializeOptions {
+        float_precision: Some(4),
+        bool_style: BoolStyle::Fortran,
+    };
+    let input_text = serialize::make_input_file_with_options(&test_input, &short).unwrap();
+    assert!(input_text.contains("\nG 0.000 0.000 0.000 K 0.3333 0.3333 0.000\n"));
+    assert!(input_text.contains("\nf = 0.5000, 0.2500, 0.000:l=2:z=0.000,0.000,1.000\n"));
+    assert!(input_text.contains("\nbegin dis_spheres\n0.3333 0.000 0.000 0.2000\n"));
+
+    // Numbers which round up to the next power of ten keep the same number of digits.
+    test_input.positions.coordinates[0].r = [0.99999999999, 9.9999999999, 99.999];
+    let input_text = make_exact(&test_input);
+    assert!(input_text.contains(" Se 0.99999999999 9.9999999999 99.999\n"));
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains(" Se 1.000000000 10.00000000 99.99900000\n"));
+    let input_text = serialize::make_input_file_with_options(&test_input, &short).unwrap();
+    assert!(input_text.contains(" Se 1.000 10.00 100.0\n"));
+}
+
+#[test]
+fn kmesh_series() {
+    let test_input = wse2_input();
+    assert_eq!(
+        test_input.with_kpoints([6, 6, 1]).kpoints,
+        KPointMesh::Uniform([6, 6, 1])
+    );
+
+    let dir = env::temp_dir().join("w90_kmesh_series");
+    let grids = [[6, 6, 1], [9, 9, 1], [12, 12, 1]];
+    let paths = serialize::write_kmesh_series(&test_input, "WSe2", &grids, &dir).unwrap();
+    assert_eq!(paths.len(), 3);
+    for (path, &nk) in paths.iter().zip(grids.iter()) {
+        assert_eq!(*path, dir.join(serialize::kmesh_dir_name(nk)).join("WSe2.win"));
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            serialize::make_input_file(&test_input.with_kpoints(nk)).unwrap()
+        );
+    }
+    assert!(dir.join("9x9x1").is_dir());
+
+    // Invalid grids are reported individually and prevent writing any of the files.
+    let bad_dir = env::temp_dir().join("w90_kmesh_series_invalid");
+    let _ = fs::remove_dir_all(&bad_dir);
+    let errs = serialize::write_kmesh_series(
+        &test_input,
+        "WSe2",
+        &[[0, 6, 1], [9, 9, 1], [4, 0, 1]],
+        &bad_dir,
+    ).unwrap_err();
+    let bad_grids: Vec<[u64; 3]> = errs.iter().map(|&(nk, _)| nk).collect();
+    assert_eq!(bad_grids, vec![[0, 6, 1], [4, 0, 1]]);
+    assert!(!bad_dir.exists());
+}
+
+#[test]
+fn projection_centers_outside_cell() {
+    let mut test_input = wse2_input();
+    test_input.num_wann = 22 + 2 * 3;
+    test_input.projections.extend(vec![
+        site(ProjectionSite::CenterCrystal([0.5, -0.4, 1.2]), vec![AngularMomentum::S]),
+        site(ProjectionSite::CenterCrystal([0.5, 0.5, 1.7]), vec![AngularMomentum::S]),
+        // 2 * 68.66 bohr is about 72.7 Angstrom along the third lattice vector.
+        site(ProjectionSite::CenterCartesian([0.0, 0.0, 72.7]), vec![AngularMomentum::S]),
+    ]);
+
+    assert_eq!(
+        input::validate(&test_input).unwrap(),
+        vec![
+            input::Warning::ProjectionCenterOutsideCell { index: 3 },
+            input::Warning::ProjectionCenterOutsideCell { index: 4 },
+        ]
+    );
+
+    let options = input::LintOptions {
+        projection_center_margin: 1.5,
+        allow_mixed_units: false,
+    };
+    assert!(input::lint_with_options(&test_input, &options).is_empty());
+
+    test_input.projection_units = Some(LatticeUnits::Bohr);
+    assert_eq!(
+        input::lint(&test_input),
+        vec![input::Warning::ProjectionCenterOutsideCell { index: 3 }]
+    );
+}
+
+#[test]
+fn selective_localization() {
+    let mut test_input = wse2_input();
+    test_input.selective_localization = Some(SelectiveLocalization {
+        slwf_num: 10,
+        slwf_constrain: Some(true),
+        slwf_lambda: Some(1.5),
+    });
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains("slwf_num = 10\nslwf_constrain=.true.\nslwf_lambda = 1.5\n"));
+
+    test_input.selective_localization = Some(SelectiveLocalization {
+        slwf_num: 23,
+        slwf_constrain: None,
+        slwf_lambda: Some(-1.0),
+    });
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 2);
+    assert!(errs.iter().all(|e| e.exit_code() == 6));
+}
+
+#[test]
+fn site_symmetry() {
+    let mut test_input = wse2_input();
+    test_input.site_symmetry = Some(true);
+    test_input.symmetrize_eps = Some(1e-9);
+
+    let header = serialize::make_header(&test_input, &SerializeOptions::exact());
+    assert!(header.contains("\nsite_symmetry=.true.\nsymmetrize_eps = 0.000000001\n"));
+
+    test_input.symmetrize_eps = Some(0.0);
+    assert_eq!(input::validate(&test_input).unwrap_err().errs.len(), 1);
+
+    test_input.site_symmetry = None;
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 2);
+    assert!(errs.iter().all(|e| e.exit_code() == 6));
+}
+
+#[test]
+fn position_units_mismatch() {
+    let mut test_input = wse2_input();
+    test_input.positions = test_input
+        .positions
+        .to_cartesian(&test_input.unit_cell_cart, LatticeUnits::Bohr);
+    assert!(input::lint(&test_input).is_empty());
+
+    test_input.unit_cell_cart = test_input.unit_cell_cart.to_units(LatticeUnits::Angstrom);
+    assert_eq!(
+        input::lint(&test_input),
+        vec![input::Warning::PositionUnitsMismatch]
+    );
+
+    let options = input::LintOptions {
+        allow_mixed_units: true,
+        ..Default::default()
+    };
+    assert!(input::lint_with_options(&test_input, &options).is_empty());
+
+    test_input.positions = test_input.positions.to_crystal(&test_input.unit_cell_cart).unwrap();
+    assert!(input::lint(&test_input).is_empty());
+}
+
+#[test]
+fn berry() {
+    let mut test_input = wse2_input();
+    test_input.berry = Some(Berry {
+        berry_task: BerryTask::Ahc,
+        berry_kmesh: [100, 100, 1],
+        berry_curv_adpt_kmesh: Some(5),
+    });
+
+    let sections = serialize::make_sections(&test_input).unwrap();
+    assert_eq!(
+        sections.berry.unwrap(),
+        "berry=.true.\nberry_task = ahc\nberry_kmesh = 100 100 1\nberry_curv_adpt_kmesh = 5"
+    );
+
+    let tasks = vec![
+        (BerryTask::Morb, "morb"),
+        (BerryTask::Kubo, "kubo"),
+        (BerryTask::Shc, "shc"),
+    ];
+    for (task, expected) in tasks {
+        assert_eq!(task.value(), expected);
+    }
+
+    if let Some(ref mut berry) = test_input.berry {
+        berry.berry_kmesh = [100, 0, 1];
+        berry.berry_curv_adpt_kmesh = Some(0);
+    }
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 2);
+    assert!(errs.iter().all(|e| e.exit_code() == 7));
+}
+
+#[test]
+fn boltzwann() {
+    let mut test_input = wse2_input();
+    test_input.boltzwann = Some(BoltzWann {
+        boltz_kmesh: [60, 60, 1],
+        boltz_tdf_energy_step: Some(0.001),
+        boltz_mu_min: -1.0,
+        boltz_mu_max: 1.0,
+        boltz_mu_step: 0.05,
+        boltz_temp_min: 300.0,
+        boltz_temp_max: 300.0,
+        boltz_temp_step: 50.0,
+        boltz_relax_time: Some(10.0),
+    });
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains(
+        "boltzwann=.true.\nboltz_kmesh = 60 60 1\nboltz_tdf_energy_step = 0.001\n\
+         boltz_mu_min = -1\nboltz_mu_max = 1\nboltz_mu_step = 0.05\n\
+         boltz_temp_min = 300\nboltz_temp_max = 300\nboltz_temp_step = 50\n\
+         boltz_relax_time = 10\n"
+    ));
+
+    if let Some(ref mut boltz) = test_input.boltzwann {
+        boltz.boltz_mu_min = 2.0;
+        boltz.boltz_mu_step = 0.0;
+        boltz.boltz_temp_max = 100.0;
+        boltz.boltz_temp_step = -50.0;
+    }
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 4);
+    assert!(errs.iter().all(|e| e.exit_code() == 7));
+}
+
+#[test]
+fn geninterp() {
+    let mut test_input = wse2_input();
+    test_input.geninterp_alsofirstder = Some(true);
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].exit_code(), 7);
+
+    test_input.geninterp = Some(true);
+    let sections = serialize::make_sections(&test_input).unwrap();
+    assert_eq!(
+        sections.geninterp.unwrap(),
+        "geninterp=.true.\ngeninterp_alsofirstder=.true."
+    );
+
+    let kpoints = vec![[0.0, 0.0, 0.0], [0.5, 0.25, 0.0]];
+    let kpt_text = serialize::make_geninterp_kpt(
+        &kpoints,
+        GeninterpCoordinates::Crystal,
+        &SerializeOptions::exact(),
+    ).unwrap();
+    let lines: Vec<&str> = kpt_text.lines().collect();
+    assert!(lines[0].starts_with("! "));
+    assert_eq!(&lines[1..], &["crystal", "2", "1 0 0 0", "2 0.5 0.25 0"]);
+
+    let path = env::temp_dir().join("w90_input_geninterp.kpt");
+    let err = serialize::write_geninterp_kpt(
+        &[],
+        GeninterpCoordinates::Cartesian,
+        &SerializeOptions::default(),
+        &path,
+    ).unwrap_err();
+    assert_eq!(err.exit_code(), 9);
+    assert_eq!(format!("{}", err), "The geninterp k-point list is empty.");
+    assert!(!path.exists());
+}
+
+#[test]
+fn for_restart() {
+    let test_input = wse2_input();
+    let restart = test_input.for_restart(Restart::Plot);
+
+    assert_eq!(restart.restart, Some(Restart::Plot));
+    assert_eq!(restart.unit_cell_cart, test_input.unit_cell_cart);
+    assert_eq!(restart.positions, test_input.positions);
+    assert_eq!(restart.kpoints, test_input.kpoints);
+
+    let input_text = serialize::make_input_file(&restart).unwrap();
+    assert!(input_text.lines().any(|l| l == "restart = plot"));
+}
+
+#[test]
+fn kmesh_tuning() {
+    let mut test_input = wse2_input();
+    test_input.kmesh_tol = Some(1e-4);
+    test_input.search_shells = Some(24);
+    test_input.skip_b1_tests = Some(true);
+
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.contains(
+        "kmesh_tol = 0.0001\nsearch_shells = 24\nskip_b1_tests=.true.\nmp_grid = 9 9 1"
+    ));
+
+    test_input.kmesh_tol = Some(0.0);
+    test_input.search_shells = Some(6);
+    assert_eq!(input::validate(&test_input).unwrap_err().errs.len(), 2);
+}
+
+#[test]
+fn uniform_shifted_kpoints() {
+    let expected = vec![
+        [1.0 / 6.0, 0.0, 0.25],
+        [1.0 / 6.0, 0.0, 0.75],
+        [0.5, 0.0, 0.25],
+        [0.5, 0.0, 0.75],
+        [5.0 / 6.0, 0.0, 0.25],
+        [5.0 / 6.0, 0.0, 0.75],
+    ];
+    let kpoints = kmesh::uniform_shifted([3, 1, 2], [0.5, 0.0, 0.5]);
+    assert_eq!(kpoints.len(), expected.len());
+    for (k, k_expected) in kpoints.iter().zip(&expected) {
+        assert!((0..3).all(|i| (k[i] - k_expected[i]).abs() < 1e-12));
+    }
+
+    let mesh = KPointMesh::UniformShifted([3, 1, 2], [0.5, 0.0, 0.5]);
+    assert_eq!(mesh.points().collect::<Vec<[f64; 3]>>(), kpoints);
+
+    let mut test_input = wse2_input();
+    test_input.kpoints = mesh;
+    let mut listing = Vec::new();
+    serialize::make_kpoints(kpoints, &SerializeOptions::default(), &mut listing).unwrap();
+    assert!(serialize::make_input_file(&test_input)
+        .unwrap()
+        .ends_with(String::from_utf8(listing).unwrap().as_str()));
+}
+
+#[test]
+fn input_kpoint_iter() {
+    let mut test_input = wse2_input();
+    test_input.kpoints = KPointMesh::Uniform([2, 2, 1]);
+
+    // The first component varies slowest.
+    let ks = test_input.kpoint_iter();
+    assert_eq!(ks.size_hint(), (4, Some(4)));
+    assert_eq!(
+        ks.collect::<Vec<[f64; 3]>>(),
+        vec![
+            [0.0, 0.0, 0.0],
+            [0.0, 0.5, 0.0],
+            [0.5, 0.0, 0.0],
+            [0.5, 0.5, 0.0],
+        ]
+    );
+    let input_text = make_exact(&test_input);
+    assert!(input_text
+        .ends_with("begin kpoints\n0 0 0\n0 0.5 0\n0.5 0 0\n0.5 0.5 0\nend kpoints"));
+
+    test_input.kpoints = KPointMesh::Uniform([1, 1, 1]);
+    test_input.gamma_only = Some(true);
+    assert_eq!(test_input.kpoint_iter().collect::<Vec<[f64; 3]>>(), vec![[0.0; 3]]);
+}
+
+#[test]
+fn uniform_kpoint_order() {
+    // The last component varies fastest.
+    let expected_first = vec![
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.5],
+        [0.0, 0.5, 0.0],
+        [0.0, 0.5, 0.5],
+    ];
+    let expected_last = vec![
+        [0.5, 0.0, 0.0],
+        [0.5, 0.0, 0.5],
+        [0.5, 0.5, 0.0],
+        [0.5, 0.5, 0.5],
+    ];
+
+    let qe_kpoints = generate_uniform_kpoints([2, 2, 2]);
+    assert_eq!(qe_kpoints.len(), 8);
+    assert_eq!(qe_kpoints[..4].to_vec(), expected_first);
+    assert_eq!(qe_kpoints[4..].to_vec(), expected_last);
+
+    let w90_kpoints: Vec<[f64; 3]> = KPointMesh::Uniform([2, 2, 2]).points().collect();
+    assert_eq!(w90_kpoints, qe_kpoints);
+
+    let mut test_input = wse2_input();
+    test_input.kpoints = KPointMesh::Uniform([2, 2, 2]);
+    let input_text = make_exact(&test_input);
+    assert!(input_text.ends_with(
+        "begin kpoints\n0 0 0\n0 0 0.5\n0 0.5 0\n0 0.5 0.5\n\
+         0.5 0 0\n0.5 0 0.5\n0.5 0.5 0\n0.5 0.5 0.5\nend kpoints"
+    ));
+}
+
+#[test]
+fn lazy_uniform_kpoints() {
+    for &nk in &[[1, 1, 1], [9, 9, 1], [3, 2, 4]] {
+        let lazy: Vec<[f64; 3]> = kmesh::uniform(nk).collect();
+        let expected = generate_uniform_kpoints(nk);
+
+        assert_eq!(lazy.len(), expected.len());
+        assert_eq!(kmesh::uniform(nk).len(), expected.len());
+        for (k, k_expected) in lazy.iter().zip(expected.iter()) {
+            for i in 0..3 {
+                assert!((k[i] - k_expected[i]).abs() < 1e-12);
+            }
+        }
+    }
+
+    let test_input = wse2_input();
+    assert_eq!(
+        serialize::make_mp_grid(&test_input),
+        Some(String::from("mp_grid = 9 9 1"))
+    );
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_kpoints() {
+    // More k-points than are formatted in one parallel chunk.
+    let kpoints: Vec<[f64; 3]> = kmesh::uniform([17, 17, 17]).collect();
+    let exact = SerializeOptions::exact();
+
+    assert_eq!(
+        serialize::format_kpoints_parallel(&kpoints, &exact),
+        serialize::format_kpoints(&kpoints, &exact)
+    );
+
+    let mut test_input = wse2_input();
+    test_input.kpoints = KPointMesh::Uniform([17, 17, 17]);
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(input_text.ends_with(&format!(
+        "begin kpoints\n{}end kpoints",
+        serialize::format_kpoints(&kpoints, &SerializeOptions::default())
+    )));
+}
+
+#[test]
+fn parse_angular_momentum() {
+    let cases = vec![
+        ("l=0", AngularMomentum::S),
+        ("s", AngularMomentum::S),
+        ("l=1", AngularMomentum::P),
+        ("P", AngularMomentum::P),
+        ("l = 2", AngularMomentum::D),
+        ("d", AngularMomentum::D),
+        ("l=3", AngularMomentum::F),
+        ("f", AngularMomentum::F),
+    ];
+
+    for (token, expected) in cases {
+        assert_eq!(token.parse::<AngularMomentum>().unwrap(), expected);
+    }
+
+    for token in &["l=4", "g", "sp4", ""] {
+        assert!(token.parse::<AngularMomentum>().is_err());
+    }
+
+    let proj: Projection = "W:d;s".parse().unwrap();
+    assert_eq!(proj.value(), "W:l=2;l=0");
+}
+
+fn site(site: ProjectionSite, ang_mtm: Vec<AngularMomentum>) -> Projection {
+    Projection::Site {
+        site,
+        ang_mtm,
+        zaxis: None,
+        xaxis: None,
+        radial: None,
+        zona: None,
+        spin: None,
+        quant_dir: None,
+        per_orbital: Vec::new(),
+    }
+}
+
+#[test]
+fn parse_projection() {
+    use AngularMomentum::{D, P, S};
+    use ProjectionSite::{CenterCartesian, CenterCrystal, Species};
+
+    let cases = vec![
+        ("random", Projection::Random),
+        ("Random", Projection::Random),
+        ("Se:l=1", site(Species(String::from("Se")), vec![P])),
+        ("Ba:s;p", site(Species(String::from("Ba")), vec![S, P])),
+        (
+            "f=0.5,0.5,0.5:s",
+            site(CenterCrystal([0.5, 0.5, 0.5]), vec![S]),
+        ),
+        (
+            "c=0,0,0:l=0;l=1:z=0,0,1",
+            Projection::Site {
+                site: CenterCartesian([0.0, 0.0, 0.0]),
+                ang_mtm: vec![S, P],
+                zaxis: Some([0.0, 0.0, 1.0]),
+                xaxis: None,
+                radial: None,
+                zona: None,
+                spin: None,
+                quant_dir: None,
+                per_orbital: Vec::new(),
+            },
+        ),
+        (
+            "O:p:z=0,0,1:x=1,0,0",
+            Projection::Site {
+                site: Species(String::from("O")),
+                ang_mtm: vec![P],
+                zaxis: Some([0.0, 0.0, 1.0]),
+                xaxis: Some([1.0, 0.0, 0.0]),
+                radial: None,
+                zona: None,
+                spin: None,
+                quant_dir: None,
+                per_orbital: Vec::new(),
+            },
+        ),
+        (
+            "Fe:l=2:r=2:zona=2.5",
+            Projection::Site {
+                site: Species(String::from("Fe")),
+                ang_mtm: vec![D],
+                zaxis: None,
+                xaxis: None,
+                radial: Some(2),
+                zona: Some(2.5),
+                spin: None,
+                quant_dir: None,
+                per_orbital: Vec::new(),
+            },
+        ),
+    ];
+
+    for (line, expected) in cases {
+        let proj: Projection = line.parse().unwrap();
+        assert_eq!(proj, expected, "{}", line);
+
+        // Serialized forms parse back to the same projection.
+        assert_eq!(proj.value().parse::<Projection>().unwrap(), proj);
+    }
+
+    let invalid = vec![
+        "",
+        "Se",
+        "Se:l=5",
+        "c=0,0:l=0",
+        "Se:l=1:z=0,0,1:z=1,0,0",
+        "Se:l=1:y=0,1,0",
+        "Se:l=1:r=two",
+    ];
+    for line in invalid {
+        assert!(line.parse::<Projection>().is_err(), "{}", line);
+    }
+}
+
+#[test]
+fn reassemble_sections() {
+    let mut test_input = wse2_input();
+    let sections = serialize::make_sections(&test_input).unwrap();
+    assert_eq!(sections.join(), serialize::make_input_file(&test_input).unwrap());
+    assert!(sections.dos.is_none());
+    assert!(sections.unit_cell.starts_with("begin unit_cell_cart"));
+
+    test_input.disentanglement = None;
+    test_input.gamma_only = Some(true);
+    test_input.kpoints = KPointMesh::Uniform([1, 1, 1]);
+    let sections = serialize::make_sections(&test_input).unwrap();
+    assert_eq!(sections.join(), serialize::make_input_file(&test_input).unwrap());
+}
+
+#[test]
+fn projection_axes() {
+    let mut test_input = wse2_input();
+    let with_axes = |zaxis, xaxis| Projection::Site {
+        site: ProjectionSite::Species(String::from("W")),
+        ang_mtm: vec![AngularMomentum::D],
+        zaxis,
+        xaxis,
+        radial: None,
+        zona: None,
+        spin: None,
+        quant_dir: None,
+        per_orbital: Vec::new(),
+    };
+
+    test_input.projections[1] = with_axes(Some([0.0, 0.0, 2.0]), Some([1.0, 1.0, 0.0]));
+    assert!(input::validate(&test_input).is_ok());
+
+    test_input.projections[1] = with_axes(Some([1.0, 1.0, 1.0]), Some([1.0, 0.0, 0.0]));
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.projections[1] = with_axes(Some([0.0, 0.0, 0.0]), None);
+    assert!(input::validate(&test_input).is_err());
+
+    test_input.projections[1] = with_axes(None, Some([0.0, 0.0, 0.0]));
+    assert!(input::validate(&test_input).is_err());
+}
+
+#[test]
+fn indexed_projection_errors() {
+    let mut test_input = wse2_input();
+    if let Projection::Site { ref mut site, .. } = test_input.projections[0] {
+        *site = ProjectionSite::Species(String::from("Mo"));
+    }
+    if let Projection::Site { ref mut zaxis, .. } = test_input.projections[1] {
+        *zaxis = Some([0.0, 0.0, 0.0]);
+    }
+    test_input.projections.push(Projection::Random);
+    test_input.projections.push(Projection::Random);
+
+    let errs = input::validate(&test_input).unwrap_err();
+    let message = format!("{}", errs);
+    assert!(message.contains(
+        "Projection 0: species `Mo` does not match the species or label of an atom."
+    ));
+    assert!(message.contains("Projection 1: axes must be nonzero."));
+    assert!(message.contains("`Random` may appear at most once in the list of projections."));
+    assert!(errs.errs.iter().any(|e| match *e {
+        input::Error::UnknownProjectionSpecies { index: 0, ref species } => species == "Mo",
+        _ => false,
+    }));
+}
+
+#[test]
+fn radial_and_zona() {
+    let set_radial = |proj: &mut Projection, r: Option<u64>, z: Option<f64>| {
+        if let Projection::Site {
+            ref mut radial,
+            ref mut zona,
+            ..
+        } = *proj
+        {
+            *radial = r;
+            *zona = z;
+        }
+    };
+
+    let mut test_input = wse2_input();
+    set_radial(&mut test_input.projections[0], Some(1), Some(0.5));
+    set_radial(&mut test_input.projections[1], Some(3), None);
+    assert!(input::validate(&test_input).is_ok());
+
+    set_radial(&mut test_input.projections[0], Some(4), None);
+    set_radial(&mut test_input.projections[1], Some(2), Some(0.0));
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 2);
+    assert!(errs.iter().any(|e| match *e {
+        input::Error::InvalidRadial { index: 0 } => true,
+        _ => false,
+    }));
+    assert!(errs.iter().any(|e| match *e {
+        input::Error::InvalidZona { index: 1 } => true,
+        _ => false,
+    }));
+    assert!(errs.iter().all(|e| e.exit_code() == 2));
+
+    // Per-orbital values are checked too.
+    set_radial(&mut test_input.projections[0], None, None);
+    set_radial(&mut test_input.projections[1], None, None);
+    if let Projection::Site {
+        ref mut per_orbital,
+        ..
+    } = test_input.projections[0]
+    {
+        per_orbital.push(OrbitalRadial {
+            ang_mtm: AngularMomentum::P,
+            radial: Some(0),
+            zona: Some(-1.0),
+        });
+    }
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 2);
+    let message = format!("{}", input::ErrorList { errs });
+    assert!(message.contains("Projection 0: `radial` must be in the range 1..=3."));
+    assert!(message.contains("Projection 0: `zona` must be positive."));
+}
+
+#[test]
+fn normalized_projection_axes() {
+    let proj: Projection = "Se:l=1:z=1,1,1:x=1,-1,0".parse().unwrap();
+    let normalized = proj.normalized();
+
+    match normalized {
+        Projection::Site { zaxis, xaxis, .. } => {
+            let (z, x) = (zaxis.unwrap(), xaxis.unwrap());
+            let a = 1.0 / (3.0f64).sqrt();
+            let b = 1.0 / (2.0f64).sqrt();
+            for i in 0..3 {
+                assert!((z[i] - a).abs() < 1e-12);
+            }
+            assert!((x[0] - b).abs() < 1e-12 && (x[1] + b).abs() < 1e-12 && x[2] == 0.0);
+        }
+        Projection::Random => panic!("expected site projection"),
+    }
+
+    // Normalization is opt-in: serialization of the original is unchanged.
+    assert_eq!(proj.value(), "Se:l=1:z=1,1,1:x=1,-1,0");
+    assert_eq!(Projection::Random.normalized(), Projection::Random);
+}
+
+#[test]
+fn per_orbital_radial() {
+    use AngularMomentum::{D, P, S};
+
+    // Whole-site shorthand: one line with a single `r` and `zona`.
+    let shorthand = Projection::Site {
+        site: ProjectionSite::Species(String::from("Ba")),
+        ang_mtm: vec![S, P],
+        zaxis: None,
+        xaxis: None,
+        radial: Some(2),
+        zona: Some(1.5),
+        spin: None,
+        quant_dir: None,
+        per_orbital: Vec::new(),
+    };
+    assert_eq!(shorthand.value(), "Ba:l=0;l=1:r=2:zona=1.5");
+    assert_eq!(shorthand.radial_for(&P), (Some(2), Some(1.5)));
+
+    // Per-orbital settings override the site-wide values; orbitals sharing settings
+    // stay on one line, in their original order.
+    let per_orbital = Projection::Site {
+        site: ProjectionSite::Species(String::from("Ba")),
+        ang_mtm: vec![S, P, D],
+        zaxis: Some([0.0, 0.0, 1.0]),
+        xaxis: None,
+        radial: Some(2),
+        zona: Some(1.5),
+        spin: None,
+        quant_dir: None,
+        per_orbital: vec![
+            OrbitalRadial {
+                ang_mtm: D,
+                radial: Some(3),
+                zona: Some(0.5),
+            },
+        ],
+    };
+    assert_eq!(per_orbital.radial_for(&D), (Some(3), Some(0.5)));
+    assert_eq!(
+        per_orbital.value(),
+        "Ba:l=0;l=1:z=0,0,1:r=2:zona=1.5\nBa:l=2:z=0,0,1:r=3:zona=0.5"
+    );
+
+    // An override with only `zona` keeps the site-wide `r`.
+    let zona_only = Projection::Site {
+        site: ProjectionSite::Species(String::from("Ba")),
+        ang_mtm: vec![S, P],
+        zaxis: None,
+        xaxis: None,
+        radial: Some(2),
+        zona: None,
+        spin: None,
+        quant_dir: None,
+        per_orbital: vec![
+            OrbitalRadial {
+                ang_mtm: S,
+                radial: None,
+                zona: Some(2.0),
+            },
+        ],
+    };
+    assert_eq!(zona_only.value(), "Ba:l=0:r=2:zona=2\nBa:l=1:r=2");
+
+    // Each emitted line parses back to the corresponding shorthand projection.
+    let lines: Vec<Projection> = per_orbital
+        .value()
+        .lines()
+        .map(|l| l.parse().unwrap())
+        .collect();
+    assert_eq!(lines[1].radial_for(&D), (Some(3), Some(0.5)));
+
+    // Overrides must refer to an angular momentum in the projection.
+    let mut test_input = wse2_input();
+    test_input.projections[1] = Projection::Site {
+        site: ProjectionSite::Species(String::from("W")),
+        ang_mtm: vec![D],
+        zaxis: None,
+        xaxis: None,
+        radial: None,
+        zona: None,
+        spin: None,
+        quant_dir: None,
+        per_orbital: vec![
+            OrbitalRadial {
+                ang_mtm: S,
+                radial: None,
+                zona: Some(2.0),
+            },
+        ],
+    };
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].exit_code(), 2);
+}
+
+#[test]
+fn kpoints_field() {
+    use std::fs;
+
+    let mut test_input = wse2_input();
+    test_input.kpoints = KPointMesh::Uniform([2, 1, 1]);
+
+    let text = make_exact(&test_input);
+    assert!(text.contains("mp_grid = 2 1 1\n"));
+    assert!(text.ends_with("begin kpoints\n0 0 0\n0.5 0 0\nend kpoints"));
+
+    // Configuration files using the previous field name are still accepted.
+    let json_path = env::temp_dir().join("w90_kpoints_field.json");
+    input::to_json_file(&test_input, &json_path).unwrap();
+    let old = fs::read_to_string(&json_path)
+        .unwrap()
+        .replace("\"kpoints\"", "\"k_points\"");
+    fs::write(&json_path, old).unwrap();
+    assert_eq!(input::from_json_file(&json_path).unwrap(), test_input);
+}
+
+#[test]
+fn write_tb_u_matrices() {
+    let exact = SerializeOptions::exact();
+    let mut test_input = wse2_input();
+    test_input.write_tb = Some(true);
+    test_input.write_u_matrices = Some(true);
+
+    let header = serialize::make_header(&test_input, &exact);
+    assert!(header.ends_with("write_hr=.true.\nwrite_tb=.true.\nwrite_u_matrices=.true."));
+    assert_eq!(input::lint(&test_input), vec![input::Warning::RedundantWriteHr]);
+
+    test_input.write_hr = None;
+    test_input.write_u_matrices = Some(false);
+    let header = serialize::make_header(&test_input, &exact);
+    assert!(!header.contains("write_hr"));
+    assert!(header.ends_with("write_tb=.true.\nwrite_u_matrices=.false."));
+    assert!(input::lint(&test_input).is_empty());
+}
+
+#[test]
+fn print_dump_cycles() {
+    let exact = SerializeOptions::exact();
+    let mut test_input = wse2_input();
+    test_input.num_print_cycles = Some(50);
+    test_input.num_dump_cycles = Some(100);
+
+    let header = serialize::make_header(&test_input, &exact);
+    assert!(header.contains("num_iter = 0\nnum_print_cycles = 50\nnum_dump_cycles = 100\n"));
+
+    test_input.num_print_cycles = Some(0);
+    test_input.num_dump_cycles = Some(0);
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 2);
+    assert!(errs.iter().all(|e| e.exit_code() == 6));
+}
+
+#[test]
+fn optimisation() {
+    let exact = SerializeOptions::exact();
+    let mut test_input = wse2_input();
+    test_input.optimisation = Some(-1);
+    assert!(input::validate(&test_input).is_ok());
+
+    let header = serialize::make_header(&test_input, &exact);
+    assert!(header.lines().any(|l| l == "optimisation = -1"));
+
+    test_input.optimisation = Some(input::MAX_OPTIMISATION + 1);
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert_eq!(format!("{}", errs[0]), "`optimisation` must be in the range -1..=3.");
+}
+
+#[test]
+fn restart_modes() {
+    let exact = SerializeOptions::exact();
+    let mut test_input = wse2_input();
+    let header = serialize::make_header(&test_input, &exact);
+    assert!(!header.contains("restart"));
+
+    let modes = vec![
+        (Restart::Default, "restart = default"),
+        (Restart::Wannierise, "restart = wannierise"),
+        (Restart::Plot, "restart = plot"),
+        (Restart::Transport, "restart = transport"),
+    ];
+    for (mode, expected) in modes {
+        test_input.restart = Some(mode);
+        let header = serialize::make_header(&test_input, &exact);
+        assert_eq!(header.lines().filter(|l| l.starts_with("restart")).count(), 1);
+        assert!(header.lines().any(|l| l == expected));
+    }
+}
+
+#[test]
+fn use_ws_distance() {
+    let exact = SerializeOptions::exact();
+    let mut test_input = wse2_input();
+    assert!(!serialize::make_header(&test_input, &exact).contains("use_ws_distance"));
+
+    test_input.use_ws_distance = Some(true);
+    let header = serialize::make_header(&test_input, &exact);
+    assert!(header.lines().any(|l| l == "use_ws_distance=.true."));
+}
+
+#[test]
+fn provenance_header() {
+    use std::time::{Duration, UNIX_EPOCH};
+    use w90::parse;
+
+    let time = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let header = serialize::provenance_header(time, Some("WSe2 monolayer\n\nwith SOC"));
+    let expected = format!(
+        "! Generated by w90-rs v{} on 2001-09-09T01:46:40Z\n! WSe2 monolayer\n!\n! with SOC\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    assert_eq!(header, expected);
+
+    let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+    assert!(serialize::provenance_header(leap_day, None).ends_with(" on 2000-02-29T00:00:00Z\n"));
+
+    // The header lines are comments, so the file reads back the same as without them.
+    let test_input = wse2_input();
+    let with_header = serialize::make_input_file_with_header(&test_input, Some("note")).unwrap();
+    assert!(with_header.starts_with("! Generated by w90-rs v"));
+    let plain = serialize::make_input_file(&test_input).unwrap();
+    assert!(with_header.ends_with(&plain));
+
+    let win = parse::parse_win(&with_header).unwrap();
+    assert_eq!(win, parse::parse_win(&plain).unwrap());
+    assert_eq!(win.keyword("num_wann"), Some("22"));
+    assert_eq!(win.keyword("write_hr"), Some(".true."));
+    assert_eq!(win.block("kpoints").map(|b| b.len()), Some(81));
+}
+
+#[test]
+fn parse_win_structure() {
+    use w90::parse;
+
+    let text = "NUM_WANN : 8 ! 4 per atom
+num_bands 12
+# full-line comment
+begin Projections
+  Se:l=1
+end projections
+";
+    let win = parse::parse_win(text).unwrap();
+    assert_eq!(win.keyword("num_wann"), Some("8"));
+    assert_eq!(win.keyword("num_bands"), Some("12"));
+    assert_eq!(win.block("projections"), Some(&[String::from("Se:l=1")][..]));
+
+    assert!(parse::parse_win("begin kpoints\n0 0 0\n").is_err());
+    assert!(parse::parse_win("begin kpoints\n0 0 0\nend projections\n").is_err());
+    assert!(parse::parse_win("num_wann =\n").is_err());
+}
+
+#[test]
+fn comment_round_trip() {
+    use w90::parse;
+
+    let text = "num_bands = 44
+num_wann = 22 ! 11 per spin
+write_hr=.true. # for the tight-binding model
+begin projections ! W d, Se p
+W:l=2 ! not kept
+end projections
+";
+    let win = parse::parse_win(text).unwrap();
+    assert_eq!(win.comments.len(), 3);
+    assert_eq!(win.comments["num_wann"], "11 per spin");
+    assert_eq!(win.comments["projections"], "W d, Se p");
+
+    let mut test_input = wse2_input();
+    test_input.comments = win.comments.clone();
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+
+    assert!(input_text.contains("\nnum_wann = 22 ! 11 per spin\n"));
+    assert!(input_text.contains("\nwrite_hr=.true. ! for the tight-binding model\n"));
+    assert!(input_text.contains("\nbegin projections ! W d, Se p\n"));
+    assert!(input_text.starts_with("num_bands = 44\n"));
+
+    let reparsed = parse::parse_win(&input_text).unwrap();
+    assert_eq!(reparsed.comments, win.comments);
+    assert_eq!(reparsed.keyword("num_wann"), Some("22"));
+}
+
+#[test]
+fn zero_kpoint_dimension() {
+    let mut test_input = wse2_input();
+    test_input.kpoints = KPointMesh::Uniform([9, 9, 0]);
+
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].exit_code(), 4);
+
+    let path = env::temp_dir().join("w90_zero_kpoint_dimension.win");
+    let _ = std::fs::remove_file(&path);
+    assert!(serialize::write_input_file(&test_input, &path).is_err());
+    assert!(!path.exists());
+
+    test_input.kpoints = KPointMesh::UniformShifted([0, 9, 1], [0.5, 0.5, 0.0]);
+    assert!(input::validate(&test_input).is_err());
+}
+
+#[test]
+fn merge_projections() {
+    let parse = |s: &str| -> Projection { s.parse().unwrap() };
+    let projections = vec![
+        parse("Se:l=0"),
+        parse("W:l=2"),
+        Projection::Random,
+        parse("Se:l=1"),
+        parse("Se:l=1:z=0,0,1"),
+        parse("W:l=0"),
+    ];
+
+    let merged = input::merge_projections(&projections);
+    assert_eq!(
+        merged,
+        vec![
+            parse("Se:l=0;l=1"),
+            parse("W:l=2;l=0"),
+            Projection::Random,
+            parse("Se:l=1:z=0,0,1"),
+        ]
+    );
+
+    let positions = wse2_input().positions;
+    let count = |ps: &[Projection]| -> u64 {
+        ps.iter()
+            .map(|p| input::count_projection_orbitals(p, &positions))
+            .sum()
+    };
+    assert_eq!(count(&merged), count(&projections));
+}
+
+#[test]
+fn spinor_projections() {
+    use w90::input::SpinProjection;
+
+    let up: Projection = "W:l=2(u)[0,0,1]".parse().unwrap();
+    match up {
+        Projection::Site {
+            ref spin,
+            quant_dir,
+            ..
+        } => {
+            assert_eq!(*spin, Some(SpinProjection::Up));
+            assert_eq!(quant_dir, Some([0.0, 0.0, 1.0]));
+        }
+        Projection::Random => panic!("expected site projection"),
+    }
+    assert_eq!(up.value(), "W:l=2(u)[0,0,1]");
+
+    let both: Projection = "Se : l=0;l=1 (u,d) : z=0,0,1".parse().unwrap();
+    assert_eq!(both.value(), "Se:l=0;l=1(u,d):z=0,0,1");
+    assert_eq!("Se:p(d)".parse::<Projection>().unwrap().value(), "Se:l=1(d)");
+    assert!("Se:p(x)".parse::<Projection>().is_err());
+    assert!("Se:p(u)0,0,1".parse::<Projection>().is_err());
+
+    // Selecting one spin channel gives one Wannier function per orbital.
+    let mut test_input = wse2_input();
+    test_input.projections = vec![up, both];
+    test_input.num_wann = 5 + 2 * 2 * 4;
+    assert!(input::validate(&test_input).is_ok());
+    assert_eq!(input::count_wannier(&test_input), 5 + 2 * 2 * 4);
+
+    // Two spin projections without spinors, and the count no longer matches `num_wann`.
+    test_input.spinors = false;
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 3);
+    assert!(errs.iter().all(|e| e.exit_code() == 2));
+}
+
+#[test]
+fn spinor_wannier_count() {
+    let mut test_input = wse2_input();
+    assert!(test_input.spinors);
+
+    // W d (5) and two Se p (3 each): 11 orbitals, doubled once for spinors.
+    let orbitals: u64 = test_input
+        .projections
+        .iter()
+        .map(|p| input::count_projection_orbitals(p, &test_input.positions))
+        .sum();
+    assert_eq!(orbitals, 11);
+    assert_eq!(input::count_wannier(&test_input), test_input.num_wann);
+
+    test_input.spinors = false;
+    assert_eq!(input::count_wannier(&test_input), 11);
+
+    // The spin factor counts towards the `num_bands` limit.
+    test_input.spinors = true;
+    test_input.num_bands = 21;
+    test_input.num_wann = 21;
+    test_input.disentanglement = None;
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert!(errs.iter().any(|e| match *e {
+        input::Error::ProjectionsExceedNumBands => true,
+        _ => false,
+    }));
+
+    test_input.spinors = false;
+    test_input.num_wann = 11;
+    assert!(input::validate(&test_input).is_ok());
+}
+
+#[test]
+fn projection_orbital_counts() {
+    let positions = wse2_input().positions;
+    let count = |line: &str| {
+        let proj: Projection = line.parse().unwrap();
+        input::count_projection_orbitals(&proj, &positions)
+    };
+
+    // Complete shells, on the one W atom and on both Se atoms.
+    assert_eq!(count("W:s"), 1);
+    assert_eq!(count("W:l=1"), 3);
+    assert_eq!(count("W:d"), 5);
+    assert_eq!(count("W:l=3"), 7);
+    assert_eq!(count("Se:p"), 6);
+
+    // Hybrids.
+    assert_eq!(count("W:sp"), 2);
+    assert_eq!(count("W:l=-2"), 3);
+    assert_eq!(count("W:sp3"), 4);
+    assert_eq!(count("W:sp3d"), 5);
+    assert_eq!(count("W:l=-5"), 6);
+
+    // Explicit orbitals, by `mr` or by name.
+    assert_eq!(count("W:l=2,mr=1,4"), 2);
+    assert_eq!(count("W:eg"), 2);
+    assert_eq!(count("W:t2g"), 3);
+    assert_eq!(count("Se:pz"), 2);
+    assert_eq!(count("W:sp3-1"), 1);
+
+    // Mixtures.
+    assert_eq!(count("W:l=0;l=2,mr=1,4;sp2"), 1 + 2 + 3);
+    assert_eq!(count("Se:pz,l=2,mr=2,3,5,s"), 2 * (1 + 3 + 1));
+    assert_eq!(count("c=0,0,0:dxy;sp3d2"), 1 + 6);
+}
+
+#[test]
+fn parse_orbitals() {
+    let cases = vec![
+        ("sp2", AngularMomentum::Sp2),
+        ("l=-4", AngularMomentum::Sp3d),
+        ("dx2-y2", AngularMomentum::Orbitals { l: 2, mr: vec![4] }),
+        ("l=2, mr=1,4", AngularMomentum::Orbitals { l: 2, mr: vec![1, 4] }),
+        ("sp3-2", AngularMomentum::Orbitals { l: -3, mr: vec![2] }),
+    ];
+    for (token, expected) in cases {
+        assert_eq!(token.parse::<AngularMomentum>().unwrap(), expected);
+    }
+    for token in &["l=-6", "sp3-", "l=2,mr=", "l=2,mr=x", "dxx"] {
+        assert!(token.parse::<AngularMomentum>().is_err());
+    }
+
+    let proj: Projection = "W:sp3;eg,l=0".parse().unwrap();
+    assert_eq!(proj.value(), "W:l=-3;l=2,mr=1,4;l=0");
+    assert_eq!(proj.value().parse::<Projection>().unwrap(), proj);
+
+    // Each `mr` must be a distinct orbital of its shell.
+    let mut test_input = wse2_input();
+    for line in &["W:l=2,mr=6", "W:l=1,mr=1,1", "W:l=4,mr=1"] {
+        test_input.projections = vec![line.parse().unwrap(), Projection::Random];
+        let errs = input::validate(&test_input).unwrap_err().errs;
+        assert!(errs.iter().any(|e| match *e {
+            input::Error::InvalidOrbitals { index: 0 } => true,
+            _ => false,
+        }));
+    }
+}
+
+#[test]
+fn projections_match_num_wann() {
+    let mut test_input = wse2_input();
+    assert!(input::validate(&test_input).is_ok());
+
+    // W t2g and Se p: 3 + 2 * 3 orbitals, doubled for spinors.
+    test_input.projections = vec!["W:t2g".parse().unwrap(), "Se:p".parse().unwrap()];
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 1);
+    match errs[0] {
+        input::Error::NumWannProjections { num_wann, count } => {
+            assert_eq!((num_wann, count), (22, 18));
+        }
+        _ => panic!("expected NumWannProjections"),
+    }
+    assert_eq!(errs[0].exit_code(), 2);
+
+    test_input.num_wann = 18;
+    assert!(input::validate(&test_input).is_ok());
+
+    // `Random` chooses the remaining Wannier functions.
+    test_input.num_wann = 22;
+    test_input.projections.push(Projection::Random);
+    assert!(input::validate(&test_input).is_ok());
+}
+
+#[test]
+fn display_input() {
+    let mut test_input = wse2_input();
+    assert_eq!(
+        format!("{}", test_input),
+        serialize::make_input_file(&test_input).unwrap()
+    );
+
+    // Display does not validate.
+    test_input.num_bands = 10;
+    assert!(serialize::make_input_file(&test_input).is_err());
+    assert!(format!("{}", test_input).starts_with("num_bands = 10\nnum_wann = 22\n"));
+}
+
+#[test]
+fn sorted_input() {
+    let test_input = wse2_input();
+
+    let mut shuffled = test_input.clone();
+    shuffled.positions.coordinates.reverse();
+    shuffled.projections.reverse();
+    assert!(serialize::make_input_file(&shuffled).unwrap()
+        != serialize::make_input_file(&test_input).unwrap());
+
+    let sorted = shuffled.sorted();
+    assert_eq!(sorted.sorted(), sorted);
+    assert_eq!(sorted, test_input.sorted());
+    assert_eq!(
+        serialize::make_input_file(&sorted).unwrap(),
+        serialize::make_input_file(&test_input.sorted()).unwrap()
+    );
+
+    let species: Vec<&str> = sorted
+        .positions
+        .coordinates
+        .iter()
+        .map(|c| c.species.as_str())
+        .collect();
+    let mut expected = species.clone();
+    expected.sort();
+    assert_eq!(species, expected);
+
+    // Sorting does not change the number of Wannier functions.
+    assert_eq!(input::count_wannier(&sorted), input::count_wannier(&test_input));
+}
+
+#[test]
+fn dis_spheres() {
+    use w90::parse;
+
+    let mut test_input = wse2_input();
+    if let Some(ref mut dis) = test_input.disentanglement {
+        dis.dis_spheres = Some(DisSpheres {
+            dis_spheres_num: 2,
+            dis_spheres_first_wann: Some(3),
+            spheres: vec![
+                DisSphere {
+                    center: [0.0, 0.0, 0.0],
+                    radius: 0.2,
+                },
+                DisSphere {
+                    center: [1.0 / 3.0, 1.0 / 3.0, 0.0],
+                    radius: 0.1,
+                },
+            ],
+        });
+    }
+    let input_text = make_exact(&test_input);
+    assert!(input_text.contains(&format!(
+        "dis_spheres_num = 2\ndis_spheres_first_wann = 3\nbegin dis_spheres\n\
+         0 0 0 0.2\n{} {} 0 0.1\nend dis_spheres\n",
+        1.0 / 3.0,
+        1.0 / 3.0
+    )));
+
+    let win = parse::parse_win(&input_text).unwrap();
+    assert_eq!(win.disentanglement().unwrap(), test_input.disentanglement);
+
+    if let Some(ref mut spheres) = test_input
+        .disentanglement
+        .as_mut()
+        .and_then(|dis| dis.dis_spheres.as_mut())
+    {
+        spheres.dis_spheres_num = 3;
+        spheres.dis_spheres_first_wann = Some(0);
+        spheres.spheres[1].radius = 0.0;
+    }
+    let errs = input::validate(&test_input).unwrap_err().errs;
+    assert_eq!(errs.len(), 3);
+    assert!(errs.iter().all(|e| e.exit_code() == 5));
+}
+
+#[test]
+fn projectability_disentanglement() {
+    use w90::parse;
+
+    let mut test_input = wse2_input();
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(!input_text.contains("dis_froz_proj") && !input_text.contains("dis_proj_"));
+
+    if let Some(ref mut dis) = test_input.disentanglement {
+        dis.dis_froz_proj = Some(true);
+        dis.dis_proj_min = Some(0.01);
+        dis.dis_proj_max = Some(0.95);
+    }
+    assert!(input::validate(&test_input).is_ok());
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    assert!(
+        input_text.contains("dis_froz_proj=.true.\ndis_proj_min = 0.01\ndis_proj_max = 0.95\n")
+    );
+
+    let win = parse::parse_win(&input_text).unwrap();
+    assert_eq!(win.disentanglement().unwrap(), test_input.disentanglement);
+
+    let invalid = vec![
+        (Some(-0.1), None),
+        (None, Some(1.5)),
+        (Some(0.9), Some(0.5)),
+        (Some(0.5), Some(0.5)),
+        (Some(1.0), None),
+    ];
+    for (min, max) in invalid {
+        if let Some(ref mut dis) = test_input.disentanglement {
+            dis.dis_proj_min = min;
+            dis.dis_proj_max = max;
+        }
+        let errs = input::validate(&test_input).unwrap_err().errs;
+        assert_eq!(errs.len(), 1);
+        match errs[0] {
+            input::Error::DisProjRange => assert_eq!(errs[0].exit_code(), 5),
+            _ => panic!("expected DisProjRange"),
+        }
+    }
+}
+
+#[test]
+fn parse_disentanglement() {
+    use w90::parse;
+
+    let test_input = wse2_input();
+    let input_text = serialize::make_input_file(&test_input).unwrap();
+    let win = parse::parse_win(&input_text).unwrap();
+    assert_eq!(win.disentanglement().unwrap(), test_input.disentanglement);
+
+    let text = "num_wann = 22
+DIS_FROZ_MIN = -4.5582
+dis_froz_max : 6.4418d0
+";
+    let dis = parse::parse_win(text).unwrap().disentanglement().unwrap();
+    assert_eq!(
+        dis,
+        Some(Disentanglement {
+            dis_win_min: None,
+            dis_win_max: None,
+            dis_froz_min: Some(-4.5582),
+            dis_froz_max: Some(6.4418),
+            dis_num_iter: input::DEFAULT_DIS_NUM_ITER,
+            dis_mix_ratio: input::DEFAULT_DIS_MIX_RATIO,
+            dis_spheres: None,
+            dis_froz_proj: None,
+            dis_proj_min: None,
+            dis_proj_max: None,
+        })
+    );
+
+    let no_dis = parse::parse_win("num_wann = 22\n").unwrap();
+    assert_eq!(no_dis.disentanglement().unwrap(), None);
+
+    // Every disentanglement keyword is optional.
+    let only_num_iter = parse::parse_win("num_wann = 22\ndis_num_iter = 500\n").unwrap();
+    assert_eq!(
+        only_num_iter.disentanglement().unwrap(),
+        Some(Disentanglement {
+            dis_win_min: None,
+            dis_win_max: None,
+            dis_froz_min: None,
+            dis_froz_max: None,
+            dis_num_iter: 500,
+            dis_mix_ratio: input::DEFAULT_DIS_MIX_RATIO,
+            dis_spheres: None,
+            dis_froz_proj: None,
+            dis_proj_min: None,
+            dis_proj_max: None,
+        })
+    );
+    let no_froz = parse::parse_win("dis_win_max = 8.0\n").unwrap();
+    let dis = no_froz.disentanglement().unwrap().unwrap();
+    assert_eq!((dis.dis_win_max, dis.dis_froz_min, dis.dis_froz_max), (Some(8.0), None, None));
+    let invalid = parse::parse_win("dis_froz_min = -4\ndis_froz_max = x\n").unwrap();
+    assert!(invalid.disentanglement().is_err());
+}
@@ -0,0 +1,140 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const CONFIG: &str = include_str!("golden/wse2.toml");
+const GOLDEN: &str = include_str!("golden/wse2.win");
+
+/// Write `config` to a file named `name` in the temporary directory.
+fn write_config(name: &str, config: &str) -> PathBuf {
+    let path = env::temp_dir().join(name);
+    fs::write(&path, config).unwrap();
+    path
+}
+
+fn w90gen(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_w90gen"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn stdout() {
+    let path = write_config("w90gen_stdout.toml", CONFIG);
+
+    let output = w90gen(&["--stdout", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), GOLDEN);
+    assert!(!path.with_extension("win").exists());
+}
+
+#[test]
+fn check() {
+    let path = write_config("w90gen_check.toml", CONFIG);
+
+    let output = w90gen(&["--check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(!path.with_extension("win").exists());
+}
+
+#[test]
+fn write_file() {
+    // Without an output path, the input file is written next to the configuration.
+    let path = write_config("w90gen_write_file.toml", CONFIG);
+    let win_path = path.with_extension("win");
+    let _ = fs::remove_file(&win_path);
+
+    let output = w90gen(&[path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&win_path).unwrap().trim_end(), GOLDEN.trim_end());
+
+    let out_path = env::temp_dir().join("w90gen_write_file_out.win");
+    let output = w90gen(&[path.to_str().unwrap(), out_path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&out_path).unwrap().trim_end(), GOLDEN.trim_end());
+
+    // Writing into a missing directory is an I/O error.
+    let bad_path = env::temp_dir().join("w90gen_missing_dir").join("out.win");
+    let output = w90gen(&[path.to_str().unwrap(), bad_path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(74));
+}
+
+#[test]
+fn usage_exit_code() {
+    let path = write_config("w90gen_usage.toml", CONFIG);
+    let path = path.to_str().unwrap();
+
+    let invalid: Vec<Vec<&str>> = vec![
+        vec![],
+        vec!["--check"],
+        vec!["--stdout", "--check", path],
+        vec!["--verbose", path],
+        vec!["--stdout", path, "out.win"],
+        vec![path, "out.win", "extra.win"],
+    ];
+    for args in invalid {
+        let output = w90gen(&args);
+        assert_eq!(output.status.code(), Some(64), "args {:?}", args);
+        assert!(stderr(&output).starts_with("usage: w90gen"));
+    }
+}
+
+#[test]
+fn config_exit_codes() {
+    let path = write_config("w90gen_invalid.toml", "num_wann = \"22\"\n");
+    let output = w90gen(&["--check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(stderr(&output).starts_with(&format!("error: {}: ", path.display())));
+
+    let missing = env::temp_dir().join("w90gen_missing.toml");
+    let _ = fs::remove_file(&missing);
+    let output = w90gen(&["--check", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(74));
+}
+
+#[test]
+fn projection_count_exit_code() {
+    let config = CONFIG.replace("num_wann = 22", "num_wann = 20");
+    let path = write_config("w90gen_projection_count.toml", &config);
+
+    let output = w90gen(&["--check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // The error is followed by the configuration line setting `num_wann`.
+    let expected = format!(
+        "error: Projections give 22 Wannier functions, but `num_wann` is 20.\n\
+         \x20 --> {}:2\n   | num_wann = 20\n",
+        path.display()
+    );
+    assert_eq!(stderr(&output), expected);
+}
+
+#[test]
+fn geometry_exit_code() {
+    // The third lattice vector repeats the second.
+    let config = CONFIG.replace(
+        "[0.0, 0.0, 68.6629186029]",
+        "[3.13603975949, 5.43178019799, 0.0]",
+    );
+    let path = write_config("w90gen_geometry.toml", &config);
+
+    let output = w90gen(&["--check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+
+    // The error message names no field, but `SingularCell` concerns `unit_cell_cart`.
+    let number = config.lines().position(|l| l == "[unit_cell_cart]").unwrap() + 1;
+    let expected = format!(
+        "error: Lattice vectors are linearly dependent.\n\
+         \x20 --> {}:{}\n   | [unit_cell_cart]\n",
+        path.display(),
+        number
+    );
+    assert_eq!(stderr(&output), expected);
+}
@@ -0,0 +1,70 @@
+extern crate w90;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use w90::input;
+use w90::serialize;
+
+/// Allocator which tracks the peak number of bytes allocated at once.
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(current, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOC: PeakAlloc = PeakAlloc;
+
+/// Peak bytes allocated while running `f`, above those allocated beforehand.
+fn peak_bytes<F: FnOnce()>(f: F) -> usize {
+    let start = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(start, Ordering::SeqCst);
+    f();
+
+    PEAK.load(Ordering::SeqCst) - start
+}
+
+// Writing the 10^6 k-points of a 100x100x100 grid takes a while in debug builds; run with
+// `cargo test --release --test write_memory -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn write_input_peak_memory() {
+    let config = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/wse2.toml");
+    let test_input = input::from_toml_file(config).unwrap().with_kpoints([100, 100, 100]);
+
+    let start = Instant::now();
+    let streamed = peak_bytes(|| serialize::write_input(&test_input, &mut io::sink()).unwrap());
+    let streamed_time = start.elapsed();
+
+    let start = Instant::now();
+    let full = peak_bytes(|| {
+        serialize::make_input_file(&test_input).unwrap();
+    });
+    let full_time = start.elapsed();
+
+    println!("write_input: peak {} bytes in {:?}", streamed, streamed_time);
+    println!("make_input_file: peak {} bytes in {:?}", full, full_time);
+
+    // The full file text is tens of megabytes; streaming it should need a small fraction.
+    assert!(full > 10_000_000);
+    assert!(streamed * 100 < full);
+}